@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("server decode ack", |b| {
+        use protocol::send_to_client::decode::{Decode, Message};
+        use protocol::send_to_server::encode::Ack;
+
+        let mut decode = Decode::new(0);
+        let sub_name = "test";
+        let ack_encode = Ack::new(9, sub_name).encode();
+
+        b.iter(|| {
+            decode.set_buff(&ack_encode);
+
+            if let Message::Ack(ack) = decode.iter().next().unwrap().unwrap() {
+                assert_eq!(ack.offset, 9);
+                assert_eq!(&ack.sub_name, sub_name.as_bytes());
+            }
+        });
+    });
+
+    c.bench_function("client decode ack", |b| {
+        use protocol::send_to_client::encode::Ack;
+        use protocol::send_to_server::decode::{Decode, Message};
+
+        let mut decode = Decode::new(0);
+        let sub_name = b"test";
+        let ack_encode = Ack::new(9, &sub_name[..]).encode();
+
+        b.iter(|| {
+            decode.set_buff(&ack_encode);
+
+            if let Message::Ack(ack) = decode.iter().next().unwrap().unwrap() {
+                assert_eq!(ack.offset, 9);
+                assert_eq!(&ack.sub_name, &sub_name[..]);
+            }
+        });
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,4 +1,5 @@
 use criterion::{criterion_group, criterion_main, Criterion};
+use protocol::error::ErrorCode;
 use protocol::send_to_client::decode::{Decode, Message};
 use protocol::send_to_server::encode::Err;
 
@@ -6,7 +7,9 @@ fn criterion_benchmark(c: &mut Criterion) {
     c.bench_function("server receiver error", |b| {
         let mut decode = Decode::new(0);
         let content = "decode error";
-        let err_encode = Err::new(content).encode();
+        let err_encode = Err::new(ErrorCode::ProtocolViolation)
+            .with_msg(content)
+            .encode();
 
         b.iter(|| {
             decode.set_buff(&err_encode);
@@ -21,7 +24,9 @@ fn criterion_benchmark(c: &mut Criterion) {
         let mut decode = Decode::new(0);
         const content: [u8; 65535] = [b' '; 65535];
         let content_str = unsafe { std::str::from_utf8_unchecked(&content) };
-        let err_encode = Err::new(content_str).encode();
+        let err_encode = Err::new(ErrorCode::ProtocolViolation)
+            .with_msg(content_str)
+            .encode();
 
         b.iter(|| {
             decode.set_buff(&err_encode);
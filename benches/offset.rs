@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn criterion_benchmark(c: &mut Criterion) {
+    c.bench_function("server decode offset", |b| {
+        use protocol::send_to_client::decode::{Decode, Message};
+        use protocol::send_to_server::encode::Offset;
+
+        let mut decode = Decode::new(0);
+        let sub_name = "test";
+        let offset_encode = Offset::new(9, sub_name).encode();
+
+        b.iter(|| {
+            decode.set_buff(&offset_encode);
+
+            if let Message::Offset(offset) = decode.iter().next().unwrap().unwrap() {
+                assert_eq!(offset.offset, 9);
+                assert_eq!(&offset.sub_name, sub_name.as_bytes());
+            }
+        });
+    });
+
+    c.bench_function("client decode offset", |b| {
+        use protocol::send_to_client::encode::Offset;
+        use protocol::send_to_server::decode::{Decode, Message};
+
+        let mut decode = Decode::new(0);
+        let sub_name = b"test";
+        let offset_encode = Offset::new(9, &sub_name[..]).encode();
+
+        b.iter(|| {
+            decode.set_buff(&offset_encode);
+
+            if let Message::Offset(offset) = decode.iter().next().unwrap().unwrap() {
+                assert_eq!(offset.offset, 9);
+                assert_eq!(&offset.sub_name, &sub_name[..]);
+            }
+        });
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,60 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::io::IoSlice;
+
+// 4MB 的内容, 对比拷贝到新缓冲和只编码帧头
+const PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let payload = vec![7u8; PAYLOAD_SIZE];
+
+    c.bench_function("msg encode 4MB", |b| {
+        use protocol::send_to_client::encode::{Msg, ServerFrame};
+
+        let msg = Msg::new(9, b"test", &payload);
+        let encoded_len = ServerFrame::Msg(Msg::new(9, b"test", &payload)).encoded_len();
+        b.iter(|| {
+            let buff = msg.encode();
+            assert_eq!(buff.len(), encoded_len);
+        });
+    });
+
+    c.bench_function("msg encode_chain 4MB", |b| {
+        use bytes::Buf;
+        use protocol::send_to_client::encode::Msg;
+
+        let msg = Msg::new(9, b"test", &payload);
+        b.iter(|| {
+            let chain = msg.encode_chain();
+            let mut slices = [IoSlice::new(&[]); 2];
+            assert_eq!(chain.bytes_vectored(&mut slices), 2);
+            assert_eq!(slices[1].len(), PAYLOAD_SIZE);
+        });
+    });
+
+    c.bench_function("pub encode 4MB", |b| {
+        use protocol::send_to_server::encode::{ClientFrame, Pub};
+
+        let publish = Pub::new("test", &payload);
+        let encoded_len = ClientFrame::Pub(Pub::new("test", &payload[..])).encoded_len();
+        b.iter(|| {
+            let buff = publish.encode();
+            assert_eq!(buff.len(), encoded_len);
+        });
+    });
+
+    c.bench_function("pub encode_chain 4MB", |b| {
+        use bytes::Buf;
+        use protocol::send_to_server::encode::Pub;
+
+        let publish = Pub::new("test", &payload);
+        b.iter(|| {
+            let chain = publish.encode_chain();
+            let mut slices = [IoSlice::new(&[]); 2];
+            assert_eq!(chain.bytes_vectored(&mut slices), 2);
+            assert_eq!(slices[1].len(), PAYLOAD_SIZE);
+        });
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
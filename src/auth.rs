@@ -0,0 +1,149 @@
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::fmt;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// 服务器每个连接生成的随机数长度
+pub const NONCE_LEN: usize = 16;
+
+// HMAC-SHA256 的长度
+pub const PROOF_LEN: usize = 32;
+
+// 用密钥对随机数计算 HMAC-SHA256, 密钥本身不会发送出去
+pub fn sign(secret: &[u8], nonce: &[u8]) -> [u8; PROOF_LEN] {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(nonce);
+    mac.finalize().into_bytes().into()
+}
+
+// 按常量时间比较, 不泄露匹配的长度
+pub fn verify(secret: &[u8], nonce: &[u8], proof: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(nonce);
+    mac.verify_slice(proof).is_ok()
+}
+
+pub(crate) fn nonce() -> Bytes {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).expect("system random source is unavailable");
+    Bytes::copy_from_slice(&nonce)
+}
+
+// 客户端的凭证, 握手时只发送用户名和证明
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    Token(Vec<u8>),
+    User { user: Vec<u8>, password: Vec<u8> },
+}
+
+impl Credentials {
+    // 令牌认证时用户名为空
+    pub fn user(&self) -> &[u8] {
+        match self {
+            Credentials::Token(_) => &[],
+            Credentials::User { user, password: _ } => user,
+        }
+    }
+
+    pub fn secret(&self) -> &[u8] {
+        match self {
+            Credentials::Token(token) => token,
+            Credentials::User { user: _, password } => password,
+        }
+    }
+
+    pub fn proof(&self, nonce: &[u8]) -> [u8; PROOF_LEN] {
+        sign(self.secret(), nonce)
+    }
+}
+
+// 不打印密钥
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Credentials::Token(_) => f.write_str("Token(..)"),
+            Credentials::User { user, password: _ } => f
+                .debug_struct("User")
+                .field("user", &String::from_utf8_lossy(user))
+                .finish(),
+        }
+    }
+}
+
+// 服务器端的认证方式, 握手时用客户端信息中的用户名和证明校验
+pub trait Authenticator {
+    // 按用户名查找密钥, 令牌认证时用户名为空, 没有时返回 None
+    fn secret(&self, user: &[u8]) -> Option<Vec<u8>>;
+
+    // 默认按照密钥校验 HMAC-SHA256, 可以替换成其他的校验方式
+    fn authenticate(&self, nonce: &[u8], user: &[u8], proof: &[u8]) -> bool {
+        match self.secret(user) {
+            Some(secret) => verify(&secret, nonce, proof),
+            None => false,
+        }
+    }
+}
+
+// 拒绝所有客户端, 服务器没有开启认证时不会用到
+#[derive(Debug, Default, Clone, Copy)]
+pub struct NoAuth;
+
+impl Authenticator for NoAuth {
+    fn secret(&self, _user: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+// 固定的令牌和用户名密码
+#[derive(Default, Clone)]
+pub struct StaticAuth {
+    token: Option<Vec<u8>>,
+    users: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl StaticAuth {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.as_bytes().to_vec());
+        self
+    }
+
+    pub fn with_user(mut self, user: &str, password: &str) -> Self {
+        self.users
+            .insert(user.as_bytes().to_vec(), password.as_bytes().to_vec());
+        self
+    }
+}
+
+impl Authenticator for StaticAuth {
+    fn secret(&self, user: &[u8]) -> Option<Vec<u8>> {
+        if user.is_empty() {
+            self.token.clone()
+        } else {
+            self.users.get(user).cloned()
+        }
+    }
+}
+
+// 不打印密钥
+impl fmt::Debug for StaticAuth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StaticAuth")
+            .field("token", &self.token.is_some())
+            .field(
+                "users",
+                &self
+                    .users
+                    .keys()
+                    .map(|user| String::from_utf8_lossy(user))
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
@@ -0,0 +1,192 @@
+use crate::send_to_client::decode::Info as ClientInfo;
+use crate::send_to_server::decode::Info as ServerInfo;
+use crate::state::Support;
+use std::fmt;
+use std::iter::FromIterator;
+use std::ops::{BitAnd, BitOr};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum NegotiateError {
+    #[error("client demands {0} which the server does not offer")]
+    Unsupported(Capabilities),
+}
+
+// 服务位掩码的集合, 不认识的位也会原样保留
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Capabilities(u16);
+
+impl Capabilities {
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    pub const fn from_bits(bits: u16) -> Self {
+        Self(bits)
+    }
+
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    // 本版本不认识的位
+    pub fn unknown_bits(self) -> u16 {
+        Support::ALL
+            .iter()
+            .fold(self.0, |bits, support| bits & !support.bit())
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(self, support: Support) -> bool {
+        self.0 & support
+    }
+
+    pub fn is_subset(self, other: Capabilities) -> bool {
+        self.0 & other.0 == self.0
+    }
+
+    pub fn insert(&mut self, support: Support) {
+        self.0 |= support;
+    }
+
+    pub fn remove(&mut self, support: Support) {
+        self.0 &= !support.bit();
+    }
+
+    pub fn iter(self) -> Iter {
+        Iter {
+            bits: self.0,
+            index: 0,
+        }
+    }
+
+    // offered 为服务器提供的服务, demanded 为客户端要求的服务
+    // 客户端要求的已知服务必须都被服务器提供, 结果为双方的交集
+    pub fn negotiate(
+        offered: Capabilities,
+        demanded: Capabilities,
+    ) -> Result<Capabilities, NegotiateError> {
+        let missing = demanded
+            .iter()
+            .filter(|support| !offered.contains(*support))
+            .collect::<Capabilities>();
+
+        if missing.is_empty() {
+            Ok(offered & demanded)
+        } else {
+            Err(NegotiateError::Unsupported(missing))
+        }
+    }
+}
+
+// 根据双方的握手信息得出最终生效的服务
+pub fn negotiate(server: &ServerInfo, client: &ClientInfo) -> Result<Capabilities, NegotiateError> {
+    Capabilities::negotiate(server.capabilities(), client.capabilities())
+}
+
+impl From<u16> for Capabilities {
+    fn from(bits: u16) -> Self {
+        Self(bits)
+    }
+}
+
+impl From<Support> for Capabilities {
+    fn from(support: Support) -> Self {
+        Self(support.bit())
+    }
+}
+
+impl From<Capabilities> for u16 {
+    fn from(capabilities: Capabilities) -> Self {
+        capabilities.0
+    }
+}
+
+impl FromIterator<Support> for Capabilities {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = Support>,
+    {
+        let mut capabilities = Capabilities::empty();
+        iter.into_iter()
+            .for_each(|support| capabilities.insert(support));
+        capabilities
+    }
+}
+
+impl BitAnd for Capabilities {
+    type Output = Capabilities;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        Self(self.0 & rhs.0)
+    }
+}
+
+impl BitOr for Capabilities {
+    type Output = Capabilities;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl IntoIterator for Capabilities {
+    type Item = Support;
+    type IntoIter = Iter;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+// 形如 push|pull, 不认识的位以十六进制输出
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return f.write_str("none");
+        }
+
+        let mut first = true;
+        for support in self.iter() {
+            if !first {
+                f.write_str("|")?;
+            }
+            f.write_str(support.name())?;
+            first = false;
+        }
+
+        let unknown = self.unknown_bits();
+        if unknown != 0 {
+            if !first {
+                f.write_str("|")?;
+            }
+            write!(f, "{:#x}", unknown)?;
+        }
+
+        Ok(())
+    }
+}
+
+// 只遍历已知的服务
+#[derive(Debug, Clone)]
+pub struct Iter {
+    bits: u16,
+    index: usize,
+}
+
+impl Iterator for Iter {
+    type Item = Support;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(support) = Support::ALL.get(self.index) {
+            self.index += 1;
+            if self.bits & *support {
+                return Some(*support);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,15 @@
+use std::time::Instant;
+
+// 时间来源, 测试时可以替换成假的时钟
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
@@ -0,0 +1,147 @@
+use crate::error::{Error as DecodeError, ErrorPolicy};
+use crate::send_to_client::decode::{Decode as ServerDecode, Message as ServerMessage};
+use crate::send_to_client::encode::ServerFrame;
+use crate::send_to_server::decode::{Decode as ClientDecode, Message as ClientMessage};
+use crate::send_to_server::encode::ClientFrame;
+use crate::version::Version;
+use bytes::BytesMut;
+use std::io;
+use thiserror::Error;
+use tokio_util::codec::{Decoder, Encoder};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    #[error("stream ended in the middle of a frame")]
+    Truncated,
+}
+
+// 客户端使用, 解析服务器发来的帧, 发送 ClientFrame
+#[derive(Debug)]
+pub struct ClientCodec {
+    decode: ClientDecode,
+}
+
+impl Default for ClientCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClientCodec {
+    pub fn new() -> Self {
+        Self {
+            decode: ClientDecode::new(0),
+        }
+    }
+
+    // 握手完成后切换版本, 同时影响编码和解码
+    pub fn set_version(&mut self, version: Version) {
+        self.decode.set_version(version);
+    }
+
+    // 对应 Decode::set_max_message_length
+    pub fn set_max_message_length(&mut self, max_message_length: usize) {
+        self.decode.set_max_message_length(max_message_length);
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.decode.set_error_policy(policy);
+    }
+}
+
+impl Decoder for ClientCodec {
+    type Item = ClientMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // 把读到的字节全部交给内部的 Decode, 连续时不会拷贝
+        self.decode.get_mut_buffer().unsplit(src.split());
+        Ok(self.decode.iter().next().transpose()?)
+    }
+
+    // 字节流结束时还有没解析完的帧就报错, 不能静默丢掉
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None if self.decode.is_partial() => Err(Error::Truncated),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> Encoder<ClientFrame<'a>> for ClientCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: ClientFrame<'a>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        item.encode_into_with(self.decode.version(), dst);
+        Ok(())
+    }
+}
+
+// 服务器使用, 解析客户端发来的帧, 发送 ServerFrame
+#[derive(Debug)]
+pub struct ServerCodec {
+    decode: ServerDecode,
+}
+
+impl Default for ServerCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ServerCodec {
+    pub fn new() -> Self {
+        Self {
+            decode: ServerDecode::new(0),
+        }
+    }
+
+    // 握手完成后切换版本, 同时影响编码和解码
+    pub fn set_version(&mut self, version: Version) {
+        self.decode.set_version(version);
+    }
+
+    // 对应 Decode::set_max_message_length
+    pub fn set_max_message_length(&mut self, max_message_length: usize) {
+        self.decode.set_max_message_length(max_message_length);
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.decode.set_error_policy(policy);
+    }
+}
+
+impl Decoder for ServerCodec {
+    type Item = ServerMessage;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode.get_mut_buffer().unsplit(src.split());
+        Ok(self.decode.iter().next().transpose()?)
+    }
+
+    // 字节流结束时还有没解析完的帧就报错, 不能静默丢掉
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None if self.decode.is_partial() => Err(Error::Truncated),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> Encoder<ServerFrame<'a>> for ServerCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: ServerFrame<'a>, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        item.encode_into_with(self.decode.version(), dst);
+        Ok(())
+    }
+}
@@ -1,6 +1,85 @@
+use crate::version::Version;
+use bytes::{BufMut, BytesMut};
 use std::mem::size_of;
 
 pub(crate) const U8_SIZE: usize = size_of::<u8>();
 pub(crate) const U16_SIZE: usize = size_of::<u16>();
 pub(crate) const U32_SIZE: usize = size_of::<u32>();
 pub(crate) const U64_SIZE: usize = size_of::<u64>();
+
+// 第二版的帧头, |type|flags|u32 length|
+pub(crate) const ENVELOPE_SIZE: usize = U8_SIZE + U8_SIZE + U32_SIZE;
+
+// 要发送的帧, 由1字节类型和帧体组成
+pub(crate) trait Frame {
+    // 帧类型
+    fn kind(&self) -> u8;
+
+    // 帧体长度, 不包括类型
+    fn body_len(&self) -> usize;
+
+    // 只写入帧体
+    fn encode_body(&self, buff: &mut BytesMut);
+
+    // 帧体随版本变化的帧重写这两个方法, 默认两个版本的帧体相同
+    fn body_len_with(&self, _version: Version) -> usize {
+        self.body_len()
+    }
+
+    fn encode_body_with(&self, _version: Version, buff: &mut BytesMut) {
+        self.encode_body(buff);
+    }
+
+    fn encoded_len(&self) -> usize {
+        self.encoded_len_with(Version::V1)
+    }
+
+    fn encode_into(&self, buff: &mut BytesMut) {
+        self.encode_into_with(Version::V1, buff);
+    }
+
+    fn encoded_len_with(&self, version: Version) -> usize {
+        match version {
+            Version::V1 => U8_SIZE + self.body_len_with(version),
+            Version::V2 => ENVELOPE_SIZE + self.body_len_with(version),
+        }
+    }
+
+    fn encode_into_with(&self, version: Version, buff: &mut BytesMut) {
+        buff.reserve(self.encoded_len_with(version));
+        self.encode_header(version, buff);
+        self.encode_body_with(version, buff);
+    }
+
+    // 只写入帧体前面的类型, 第二版还有标志位和帧体长度
+    fn encode_header(&self, version: Version, buff: &mut BytesMut) {
+        buff.put_u8(self.kind());
+        if version == Version::V2 {
+            // 还没有定义任何标志位, 预留给压缩和校验
+            buff.put_u8(0);
+            buff.put_u32(self.body_len_with(version) as u32);
+        }
+    }
+}
+
+// 截断到最多 max 字节, 不会拆开一个utf8字符
+pub(crate) fn truncate_str(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+// 编码到新的缓冲
+pub(crate) fn encode<F>(frame: &F) -> BytesMut
+where
+    F: Frame + ?Sized,
+{
+    let mut buff = BytesMut::with_capacity(frame.encoded_len());
+    frame.encode_into(&mut buff);
+    buff
+}
@@ -0,0 +1,151 @@
+use crate::version::INCOMPATIBLE_VERSION;
+use thiserror::Error;
+
+// 解析失败的原因
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ErrorKind {
+    // 不认识的帧类型
+    #[error("unknown frame type {0}")]
+    UnknownFrameType(u8),
+
+    // 认识的帧类型, 但不应该由对方发送
+    #[error("unexpected frame type {0}")]
+    UnexpectedFrame(u8),
+
+    #[error("payload of {got} bytes exceeds the limit of {limit} bytes")]
+    PayloadTooLarge { limit: usize, got: usize },
+
+    // 订阅名称为空或者不是utf8
+    #[error("invalid subject")]
+    InvalidSubject,
+
+    // 消息头的格式不对, 或者名称和值不是utf8
+    #[error("invalid headers")]
+    InvalidHeaders,
+
+    // 拉取结束帧中不认识的状态
+    #[error("unknown fetch status {0}")]
+    UnknownFetchStatus(u8),
+
+    // 第二版帧头中不支持的标志位
+    #[error("unsupported frame flags {0:#04x}")]
+    UnsupportedFlags(u8),
+
+    // 第二版帧头中声明的长度比帧体实际的长度短
+    #[error("frame body does not fit in the declared {declared} bytes")]
+    LengthMismatch { declared: usize },
+}
+
+// 两个方向的解析共用的错误, 带上出错的帧在字节流中的位置
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[error("{kind} in frame at byte {offset}")]
+pub struct Error {
+    kind: ErrorKind,
+    offset: u64,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind, offset: u64) -> Self {
+        Self { kind, offset }
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    // 出错的帧的类型字节在整个字节流中的位置
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+}
+
+// 解析出错之后的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ErrorPolicy {
+    // 出错后不再解析, 之后每次 iter 都先返回同一个错误
+    #[default]
+    Poison,
+
+    // 丢弃出错的帧继续解析, 第二版按照帧头中的长度跳过
+    // 第一版只能跳过知道在哪里结束的帧, 比如超长的消息体; 不认识的类型等错误仍然和 Poison 一样
+    Resync,
+}
+
+// 错误帧中的错误码, 客户端按错误码处理, 不需要比较文字
+// 不认识的错误码保留原来的值, 新版本增加错误码不影响旧的一端
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    ProtocolViolation,
+    UnsupportedCapabilities,
+    IncompatibleVersion,
+    PayloadTooLarge,
+    AuthFailure,
+    UnknownSubject,
+    ModeNotSupported,
+    NotInPullMode,
+    AlreadySubscribed,
+    NotSubscribed,
+
+    // 客户端处理太慢, 暂存的消息超出上限
+    SlowConsumer,
+    Unknown(u16),
+}
+
+impl ErrorCode {
+    pub const fn as_u16(self) -> u16 {
+        match self {
+            ErrorCode::ProtocolViolation => 1,
+            ErrorCode::UnsupportedCapabilities => 2,
+            ErrorCode::IncompatibleVersion => 3,
+            ErrorCode::PayloadTooLarge => 4,
+            ErrorCode::AuthFailure => 5,
+            ErrorCode::UnknownSubject => 6,
+            ErrorCode::ModeNotSupported => 7,
+            ErrorCode::NotInPullMode => 8,
+            ErrorCode::AlreadySubscribed => 9,
+            ErrorCode::NotSubscribed => 10,
+            ErrorCode::SlowConsumer => 11,
+            ErrorCode::Unknown(code) => code,
+        }
+    }
+
+    pub const fn from_u16(code: u16) -> Self {
+        match code {
+            1 => ErrorCode::ProtocolViolation,
+            2 => ErrorCode::UnsupportedCapabilities,
+            3 => ErrorCode::IncompatibleVersion,
+            4 => ErrorCode::PayloadTooLarge,
+            5 => ErrorCode::AuthFailure,
+            6 => ErrorCode::UnknownSubject,
+            7 => ErrorCode::ModeNotSupported,
+            8 => ErrorCode::NotInPullMode,
+            9 => ErrorCode::AlreadySubscribed,
+            10 => ErrorCode::NotSubscribed,
+            11 => ErrorCode::SlowConsumer,
+            code => ErrorCode::Unknown(code),
+        }
+    }
+
+    // 没有附带文字时使用的说明
+    pub const fn description(self) -> &'static str {
+        match self {
+            ErrorCode::ProtocolViolation => "protocol violation",
+            ErrorCode::UnsupportedCapabilities => "unsupported capabilities",
+            ErrorCode::IncompatibleVersion => INCOMPATIBLE_VERSION,
+            ErrorCode::PayloadTooLarge => "payload too large",
+            ErrorCode::AuthFailure => "authentication failed",
+            ErrorCode::UnknownSubject => "unknown subject",
+            ErrorCode::ModeNotSupported => "mode not supported",
+            ErrorCode::NotInPullMode => "not in pull mode",
+            ErrorCode::AlreadySubscribed => "already subscribed",
+            ErrorCode::NotSubscribed => "not subscribed",
+            ErrorCode::SlowConsumer => "slow consumer",
+            ErrorCode::Unknown(_) => "unknown error",
+        }
+    }
+}
+
+// 订阅名称不能为空, 必须是utf8
+pub(crate) fn is_valid_subject(name: &[u8]) -> bool {
+    !name.is_empty() && std::str::from_utf8(name).is_ok()
+}
@@ -0,0 +1,552 @@
+use crate::common::{ENVELOPE_SIZE, U16_SIZE, U32_SIZE, U64_SIZE, U8_SIZE};
+use crate::error::{is_valid_subject, ErrorCode, ErrorKind};
+use crate::state::{
+    FetchStatus, Support, STATE_ACK, STATE_CLIENT_INFO, STATE_CREDIT, STATE_ERR, STATE_FETCH,
+    STATE_FETCH_DONE, STATE_HMSG, STATE_HPUB, STATE_MSG, STATE_NACK, STATE_OFFSET, STATE_OK,
+    STATE_PING, STATE_PONG, STATE_PUB, STATE_REPLY, STATE_REQUEST, STATE_SERVER_INFO, STATE_SUB,
+    STATE_SUB_ACK, STATE_TURN_PULL, STATE_TURN_PUSH, STATE_UNSUB, SUB_QUEUE, SUB_REPLY,
+};
+use crate::version::Version;
+use std::convert::TryInto;
+use std::iter::FusedIterator;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum ParseError {
+    // 需要更多的字节
+    #[error("incomplete frame")]
+    Incomplete,
+
+    #[error(transparent)]
+    Invalid(#[from] ErrorKind),
+}
+
+// 直接借用输入的帧, 两个方向的帧都可以解析
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameRef<'a> {
+    ServerInfo {
+        version: u8,
+        support: u16,
+        max_message_length: u32,
+        nonce: &'a [u8],
+    },
+    ClientInfo {
+        version: u8,
+        support: u16,
+        max_message_size: u8,
+        user: &'a [u8],
+        proof: &'a [u8],
+    },
+    Ping,
+    Pong,
+    TurnPush,
+    TurnPull,
+    Ok,
+    Err {
+        code: ErrorCode,
+        msg: &'a [u8],
+    },
+    Msg {
+        offset: u64,
+        sid: u32,
+        sub_name: &'a [u8],
+        payload: &'a [u8],
+    },
+    Offset {
+        offset: u64,
+        sub_name: &'a [u8],
+    },
+    Ack {
+        offset: u64,
+        sub_name: &'a [u8],
+    },
+    Sub {
+        reply: bool,
+        name: &'a [u8],
+        queue: Option<&'a [u8]>,
+    },
+    Pub {
+        name: &'a [u8],
+        msg: &'a [u8],
+    },
+    UnSub {
+        reply: bool,
+        names: NameList<'a>,
+    },
+    Request {
+        id: u64,
+        sub_name: &'a [u8],
+        reply_to: &'a [u8],
+        payload: &'a [u8],
+    },
+    Reply {
+        id: u64,
+        sub_name: &'a [u8],
+        payload: &'a [u8],
+    },
+    HPub {
+        name: &'a [u8],
+        headers: HeaderList<'a>,
+        msg: &'a [u8],
+    },
+    HMsg {
+        offset: u64,
+        sid: u32,
+        sub_name: &'a [u8],
+        headers: HeaderList<'a>,
+        payload: &'a [u8],
+    },
+    SubAck {
+        sid: u32,
+        sub_name: &'a [u8],
+    },
+    Fetch {
+        batch: u32,
+        max_bytes: u32,
+        expires: Duration,
+        sub_name: &'a [u8],
+    },
+    FetchDone {
+        status: FetchStatus,
+        count: u32,
+        sub_name: &'a [u8],
+    },
+    Credit {
+        credit: u32,
+    },
+    Nack {
+        offset: u64,
+        delay: Duration,
+        sub_name: &'a [u8],
+        reason: &'a [u8],
+    },
+
+    // 第二版中不认识的帧类型
+    Unknown {
+        kind: u8,
+        body: &'a [u8],
+    },
+}
+
+// 取消订阅的名称列表, 迭代时才拆分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NameList<'a> {
+    total: u16,
+    buff: &'a [u8],
+}
+
+impl<'a> NameList<'a> {
+    pub fn len(&self) -> usize {
+        self.total as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn iter(&self) -> Names<'a> {
+        Names {
+            reader: Reader::new(self.buff),
+        }
+    }
+}
+
+impl<'a> IntoIterator for NameList<'a> {
+    type Item = &'a [u8];
+    type IntoIter = Names<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Names<'a> {
+    reader: Reader<'a>,
+}
+
+impl<'a> Iterator for Names<'a> {
+    type Item = &'a [u8];
+
+    // 名称列表在解析时已经检查过
+    fn next(&mut self) -> Option<Self::Item> {
+        let length = self.reader.u8().ok()? as usize;
+        self.reader.bytes(length).ok()
+    }
+}
+
+impl<'a> FusedIterator for Names<'a> {}
+
+// 消息头列表, 迭代时才拆分
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeaderList<'a> {
+    total: u16,
+    buff: &'a [u8],
+}
+
+impl<'a> HeaderList<'a> {
+    pub fn len(&self) -> usize {
+        self.total as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.total == 0
+    }
+
+    pub fn iter(&self) -> HeaderIter<'a> {
+        HeaderIter {
+            reader: Reader::new(self.buff),
+        }
+    }
+
+    // 同名的第一个值
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.iter()
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
+}
+
+impl<'a> IntoIterator for HeaderList<'a> {
+    type Item = (&'a str, &'a str);
+    type IntoIter = HeaderIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HeaderIter<'a> {
+    reader: Reader<'a>,
+}
+
+impl<'a> Iterator for HeaderIter<'a> {
+    type Item = (&'a str, &'a str);
+
+    // 消息头在解析时已经检查过
+    fn next(&mut self) -> Option<Self::Item> {
+        let length = self.reader.u8().ok()? as usize;
+        let name = self.reader.bytes(length).ok()?;
+        let length = self.reader.u16().ok()? as usize;
+        let value = self.reader.bytes(length).ok()?;
+        Some((
+            std::str::from_utf8(name).ok()?,
+            std::str::from_utf8(value).ok()?,
+        ))
+    }
+}
+
+impl<'a> FusedIterator for HeaderIter<'a> {}
+
+// 按照第一版格式解析一个帧, 返回帧和消耗的字节数
+pub fn parse_frame(buff: &[u8]) -> Result<(FrameRef<'_>, usize), ParseError> {
+    parse_frame_with(Version::V1, buff)
+}
+
+pub fn parse_frame_with(
+    version: Version,
+    buff: &[u8],
+) -> Result<(FrameRef<'_>, usize), ParseError> {
+    match version {
+        Version::V1 => {
+            let mut reader = Reader::new(buff);
+            let kind = reader.u8()?;
+            let frame = parse_body(version, kind, &mut reader)?;
+            Ok((frame, reader.pos))
+        }
+        Version::V2 => {
+            let mut reader = Reader::new(buff);
+            let kind = reader.u8()?;
+            let flags = reader.u8()?;
+            let length = reader.u32()? as usize;
+            let body = reader.bytes(length)?;
+
+            if flags != 0 {
+                return Err(ErrorKind::UnsupportedFlags(flags).into());
+            }
+
+            // 和 Decode 一样按照第一版的语法读帧体, 只能读到声明的长度为止
+            let mut body_reader = Reader::new(body);
+            let frame = match parse_body(version, kind, &mut body_reader) {
+                Ok(frame) => frame,
+                Err(ParseError::Invalid(ErrorKind::UnknownFrameType(_))) => {
+                    FrameRef::Unknown { kind, body }
+                }
+                // 整个帧体都已经在 body 中, 不够读说明声明的长度太短
+                Err(ParseError::Incomplete) => {
+                    return Err(ErrorKind::LengthMismatch { declared: length }.into());
+                }
+                Err(e) => return Err(e),
+            };
+            Ok((frame, ENVELOPE_SIZE + length))
+        }
+    }
+}
+
+// 帧体语法, 与 Decode 的状态机相同, 订阅号, 取消订阅的回复和错误码只在第二版中才有
+fn parse_body<'a>(
+    version: Version,
+    kind: u8,
+    reader: &mut Reader<'a>,
+) -> Result<FrameRef<'a>, ParseError> {
+    let frame = match kind {
+        STATE_SERVER_INFO => {
+            let version = reader.u8()?;
+            let support = reader.u16()?;
+            let max_message_length = reader.u32()?;
+            // 开启认证时才有随机数
+            let nonce = if support & Support::Auth {
+                reader.short()?
+            } else {
+                &[]
+            };
+            FrameRef::ServerInfo {
+                version,
+                support,
+                max_message_length,
+                nonce,
+            }
+        }
+        STATE_CLIENT_INFO => {
+            let version = reader.u8()?;
+            let support = reader.u16()?;
+            let max_message_size = reader.u8()?;
+            let (user, proof) = if support & Support::Auth {
+                (reader.short()?, reader.short()?)
+            } else {
+                (&[][..], &[][..])
+            };
+            FrameRef::ClientInfo {
+                version,
+                support,
+                max_message_size,
+                user,
+                proof,
+            }
+        }
+        STATE_PING => FrameRef::Ping,
+        STATE_PONG => FrameRef::Pong,
+        STATE_TURN_PUSH => FrameRef::TurnPush,
+        STATE_TURN_PULL => FrameRef::TurnPull,
+        STATE_OK => FrameRef::Ok,
+        STATE_ERR => {
+            // 第一版没有错误码
+            let code = match version {
+                Version::V1 => ErrorCode::Unknown(0),
+                Version::V2 => ErrorCode::from_u16(reader.u16()?),
+            };
+            let length = reader.u16()? as usize;
+            FrameRef::Err {
+                code,
+                msg: reader.bytes(length)?,
+            }
+        }
+        STATE_MSG => FrameRef::Msg {
+            offset: reader.u64()?,
+            sid: reader.sid(version)?,
+            sub_name: reader.subject()?,
+            payload: reader.payload()?,
+        },
+        STATE_OFFSET => FrameRef::Offset {
+            offset: reader.u64()?,
+            sub_name: reader.subject()?,
+        },
+        STATE_ACK => FrameRef::Ack {
+            offset: reader.u64()?,
+            sub_name: reader.subject()?,
+        },
+        STATE_SUB => {
+            let flags = reader.u8()?;
+            let name = reader.subject()?;
+            let queue = if flags & SUB_QUEUE != 0 {
+                Some(reader.subject()?)
+            } else {
+                None
+            };
+            FrameRef::Sub {
+                reply: flags & SUB_REPLY != 0,
+                name,
+                queue,
+            }
+        }
+        STATE_PUB => FrameRef::Pub {
+            name: reader.subject()?,
+            msg: reader.payload()?,
+        },
+        STATE_UNSUB => {
+            let reply = version == Version::V2 && reader.u8()? != 0;
+            let total = reader.u16()?;
+            let start = reader.pos;
+            for _ in 0..total {
+                reader.subject()?;
+            }
+            FrameRef::UnSub {
+                reply,
+                names: NameList {
+                    total,
+                    buff: &reader.buff[start..reader.pos],
+                },
+            }
+        }
+        STATE_REQUEST => FrameRef::Request {
+            id: reader.u64()?,
+            sub_name: reader.subject()?,
+            reply_to: reader.subject()?,
+            payload: reader.payload()?,
+        },
+        STATE_REPLY => FrameRef::Reply {
+            id: reader.u64()?,
+            sub_name: reader.subject()?,
+            payload: reader.payload()?,
+        },
+        STATE_HPUB => FrameRef::HPub {
+            name: reader.subject()?,
+            headers: reader.headers()?,
+            msg: reader.payload()?,
+        },
+        STATE_HMSG => FrameRef::HMsg {
+            offset: reader.u64()?,
+            sid: reader.sid(version)?,
+            sub_name: reader.subject()?,
+            headers: reader.headers()?,
+            payload: reader.payload()?,
+        },
+        STATE_SUB_ACK => FrameRef::SubAck {
+            sid: reader.u32()?,
+            sub_name: reader.subject()?,
+        },
+        STATE_FETCH => FrameRef::Fetch {
+            batch: reader.u32()?,
+            max_bytes: reader.u32()?,
+            expires: Duration::from_millis(reader.u32()? as u64),
+            sub_name: reader.subject()?,
+        },
+        STATE_FETCH_DONE => {
+            let byte = reader.u8()?;
+            let status = FetchStatus::from_u8(byte).ok_or(ErrorKind::UnknownFetchStatus(byte))?;
+            FrameRef::FetchDone {
+                status,
+                count: reader.u32()?,
+                sub_name: reader.subject()?,
+            }
+        }
+        STATE_CREDIT => FrameRef::Credit {
+            credit: reader.u32()?,
+        },
+        STATE_NACK => {
+            let offset = reader.u64()?;
+            let delay = Duration::from_millis(reader.u32()? as u64);
+            let sub_name = reader.subject()?;
+            let length = reader.u16()? as usize;
+            FrameRef::Nack {
+                offset,
+                delay,
+                sub_name,
+                reason: reader.bytes(length)?,
+            }
+        }
+        _ => return Err(ErrorKind::UnknownFrameType(kind).into()),
+    };
+    Ok(frame)
+}
+
+#[derive(Debug, Clone)]
+struct Reader<'a> {
+    buff: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buff: &'a [u8]) -> Self {
+        Self { buff, pos: 0 }
+    }
+
+    fn bytes(&mut self, length: usize) -> Result<&'a [u8], ParseError> {
+        let end = self.pos.checked_add(length).ok_or(ParseError::Incomplete)?;
+        let bytes = self.buff.get(self.pos..end).ok_or(ParseError::Incomplete)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ParseError> {
+        Ok(self.bytes(U8_SIZE)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, ParseError> {
+        let bytes = self.bytes(U16_SIZE)?;
+        Ok(u16::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, ParseError> {
+        let bytes = self.bytes(U32_SIZE)?;
+        Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ParseError> {
+        let bytes = self.bytes(U64_SIZE)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    // 订阅号只在第二版中发送, 第一版为0
+    fn sid(&mut self, version: Version) -> Result<u32, ParseError> {
+        match version {
+            Version::V1 => Ok(0),
+            Version::V2 => self.u32(),
+        }
+    }
+
+    // 1字节长度的订阅名称
+    fn subject(&mut self) -> Result<&'a [u8], ParseError> {
+        let length = self.u8()? as usize;
+        let name = self.bytes(length)?;
+        if is_valid_subject(name) {
+            Ok(name)
+        } else {
+            Err(ErrorKind::InvalidSubject.into())
+        }
+    }
+
+    // 1字节长度, 不检查格式
+    fn short(&mut self) -> Result<&'a [u8], ParseError> {
+        let length = self.u8()? as usize;
+        self.bytes(length)
+    }
+
+    // 4字节长度的消息内容
+    fn payload(&mut self) -> Result<&'a [u8], ParseError> {
+        let length = self.u32()? as usize;
+        self.bytes(length)
+    }
+
+    // 4字节长度的消息头, 整个读完之后再检查格式
+    fn headers(&mut self) -> Result<HeaderList<'a>, ParseError> {
+        let block = self.payload()?;
+        let mut reader = Reader::new(block);
+        let invalid = |_| ParseError::Invalid(ErrorKind::InvalidHeaders);
+
+        let total = reader.u16().map_err(invalid)?;
+        let start = reader.pos;
+        for _ in 0..total {
+            let length = reader.u8().map_err(invalid)? as usize;
+            let name = reader.bytes(length).map_err(invalid)?;
+            let length = reader.u16().map_err(invalid)? as usize;
+            let value = reader.bytes(length).map_err(invalid)?;
+            if name.is_empty()
+                || std::str::from_utf8(name).is_err()
+                || std::str::from_utf8(value).is_err()
+            {
+                return Err(ErrorKind::InvalidHeaders.into());
+            }
+        }
+        if reader.pos != block.len() {
+            return Err(ErrorKind::InvalidHeaders.into());
+        }
+
+        Ok(HeaderList {
+            total,
+            buff: &block[start..],
+        })
+    }
+}
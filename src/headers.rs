@@ -0,0 +1,148 @@
+use crate::common::{U16_SIZE, U8_SIZE};
+use crate::error::ErrorKind;
+use bytes::{Buf, BufMut, BytesMut};
+
+// 消息头, 同一个名称可以有多个值, 按照加入的顺序保存
+// 编码格式为 |u16 数量|{|u8 名称长度|名称|u16 值长度|值|}...|
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Headers {
+    entries: Vec<(String, String)>,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    // 条目数量, 同名的多个值分别计算
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // 替换掉同名的所有值
+    pub fn insert<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        self.remove(&key);
+        self.append(key, value);
+    }
+
+    // 保留同名的值, 追加一个新的值
+    pub fn append<K, V>(&mut self, key: K, value: V)
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        let key = key.into();
+        let value = value.into();
+        debug_assert!(!key.is_empty() && key.len() <= u8::MAX as usize);
+        debug_assert!(value.len() <= u16::MAX as usize);
+        self.entries.push((key, value));
+    }
+
+    // 同名的第一个值
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(name, _)| name == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.iter().any(|(name, _)| name == key)
+    }
+
+    // 删除同名的所有值, 返回是否删除了
+    pub fn remove(&mut self, key: &str) -> bool {
+        let len = self.entries.len();
+        self.entries.retain(|(name, _)| name != key);
+        self.entries.len() != len
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    // 编码后的长度, 不包括帧中的4字节长度
+    pub fn encoded_len(&self) -> usize {
+        self.entries.iter().fold(U16_SIZE, |len, (name, value)| {
+            len + U8_SIZE + name.len() + U16_SIZE + value.len()
+        })
+    }
+
+    pub fn encode_into(&self, buff: &mut BytesMut) {
+        buff.put_u16(self.entries.len() as u16);
+        self.entries.iter().for_each(|(name, value)| {
+            buff.put_u8(name.len() as u8);
+            buff.extend_from_slice(name.as_bytes());
+            buff.put_u16(value.len() as u16);
+            buff.extend_from_slice(value.as_bytes());
+        });
+    }
+
+    // 解析完整的消息头, 多出来的字节也算错误
+    pub fn decode(mut buff: &[u8]) -> Result<Self, ErrorKind> {
+        let total = read_u16(&mut buff)?;
+        let mut headers = Headers::new();
+        for _ in 0..total {
+            let length = read_u8(&mut buff)? as usize;
+            let name = read_str(&mut buff, length)?;
+            if name.is_empty() {
+                return Err(ErrorKind::InvalidHeaders);
+            }
+            let length = read_u16(&mut buff)? as usize;
+            let value = read_str(&mut buff, length)?;
+            headers.entries.push((name.to_owned(), value.to_owned()));
+        }
+
+        if buff.is_empty() {
+            Ok(headers)
+        } else {
+            Err(ErrorKind::InvalidHeaders)
+        }
+    }
+}
+
+fn read_u8(buff: &mut &[u8]) -> Result<u8, ErrorKind> {
+    if buff.remaining() >= U8_SIZE {
+        Ok(buff.get_u8())
+    } else {
+        Err(ErrorKind::InvalidHeaders)
+    }
+}
+
+fn read_u16(buff: &mut &[u8]) -> Result<u16, ErrorKind> {
+    if buff.remaining() >= U16_SIZE {
+        Ok(buff.get_u16())
+    } else {
+        Err(ErrorKind::InvalidHeaders)
+    }
+}
+
+// 名称和值都必须是utf8
+fn read_str<'a>(buff: &mut &'a [u8], length: usize) -> Result<&'a str, ErrorKind> {
+    if buff.len() < length {
+        return Err(ErrorKind::InvalidHeaders);
+    }
+    let (value, rest) = buff.split_at(length);
+    *buff = rest;
+    std::str::from_utf8(value).map_err(|_| ErrorKind::InvalidHeaders)
+}
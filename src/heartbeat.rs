@@ -0,0 +1,114 @@
+use crate::clock::{Clock, SystemClock};
+use crate::send_to_server::decode::Message;
+use std::time::{Duration, Instant};
+
+// 心跳间隔
+const PING_INTERVAL: Duration = Duration::from_secs(30);
+
+// 每次超时增加的间隔
+const TIMEOUT_BACKOFF: Duration = Duration::from_secs(10);
+
+// 超时次数上限
+const MAX_TIMEOUT: u8 = 3;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Action {
+    // 在这个时间之前不需要做任何事
+    Wait(Instant),
+
+    // 需要发出ping
+    SendPing,
+
+    // 连续超时达到上限, 应该关闭连接
+    Dead,
+}
+
+// 客户端心跳
+// 每30秒发出ping, 没有在间隔内收到pong就记一次超时 n,
+// 下一次心跳时间为 30 + n * 10 秒, n 达到3时认为连接已断开
+#[derive(Debug)]
+pub struct Heartbeat<C = SystemClock> {
+    clock: C,
+    next: Instant,
+    waiting: bool,
+    timeout: u8,
+    dead: bool,
+}
+
+impl Default for Heartbeat<SystemClock> {
+    fn default() -> Self {
+        Self::new(SystemClock)
+    }
+}
+
+impl<C> Heartbeat<C>
+where
+    C: Clock,
+{
+    pub fn new(clock: C) -> Self {
+        let next = clock.now() + PING_INTERVAL;
+
+        Self {
+            clock,
+            next,
+            waiting: false,
+            timeout: 0,
+            dead: false,
+        }
+    }
+
+    pub fn timeout(&self) -> u8 {
+        self.timeout
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead
+    }
+
+    // 下一次需要调用 poll 的时间
+    pub fn next_deadline(&self) -> Instant {
+        self.next
+    }
+
+    pub fn poll(&mut self) -> Action {
+        if self.dead {
+            return Action::Dead;
+        }
+
+        let now = self.clock.now();
+        if now < self.next {
+            return Action::Wait(self.next);
+        }
+
+        if self.waiting {
+            self.timeout += 1;
+
+            if self.timeout >= MAX_TIMEOUT {
+                self.dead = true;
+                return Action::Dead;
+            }
+        }
+
+        self.waiting = true;
+        self.next = now + PING_INTERVAL + TIMEOUT_BACKOFF * self.timeout as u32;
+        Action::SendPing
+    }
+
+    // 收到pong, 清空超时次数
+    pub fn pong(&mut self) {
+        if !self.dead {
+            self.waiting = false;
+            self.timeout = 0;
+        }
+    }
+
+    // 只处理pong, 返回是否处理了该消息
+    pub fn receive(&mut self, message: &Message) -> bool {
+        if let Message::Pong = message {
+            self.pong();
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -1,4 +1,15 @@
+pub mod auth;
+pub mod capabilities;
+pub mod clock;
+#[cfg(feature = "tokio")]
+pub mod codec;
 mod common;
+pub mod error;
+pub mod frame;
+pub mod headers;
+pub mod heartbeat;
+pub mod mode;
 pub mod send_to_client;
 pub mod send_to_server;
 pub mod state;
+pub mod version;
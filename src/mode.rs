@@ -0,0 +1,163 @@
+use crate::capabilities::Capabilities;
+use crate::state::{Mode, Support};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+// 默认等待 ok 的时间
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum ModeError {
+    #[error("{0:?} mode was not negotiated")]
+    NotAllowed(Mode),
+
+    #[error("a mode switch is already waiting for ok")]
+    SwitchPending,
+
+    #[error("no mode switch is waiting for ok")]
+    NoSwitchPending,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Stable(Mode),
+
+    // 客户端已经发出 turn_push 或 turn_pull, 等待服务器回复
+    Switching {
+        from: Mode,
+        to: Mode,
+        deadline: Instant,
+    },
+}
+
+// 投递模式的状态机, 两端共用
+// 只能切换到握手时双方都支持的模式, 客户端同时只能有一个切换请求, 服务器收到请求后立即切换
+#[derive(Debug)]
+pub struct DeliveryMode {
+    state: State,
+    capabilities: Capabilities,
+    timeout: Duration,
+}
+
+impl DeliveryMode {
+    // 支持推模式时初始为推模式, 只支持拉模式时初始为拉模式
+    pub fn new(capabilities: Capabilities) -> Self {
+        Self {
+            state: State::Stable(initial_mode(capabilities)),
+            capabilities,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    // 握手完成后设置协商出来的服务, 同时按照协商结果重新选择初始模式
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = capabilities;
+        self.state = State::Stable(initial_mode(capabilities));
+    }
+
+    // 只影响之后的切换请求
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    // 切换完成前仍然是原来的模式
+    pub fn mode(&self) -> Mode {
+        match self.state {
+            State::Stable(mode) => mode,
+            State::Switching { from, .. } => from,
+        }
+    }
+
+    pub fn is_switching(&self) -> bool {
+        matches!(self.state, State::Switching { .. })
+    }
+
+    pub fn is_allowed(&self, mode: Mode) -> bool {
+        let support = match mode {
+            Mode::Push => Support::Push,
+            Mode::Pull => Support::Pull,
+        };
+        self.capabilities.contains(support)
+    }
+
+    // 客户端发出切换请求
+    pub fn request(&mut self, to: Mode, now: Instant) -> Result<(), ModeError> {
+        if self.is_switching() {
+            return Err(ModeError::SwitchPending);
+        }
+        if !self.is_allowed(to) {
+            return Err(ModeError::NotAllowed(to));
+        }
+        self.state = State::Switching {
+            from: self.mode(),
+            to,
+            deadline: now + self.timeout,
+        };
+        Ok(())
+    }
+
+    // 客户端收到 ok, 返回切换后的模式
+    pub fn confirm(&mut self) -> Result<Mode, ModeError> {
+        match self.state {
+            State::Switching { to, .. } => {
+                self.state = State::Stable(to);
+                Ok(to)
+            }
+            State::Stable(_) => Err(ModeError::NoSwitchPending),
+        }
+    }
+
+    // 客户端收到错误, 保持原来的模式
+    pub fn reject(&mut self) -> Result<Mode, ModeError> {
+        match self.state {
+            State::Switching { from, .. } => {
+                self.state = State::Stable(from);
+                Ok(from)
+            }
+            State::Stable(_) => Err(ModeError::NoSwitchPending),
+        }
+    }
+
+    // 服务器收到切换请求, 不支持的模式需要回复错误
+    pub fn accept(&mut self, to: Mode) -> Result<Mode, ModeError> {
+        if !self.is_allowed(to) {
+            return Err(ModeError::NotAllowed(to));
+        }
+        self.state = State::Stable(to);
+        Ok(to)
+    }
+
+    pub fn next_deadline(&self) -> Option<Instant> {
+        match self.state {
+            State::Switching { deadline, .. } => Some(deadline),
+            State::Stable(_) => None,
+        }
+    }
+
+    // 超时后回到原来的模式, 返回没有得到回答的目标模式
+    pub fn poll_timeout(&mut self, now: Instant) -> Option<Mode> {
+        match self.state {
+            State::Switching { from, to, deadline } if deadline <= now => {
+                self.state = State::Stable(from);
+                Some(to)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn initial_mode(capabilities: Capabilities) -> Mode {
+    if !capabilities.contains(Support::Push) && capabilities.contains(Support::Pull) {
+        Mode::Pull
+    } else {
+        Mode::Push
+    }
+}
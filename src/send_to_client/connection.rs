@@ -0,0 +1,583 @@
+use super::decode::{
+    Ack, Decode, Erro, Error as DecodeError, ErrorCode, ErrorKind, Fetch, Info, Message, Nack,
+    Offset, Pub, Reply, Request, Sub, UnSub,
+};
+use super::encode::{self, Err, FetchDone, Msg, ServerConfig, ServerFrame, SubAck};
+use crate::auth::{self, Authenticator, NoAuth};
+use crate::capabilities::{Capabilities, NegotiateError};
+use crate::headers::Headers;
+use crate::mode::DeliveryMode;
+use crate::state::{FetchStatus, Mode, Phase, Support};
+use crate::version::{Version, VersionError};
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::AsRef;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    #[error(transparent)]
+    Negotiate(#[from] NegotiateError),
+
+    #[error(transparent)]
+    Version(#[from] VersionError),
+
+    #[error("unexpected {frame} frame while {phase:?}")]
+    UnexpectedFrame { frame: &'static str, phase: Phase },
+
+    #[error("connection is not established")]
+    NotConnected,
+
+    #[error("client is not subscribed to this name")]
+    NotSubscribed,
+
+    #[error("client failed to authenticate")]
+    AuthFailed,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Connected(Box<Info>),
+    Pub(Box<Pub>),
+    Sub(Box<Sub>),
+    UnSub(Box<UnSub>),
+    Offset(Box<Offset>),
+    Ack(Box<Ack>),
+
+    // 客户端拒绝的消息, 已经归还额度, 由调用者安排重新投递
+    Nack(Box<Nack>),
+    Request(Box<Request>),
+    Reply(Box<Reply>),
+
+    // 拉模式下客户端请求一批消息, 发完之后调用 send_fetch_done
+    Fetch(Box<Fetch>),
+
+    // 客户端额外给出的消息数量, 已经用来发送等待中的消息
+    Credit(u32),
+    Err(Box<Erro>),
+    ModeChanged(Mode),
+    Pong,
+}
+
+// 没有额度时暂存的消息, 已经按照协商的版本编码好
+#[derive(Debug)]
+struct Queued {
+    sub_name: Bytes,
+    offset: u64,
+    frame: BytesMut,
+}
+
+// 服务器端的连接状态机, 不做任何io
+// 创建时就把服务器信息放进发送缓冲, 之后必须先收到客户端信息才接受其他消息
+// 推送的消息受客户端的 max_task_size 限制, 收到应答或者额外的额度之后才继续推送
+// 服务器开启认证时, 客户端信息中的证明必须通过 authenticator 的校验
+#[derive(Debug)]
+pub struct ServerConnection<A = NoAuth> {
+    decode: Decode,
+    config: ServerConfig,
+    authenticator: A,
+    phase: Phase,
+    version: Version,
+    delivery: DeliveryMode,
+
+    // 双方都支持的服务
+    capabilities: Capabilities,
+
+    // 客户端可容纳的消息数量, 为0时不做流量控制
+    max_task_size: u8,
+
+    // 还可以推送的消息数量
+    credit: u32,
+
+    // 已经推送但还没有应答的消息
+    in_flight: HashSet<(Bytes, u64)>,
+
+    // 额度用完之后等待推送的消息
+    backlog: VecDeque<Queued>,
+
+    // 转为拉模式时还没有推送出去的消息, 由调用者之后重新投递
+    returned: Vec<(Bytes, u64)>,
+
+    // 订阅名称和分配的订阅号
+    subscriptions: HashMap<Bytes, u32>,
+    next_sid: u32,
+    send: BytesMut,
+}
+
+impl ServerConnection<NoAuth> {
+    pub fn new(config: ServerConfig) -> Self {
+        Self::with_authenticator(config, NoAuth)
+    }
+}
+
+impl<A> ServerConnection<A>
+where
+    A: Authenticator,
+{
+    pub fn with_authenticator(mut config: ServerConfig, authenticator: A) -> Self {
+        // 每个连接使用新的随机数, 防止重放之前的证明
+        if config.get_support() & Support::Auth && config.get_nonce().is_empty() {
+            config.set_nonce(auth::nonce());
+        }
+        let send = config.encode();
+        let mut decode = Decode::new(1024);
+        decode.set_max_message_length(config.get_max_message_length() as usize);
+
+        Self {
+            decode,
+            config,
+            authenticator,
+            phase: Phase::Handshake,
+            version: Version::V1,
+            delivery: DeliveryMode::new(Capabilities::empty()),
+            capabilities: Capabilities::empty(),
+            max_task_size: 0,
+            credit: 0,
+            in_flight: HashSet::new(),
+            backlog: VecDeque::new(),
+            returned: Vec::new(),
+            subscriptions: HashMap::new(),
+            next_sid: 1,
+            send,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.phase == Phase::Established
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.delivery.mode()
+    }
+
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn max_task_size(&self) -> u8 {
+        self.max_task_size
+    }
+
+    // 这个连接发给客户端的随机数, 没有开启认证时为空
+    pub fn nonce(&self) -> &Bytes {
+        self.config.get_nonce()
+    }
+
+    // 还可以直接推送的消息数量
+    pub fn credit(&self) -> u32 {
+        self.credit
+    }
+
+    // 已经推送但还没有应答的消息数量
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    // 因为额度不够还没有发出的消息数量
+    pub fn queued(&self) -> usize {
+        self.backlog.len()
+    }
+
+    // 取出转为拉模式时退回的消息, 为订阅名称和消息号
+    pub fn take_returned(&mut self) -> Vec<(Bytes, u64)> {
+        std::mem::take(&mut self.returned)
+    }
+
+    pub fn is_subscribed<N>(&self, sub_name: N) -> bool
+    where
+        N: AsRef<[u8]>,
+    {
+        self.subscriptions.contains_key(sub_name.as_ref())
+    }
+
+    pub fn subscriptions(&self) -> impl Iterator<Item = &Bytes> {
+        self.subscriptions.keys()
+    }
+
+    pub fn sid<N>(&self, sub_name: N) -> Option<u32>
+    where
+        N: AsRef<[u8]>,
+    {
+        self.subscriptions.get(sub_name.as_ref()).copied()
+    }
+
+    pub fn receive<R>(&mut self, buff: R)
+    where
+        R: AsRef<[u8]>,
+    {
+        if self.phase != Phase::Closed {
+            self.decode.set_buff(buff);
+        }
+    }
+
+    pub fn poll_event(&mut self) -> Option<Result<Event, Error>> {
+        loop {
+            if self.phase == Phase::Closed {
+                return None;
+            }
+
+            let result = match self.decode.iter().next()? {
+                Ok(message) => self.handle(message),
+                Err(e) => Err(Error::from(e)),
+            };
+
+            match result {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => {}
+                Err(e) => {
+                    // 关闭前告诉客户端原因
+                    let code = match &e {
+                        Error::Negotiate(_) => ErrorCode::UnsupportedCapabilities,
+                        Error::Version(_) => ErrorCode::IncompatibleVersion,
+                        Error::AuthFailed => ErrorCode::AuthFailure,
+                        Error::Decode(e)
+                            if matches!(e.kind(), ErrorKind::PayloadTooLarge { .. }) =>
+                        {
+                            ErrorCode::PayloadTooLarge
+                        }
+                        _ => ErrorCode::ProtocolViolation,
+                    };
+                    self.queue_err(code);
+                    self.phase = Phase::Closed;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    // 取出所有待发送的字节
+    pub fn poll_transmit(&mut self) -> Option<BytesMut> {
+        if self.send.is_empty() {
+            None
+        } else {
+            Some(self.send.split())
+        }
+    }
+
+    pub fn send_msg(&mut self, offset: u64, sub_name: &[u8], msg: &[u8]) -> Result<(), Error> {
+        self.established()?;
+        let sid = self.sid(sub_name).ok_or(Error::NotSubscribed)?;
+        self.push(
+            offset,
+            sub_name,
+            ServerFrame::Msg(Msg::new(offset, sub_name, msg).with_sid(sid)),
+        );
+        Ok(())
+    }
+
+    pub fn send_msg_with_headers(
+        &mut self,
+        offset: u64,
+        sub_name: &[u8],
+        headers: &Headers,
+        msg: &[u8],
+    ) -> Result<(), Error> {
+        self.established()?;
+        let sid = self.sid(sub_name).ok_or(Error::NotSubscribed)?;
+        self.push(
+            offset,
+            sub_name,
+            ServerFrame::Msg(
+                Msg::new(offset, sub_name, msg)
+                    .with_sid(sid)
+                    .with_headers(headers),
+            ),
+        );
+        Ok(())
+    }
+
+    // 把请求转发给订阅了这个名称的客户端
+    pub fn send_request(
+        &mut self,
+        id: u64,
+        sub_name: &[u8],
+        reply_to: &[u8],
+        payload: &[u8],
+    ) -> Result<(), Error> {
+        self.established()?;
+        if !self.subscriptions.contains_key(sub_name) {
+            return Err(Error::NotSubscribed);
+        }
+        self.queue(ServerFrame::Request(encode::Request::new(
+            id, sub_name, reply_to, payload,
+        )));
+        Ok(())
+    }
+
+    // 应答直接发到请求方的收件箱, 不需要订阅
+    pub fn send_reply(&mut self, id: u64, sub_name: &[u8], payload: &[u8]) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ServerFrame::Reply(encode::Reply::new(
+            id, sub_name, payload,
+        )));
+        Ok(())
+    }
+
+    // 结束客户端的一次拉取, count 为这次拉取中发出的消息数量
+    pub fn send_fetch_done(
+        &mut self,
+        sub_name: &[u8],
+        status: FetchStatus,
+        count: u32,
+    ) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ServerFrame::FetchDone(FetchDone::new(
+            sub_name, status, count,
+        )));
+        Ok(())
+    }
+
+    // msg 为空时只发送错误码
+    pub fn send_err(&mut self, code: ErrorCode, msg: &str) {
+        if self.phase != Phase::Closed {
+            self.queue(ServerFrame::Err(Err::new(code).with_msg(msg)));
+        }
+    }
+
+    // 握手完成前使用第一版格式
+    fn queue(&mut self, frame: ServerFrame<'_>) {
+        frame.encode_into_with(self.version, &mut self.send);
+    }
+
+    // 带上错误码的默认说明
+    fn queue_err(&mut self, code: ErrorCode) {
+        self.queue(ServerFrame::Err(
+            Err::new(code).with_msg(code.description()),
+        ));
+    }
+
+    // 有额度时直接发送, 否则按顺序暂存
+    fn push(&mut self, offset: u64, sub_name: &[u8], frame: ServerFrame<'_>) {
+        if self.max_task_size == 0 {
+            self.queue(frame);
+            return;
+        }
+
+        // 重新投递还没有应答的消息不再占用额度
+        let sub_name = Bytes::copy_from_slice(sub_name);
+        if self.in_flight.contains(&(sub_name.clone(), offset)) {
+            self.queue(frame);
+        } else if self.credit > 0 && self.backlog.is_empty() {
+            self.credit -= 1;
+            self.in_flight.insert((sub_name, offset));
+            self.queue(frame);
+        } else {
+            let mut buff = BytesMut::new();
+            frame.encode_into_with(self.version, &mut buff);
+            self.backlog.push_back(Queued {
+                sub_name,
+                offset,
+                frame: buff,
+            });
+        }
+    }
+
+    // 额度增加之后发送暂存的消息
+    fn drain(&mut self) {
+        while self.credit > 0 {
+            let queued = match self.backlog.pop_front() {
+                Some(queued) => queued,
+                None => break,
+            };
+            self.credit -= 1;
+            self.send.extend_from_slice(&queued.frame);
+            self.in_flight.insert((queued.sub_name, queued.offset));
+        }
+    }
+
+    // 没有协商的模式回复错误, 不关闭连接
+    fn turn(&mut self, mode: Mode) -> Option<Event> {
+        let from = self.delivery.mode();
+        if self.delivery.accept(mode).is_err() {
+            self.queue_err(ErrorCode::ModeNotSupported);
+            return None;
+        }
+
+        // 已经推送的消息仍然等待应答, 还没推送的退回给调用者
+        if from == Mode::Push && mode == Mode::Pull {
+            self.returned.extend(
+                self.backlog
+                    .drain(..)
+                    .map(|queued| (queued.sub_name, queued.offset)),
+            );
+        }
+        self.queue(ServerFrame::Ok);
+        Some(Event::ModeChanged(mode))
+    }
+
+    fn established(&self) -> Result<(), Error> {
+        if self.phase == Phase::Established {
+            Ok(())
+        } else {
+            Err(Error::NotConnected)
+        }
+    }
+
+    // 服务器开启认证时客户端也必须开启, 没有凭证的客户端同样被拒绝
+    fn authenticate(&self, info: &Info) -> Result<(), Error> {
+        if !(self.config.get_support() & Support::Auth) {
+            return Ok(());
+        }
+        if self.capabilities.contains(Support::Auth)
+            && self
+                .authenticator
+                .authenticate(self.config.get_nonce(), &info.user, &info.proof)
+        {
+            Ok(())
+        } else {
+            Err(Error::AuthFailed)
+        }
+    }
+
+    fn unexpected(&self, message: &Message) -> Error {
+        Error::UnexpectedFrame {
+            frame: frame_name(message),
+            phase: self.phase,
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<Option<Event>, Error> {
+        if self.phase == Phase::Handshake {
+            return match message {
+                Message::Info(info) => {
+                    // 客户端信息中的版本是客户端选定的版本
+                    self.version = Version::accept(
+                        self.config.get_min_version(),
+                        self.config.get_version(),
+                        info.version,
+                    )?;
+                    self.capabilities = Capabilities::negotiate(
+                        Capabilities::from_bits(self.config.get_support()),
+                        info.capabilities(),
+                    )?;
+                    self.authenticate(&info)?;
+                    self.delivery.set_capabilities(self.capabilities);
+                    self.max_task_size = info.max_message_size;
+                    self.credit = info.max_message_size as u32;
+                    self.decode.set_version(self.version);
+                    self.phase = Phase::Established;
+                    Ok(Some(Event::Connected(info)))
+                }
+                message => Err(self.unexpected(&message)),
+            };
+        }
+
+        match message {
+            Message::Ping => {
+                self.queue(ServerFrame::Pong);
+                Ok(None)
+            }
+            Message::Pong => Ok(Some(Event::Pong)),
+            Message::Sub(sub) => {
+                match self.subscriptions.get(&sub.name) {
+                    // 重复订阅保留原来的订阅号
+                    Some(_) if sub.reply => self.queue_err(ErrorCode::AlreadySubscribed),
+                    Some(_) => {}
+                    None => {
+                        let sid = self.next_sid;
+                        self.next_sid = self.next_sid.wrapping_add(1).max(1);
+                        self.subscriptions.insert(sub.name.clone(), sid);
+                        if sub.reply {
+                            self.queue(ServerFrame::SubAck(SubAck::new(sid, &sub.name)));
+                        }
+                    }
+                }
+                Ok(Some(Event::Sub(sub)))
+            }
+            Message::UnSub(unsub) => {
+                let mut missing = false;
+                unsub.name_list.iter().for_each(|name| {
+                    missing |= self.subscriptions.remove(name).is_none();
+                });
+
+                // 取消订阅的消息不再推送, 也不再等待应答
+                let removed = |name: &Bytes| unsub.name_list.contains(name);
+                let before = self.in_flight.len();
+                self.in_flight.retain(|(name, _)| !removed(name));
+                self.backlog.retain(|queued| !removed(&queued.sub_name));
+                self.credit = self
+                    .credit
+                    .saturating_add((before - self.in_flight.len()) as u32);
+                self.drain();
+
+                if unsub.reply {
+                    if missing {
+                        self.queue_err(ErrorCode::NotSubscribed);
+                    } else {
+                        self.queue(ServerFrame::Ok);
+                    }
+                }
+                Ok(Some(Event::UnSub(unsub)))
+            }
+            Message::Pub(r#pub) => Ok(Some(Event::Pub(r#pub))),
+            Message::Offset(offset) => Ok(Some(Event::Offset(offset))),
+            Message::Ack(ack) => {
+                if self.in_flight.remove(&(ack.sub_name.clone(), ack.offset)) {
+                    self.credit = self.credit.saturating_add(1);
+                    self.drain();
+                }
+                Ok(Some(Event::Ack(ack)))
+            }
+            Message::Nack(nack) => {
+                if self.in_flight.remove(&(nack.sub_name.clone(), nack.offset)) {
+                    self.credit = self.credit.saturating_add(1);
+                    self.drain();
+                }
+                Ok(Some(Event::Nack(nack)))
+            }
+            Message::Credit(credit) => {
+                self.credit = self.credit.saturating_add(credit);
+                self.drain();
+                Ok(Some(Event::Credit(credit)))
+            }
+            Message::Request(request) => Ok(Some(Event::Request(request))),
+            Message::Reply(reply) => Ok(Some(Event::Reply(reply))),
+            Message::Fetch(fetch) => {
+                // 推模式下消息会直接推过去, 不接受拉取
+                if self.delivery.mode() == Mode::Pull {
+                    Ok(Some(Event::Fetch(fetch)))
+                } else {
+                    self.queue_err(ErrorCode::NotInPullMode);
+                    Ok(None)
+                }
+            }
+            Message::Err(erro) => Ok(Some(Event::Err(erro))),
+            Message::TurnPush => Ok(self.turn(Mode::Push)),
+            Message::TurnPull => Ok(self.turn(Mode::Pull)),
+            message => Err(self.unexpected(&message)),
+        }
+    }
+}
+
+fn frame_name(message: &Message) -> &'static str {
+    match message {
+        Message::Info(_) => "client info",
+        Message::Ping => "ping",
+        Message::Pong => "pong",
+        Message::TurnPush => "turn push",
+        Message::TurnPull => "turn pull",
+        Message::Ok => "ok",
+        Message::Err(_) => "err",
+        Message::Pub(_) => "pub",
+        Message::Sub(_) => "sub",
+        Message::UnSub(_) => "unsub",
+        Message::Offset(_) => "offset",
+        Message::Ack(_) => "ack",
+        Message::Nack(_) => "nack",
+        Message::Request(_) => "request",
+        Message::Reply(_) => "reply",
+        Message::Fetch(_) => "fetch",
+        Message::Credit(_) => "credit",
+    }
+}
@@ -1,44 +1,120 @@
-use crate::common::{U16_SIZE, U32_SIZE, U8_SIZE};
-use crate::state::ServerState;
-use bytes::{Buf, BytesMut};
+use crate::capabilities::Capabilities;
+use crate::common::{ENVELOPE_SIZE, U16_SIZE, U32_SIZE, U64_SIZE, U8_SIZE};
+use crate::error::is_valid_subject;
+use crate::headers::Headers;
+use crate::state::{is_frame_type, ServerState, Support, SUB_QUEUE, SUB_REPLY};
+use crate::version::Version;
+use bytes::{Buf, Bytes, BytesMut};
 use std::convert::AsRef;
 use std::convert::TryInto;
-use std::iter::Iterator;
+use std::iter::{FusedIterator, Iterator};
 use std::mem::swap;
-use thiserror::Error;
+use std::time::Duration;
 
-#[derive(Debug, Error)]
-pub enum Error {
-    #[error("parse error")]
-    Parse,
-}
+pub use crate::error::{Error, ErrorCode, ErrorKind, ErrorPolicy};
 
 #[derive(Debug)]
 pub struct Info {
     pub version: u8,
     pub support: u16,
     pub max_message_size: u8,
+
+    // 开启认证时才有, 令牌认证时用户名为空
+    pub user: Bytes,
+    pub proof: Bytes,
+}
+
+impl Info {
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_bits(self.support)
+    }
 }
 
 #[derive(Debug)]
 pub struct Erro {
-    pub msg: BytesMut,
+    // 第一版没有错误码, 为 Unknown(0)
+    pub code: ErrorCode,
+
+    // 可以为空
+    pub msg: Bytes,
 }
 
 #[derive(Debug)]
 pub struct Pub {
-    pub name: BytesMut,
-    pub msg: BytesMut,
+    pub name: Bytes,
+
+    // 不带消息头的发布为空
+    pub headers: Headers,
+    pub msg: Bytes,
 }
 
 #[derive(Debug)]
 pub struct Sub {
-    pub name: BytesMut,
+    // 客户端要求服务器确认
+    pub reply: bool,
+    pub name: Bytes,
+
+    // 同一个队列组中只有一个成员收到消息
+    pub queue: Option<Bytes>,
 }
 
 #[derive(Debug)]
 pub struct UnSub {
-    pub name_list: Vec<BytesMut>,
+    pub reply: bool,
+    pub name_list: Vec<Bytes>,
+}
+
+#[derive(Debug)]
+pub struct Offset {
+    pub offset: u64,
+    pub sub_name: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Ack {
+    pub offset: u64,
+    pub sub_name: Bytes,
+}
+
+// 客户端拒绝的消息
+#[derive(Debug)]
+pub struct Nack {
+    pub offset: u64,
+    pub sub_name: Bytes,
+
+    // 重新投递前的等待时间, 0 表示由服务器决定
+    pub delay: Duration,
+    pub reason: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub id: u64,
+    pub sub_name: Bytes,
+    pub reply_to: Bytes,
+    pub payload: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Reply {
+    pub id: u64,
+    pub sub_name: Bytes,
+    pub payload: Bytes,
+}
+
+// 拉模式下请求一批消息
+#[derive(Debug)]
+pub struct Fetch {
+    pub sub_name: Bytes,
+
+    // 最多发出的消息数量
+    pub batch: u32,
+
+    // 消息内容的总字节数上限, 0 表示不限制
+    pub max_bytes: u32,
+
+    // 没有足够的消息时最多等待的时间, 0 表示立即结束
+    pub expires: Duration,
 }
 
 #[derive(Debug)]
@@ -53,62 +129,243 @@ pub enum Message {
     Pub(Box<Pub>),
     Sub(Box<Sub>),
     UnSub(Box<UnSub>),
+    Offset(Box<Offset>),
+    Ack(Box<Ack>),
+    Nack(Box<Nack>),
+    Request(Box<Request>),
+    Reply(Box<Reply>),
+    Fetch(Box<Fetch>),
+
+    // 客户端额外给出的消息数量
+    Credit(u32),
 }
 
 // 解析出来的参数暂存
 #[derive(Debug)]
 enum Transition {
     None,
+    Info {
+        version: u8,
+        support: u16,
+        max_message_size: u8,
+        user: Bytes,
+        proof: Bytes,
+    },
+    Err {
+        code: ErrorCode,
+        msg: Bytes,
+    },
     Sub {
-        name: BytesMut,
+        flags: u8,
+        name: Bytes,
+        queue: Option<Bytes>,
     },
     Pub {
-        name: BytesMut,
-        msg: BytesMut,
+        name: Bytes,
+        headers: Headers,
+        msg: Bytes,
     },
     UnSub {
-        name_list: Vec<BytesMut>,
+        reply: bool,
+        name_list: Vec<Bytes>,
         total: u16,
         count: u16,
     },
+    Offset {
+        offset: u64,
+        sub_name: Bytes,
+    },
+    Ack {
+        offset: u64,
+        sub_name: Bytes,
+    },
+    Nack {
+        offset: u64,
+        sub_name: Bytes,
+        delay: Duration,
+        reason: Bytes,
+    },
+    Request {
+        id: u64,
+        sub_name: Bytes,
+        reply_to: Bytes,
+        payload: Bytes,
+    },
+    Reply {
+        id: u64,
+        sub_name: Bytes,
+        payload: Bytes,
+    },
+    Fetch {
+        sub_name: Bytes,
+        batch: u32,
+        max_bytes: u32,
+        expires: Duration,
+    },
 }
 
 impl Transition {
-    fn sub() -> Self {
+    fn sub(flags: u8) -> Self {
         Transition::Sub {
-            name: BytesMut::new(),
+            flags,
+            name: Bytes::new(),
+            queue: None,
+        }
+    }
+
+    fn has_queue(&self) -> bool {
+        match self {
+            Transition::Sub {
+                flags,
+                name: _,
+                queue: _,
+            } => flags & SUB_QUEUE != 0,
+            _ => false,
+        }
+    }
+
+    fn set_queue(&mut self, new_queue: Bytes) {
+        if let Transition::Sub {
+            flags: _,
+            name: _,
+            queue,
+        } = self
+        {
+            *queue = Some(new_queue);
         }
     }
 
-    fn set_sub_name(&mut self, sub_name: BytesMut) {
+    fn set_sub_name(&mut self, sub_name: Bytes) {
         match self {
-            Transition::Sub { name } => {
+            Transition::Sub {
+                flags: _,
+                name,
+                queue: _,
+            } => {
                 *name = sub_name;
             }
-            Transition::Pub { name, msg: _ } => {
+            Transition::Pub {
+                name,
+                headers: _,
+                msg: _,
+            } => {
                 *name = sub_name;
             }
             Transition::UnSub {
+                reply: _,
                 name_list,
                 total: _,
                 count: _,
             } => {
                 name_list.push(sub_name);
             }
+            Transition::Offset {
+                offset: _,
+                sub_name: name,
+            } => {
+                *name = sub_name;
+            }
+            Transition::Ack {
+                offset: _,
+                sub_name: name,
+            } => {
+                *name = sub_name;
+            }
+            Transition::Nack {
+                offset: _,
+                sub_name: name,
+                delay: _,
+                reason: _,
+            } => {
+                *name = sub_name;
+            }
+            Transition::Request {
+                id: _,
+                sub_name: name,
+                reply_to: _,
+                payload: _,
+            } => {
+                *name = sub_name;
+            }
+            Transition::Reply {
+                id: _,
+                sub_name: name,
+                payload: _,
+            } => {
+                *name = sub_name;
+            }
+            Transition::Fetch {
+                sub_name: name,
+                batch: _,
+                max_bytes: _,
+                expires: _,
+            } => {
+                *name = sub_name;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_reply_to(&mut self, reply_to: Bytes) {
+        if let Transition::Request {
+            id: _,
+            sub_name: _,
+            reply_to: non_reply_to,
+            payload: _,
+        } = self
+        {
+            *non_reply_to = reply_to;
+        }
+    }
+
+    fn set_payload(&mut self, payload: Bytes) {
+        match self {
+            Transition::Request {
+                id: _,
+                sub_name: _,
+                reply_to: _,
+                payload: non_payload,
+            }
+            | Transition::Reply {
+                id: _,
+                sub_name: _,
+                payload: non_payload,
+            }
+            | Transition::Nack {
+                offset: _,
+                sub_name: _,
+                delay: _,
+                reason: non_payload,
+            }
+            | Transition::Err {
+                code: _,
+                msg: non_payload,
+            }
+            | Transition::Info {
+                version: _,
+                support: _,
+                max_message_size: _,
+                user: _,
+                proof: non_payload,
+            } => {
+                *non_payload = payload;
+            }
             _ => {}
         }
     }
 
     fn r#pub() -> Self {
         Transition::Pub {
-            name: BytesMut::new(),
-            msg: BytesMut::new(),
+            name: Bytes::new(),
+            headers: Headers::new(),
+            msg: Bytes::new(),
         }
     }
 
-    fn set_pub_msg(&mut self, msg: BytesMut) {
+    fn set_pub_msg(&mut self, msg: Bytes) {
         if let Transition::Pub {
             name: _,
+            headers: _,
             msg: non_msg,
         } = self
         {
@@ -116,35 +373,45 @@ impl Transition {
         }
     }
 
+    fn set_headers(&mut self, new_headers: Headers) {
+        if let Transition::Pub {
+            name: _,
+            headers,
+            msg: _,
+        } = self
+        {
+            *headers = new_headers;
+        }
+    }
+
     fn set_total(&mut self, new_total: u16) {
-        match self {
-            Self::UnSub {
-                name_list: _,
-                total,
-                count: _,
-            } => {
-                *total = new_total;
-            }
-            _ => {}
+        if let Self::UnSub {
+            reply: _,
+            name_list: _,
+            total,
+            count: _,
+        } = self
+        {
+            *total = new_total;
         }
     }
 
     fn fetch_add_one(&mut self) {
-        match self {
-            Self::UnSub {
-                name_list: _,
-                total: _,
-                count,
-            } => {
-                *count += 1;
-            }
-            _ => {}
+        if let Self::UnSub {
+            reply: _,
+            name_list: _,
+            total: _,
+            count,
+        } = self
+        {
+            *count += 1;
         }
     }
 
     fn is_enough(&self) -> bool {
         match self {
             Self::UnSub {
+                reply: _,
                 name_list: _,
                 total,
                 count,
@@ -153,27 +420,233 @@ impl Transition {
         }
     }
 
-    fn unsub() -> Self {
+    fn unsub(reply: bool) -> Self {
         Transition::UnSub {
+            reply,
             name_list: Vec::new(),
             total: 0,
             count: 0,
         }
     }
 
-    fn return_params(&mut self) -> Result<Message, Error> {
+    fn offset(offset: u64) -> Self {
+        Transition::Offset {
+            offset,
+            sub_name: Bytes::new(),
+        }
+    }
+
+    fn ack(offset: u64) -> Self {
+        Transition::Ack {
+            offset,
+            sub_name: Bytes::new(),
+        }
+    }
+
+    fn info(version: u8, support: u16, max_message_size: u8) -> Self {
+        Transition::Info {
+            version,
+            support,
+            max_message_size,
+            user: Bytes::new(),
+            proof: Bytes::new(),
+        }
+    }
+
+    fn set_user(&mut self, new_user: Bytes) {
+        if let Transition::Info {
+            version: _,
+            support: _,
+            max_message_size: _,
+            user,
+            proof: _,
+        } = self
+        {
+            *user = new_user;
+        }
+    }
+
+    fn err(code: ErrorCode) -> Self {
+        Transition::Err {
+            code,
+            msg: Bytes::new(),
+        }
+    }
+
+    fn nack(offset: u64, delay: Duration) -> Self {
+        Transition::Nack {
+            offset,
+            sub_name: Bytes::new(),
+            delay,
+            reason: Bytes::new(),
+        }
+    }
+
+    fn request(id: u64) -> Self {
+        Transition::Request {
+            id,
+            sub_name: Bytes::new(),
+            reply_to: Bytes::new(),
+            payload: Bytes::new(),
+        }
+    }
+
+    fn reply(id: u64) -> Self {
+        Transition::Reply {
+            id,
+            sub_name: Bytes::new(),
+            payload: Bytes::new(),
+        }
+    }
+
+    fn fetch(batch: u32, max_bytes: u32, expires: Duration) -> Self {
+        Transition::Fetch {
+            sub_name: Bytes::new(),
+            batch,
+            max_bytes,
+            expires,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            Transition::None => true,
+            Transition::Err { code: _, msg: _ } => true,
+            Transition::Info {
+                version: _,
+                support: _,
+                max_message_size: _,
+                user: _,
+                proof: _,
+            } => true,
+            Transition::Sub {
+                flags: _,
+                name,
+                queue,
+            } => is_valid_subject(name) && queue.iter().all(|queue| is_valid_subject(queue)),
+            Transition::Pub {
+                name,
+                headers: _,
+                msg: _,
+            } => is_valid_subject(name),
+            Transition::UnSub {
+                reply: _,
+                name_list,
+                total: _,
+                count: _,
+            } => name_list.iter().all(|name| is_valid_subject(name)),
+            Transition::Offset {
+                offset: _,
+                sub_name,
+            }
+            | Transition::Ack {
+                offset: _,
+                sub_name,
+            }
+            | Transition::Nack {
+                offset: _,
+                sub_name,
+                delay: _,
+                reason: _,
+            }
+            | Transition::Reply {
+                id: _,
+                sub_name,
+                payload: _,
+            }
+            | Transition::Fetch {
+                sub_name,
+                batch: _,
+                max_bytes: _,
+                expires: _,
+            } => is_valid_subject(sub_name),
+            Transition::Request {
+                id: _,
+                sub_name,
+                reply_to,
+                payload: _,
+            } => is_valid_subject(sub_name) && is_valid_subject(reply_to),
+        }
+    }
+
+    fn return_params(&mut self) -> Message {
         let mut item = Transition::None;
         swap(self, &mut item);
 
         match item {
-            Self::None => Err(Error::Parse),
-            Self::Sub { name } => Ok(Message::Sub(Box::new(Sub { name }))),
-            Self::Pub { name, msg } => Ok(Message::Pub(Box::new(Pub { name, msg }))),
+            Self::None => unreachable!("frame finished without params"),
+            Self::Err { code, msg } => Message::Err(Box::new(Erro { code, msg })),
+            Self::Info {
+                version,
+                support,
+                max_message_size,
+                user,
+                proof,
+            } => Message::Info(Box::new(Info {
+                version,
+                support,
+                max_message_size,
+                user,
+                proof,
+            })),
+            Self::Sub { flags, name, queue } => Message::Sub(Box::new(Sub {
+                reply: flags & SUB_REPLY != 0,
+                name,
+                queue,
+            })),
+            Self::Pub { name, headers, msg } => Message::Pub(Box::new(Pub { name, headers, msg })),
             Self::UnSub {
+                reply,
                 name_list,
                 total: _,
                 count: _,
-            } => Ok(Message::UnSub(Box::new(UnSub { name_list }))),
+            } => Message::UnSub(Box::new(UnSub { reply, name_list })),
+            Self::Offset { offset, sub_name } => {
+                Message::Offset(Box::new(Offset { offset, sub_name }))
+            }
+            Self::Ack { offset, sub_name } => Message::Ack(Box::new(Ack { offset, sub_name })),
+            Self::Nack {
+                offset,
+                sub_name,
+                delay,
+                reason,
+            } => Message::Nack(Box::new(Nack {
+                offset,
+                sub_name,
+                delay,
+                reason,
+            })),
+            Self::Request {
+                id,
+                sub_name,
+                reply_to,
+                payload,
+            } => Message::Request(Box::new(Request {
+                id,
+                sub_name,
+                reply_to,
+                payload,
+            })),
+            Self::Reply {
+                id,
+                sub_name,
+                payload,
+            } => Message::Reply(Box::new(Reply {
+                id,
+                sub_name,
+                payload,
+            })),
+            Self::Fetch {
+                sub_name,
+                batch,
+                max_bytes,
+                expires,
+            } => Message::Fetch(Box::new(Fetch {
+                sub_name,
+                batch,
+                max_bytes,
+                expires,
+            })),
         }
     }
 }
@@ -184,6 +657,33 @@ pub struct Decode {
     state: Option<ServerState>,
     length: usize,
     params: Transition,
+    version: Version,
+
+    // 单个消息体允许的最大长度, 超过时直接报错而不是等待缓冲
+    max_message_length: usize,
+
+    // 之前已经解析掉的字节数, 用来定位出错的帧
+    position: u64,
+
+    // 本次迭代开始时缓冲的长度
+    mark: usize,
+
+    // 当前帧的类型字节所在的位置
+    frame_start: u64,
+
+    // 第二版中当前帧结束的位置
+    frame_end: Option<u64>,
+
+    policy: ErrorPolicy,
+
+    // Poison 策略下第一次出错的原因
+    poisoned: Option<Error>,
+
+    // Resync 策略下还需要丢弃的字节数
+    skip: usize,
+
+    // 第一版消息头出错时先读出消息体的长度, 再连同消息体一起跳过
+    header_error: Option<ErrorKind>,
 }
 
 impl Decode {
@@ -193,9 +693,50 @@ impl Decode {
             state: None,
             length: 0,
             params: Transition::None,
+            version: Version::V1,
+            max_message_length: u32::MAX as usize,
+            position: 0,
+            mark: 0,
+            frame_start: 0,
+            frame_end: None,
+            policy: ErrorPolicy::Poison,
+            poisoned: None,
+            skip: 0,
+            header_error: None,
         }
     }
 
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    // 握手完成后按照协商出来的版本解析之后的帧
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.policy = policy;
+    }
+
+    // 已经出错并且不再解析
+    pub fn poisoned(&self) -> Option<&Error> {
+        self.poisoned.as_ref()
+    }
+
+    pub fn max_message_length(&self) -> usize {
+        self.max_message_length
+    }
+
+    // 设置协商好的消息长度上限, 对Pub, Msg和Err帧生效
+    pub fn set_max_message_length(&mut self, max_message_length: usize) {
+        self.max_message_length = max_message_length;
+    }
+
     pub fn get_mut_buffer(&mut self) -> &mut BytesMut {
         &mut self.buffer
     }
@@ -207,8 +748,16 @@ impl Decode {
         self.buffer.extend_from_slice(buff.as_ref());
     }
 
+    // 缓冲中还有没解析完的帧, 字节流结束时说明最后一帧被截断
+    pub fn is_partial(&self) -> bool {
+        !self.buffer.is_empty() || self.state.is_some() || self.skip > 0
+    }
+
     pub fn iter(&mut self) -> Iter<'_> {
-        Iter { source: self }
+        Iter {
+            source: self,
+            done: false,
+        }
     }
 
     // 重置状态和length
@@ -216,11 +765,111 @@ impl Decode {
         self.state = None;
         self.length = 0;
         self.params = Transition::None;
+        self.header_error = None;
+    }
+
+    // 当前在整个字节流中的位置
+    fn consumed(&self) -> u64 {
+        self.position + (self.mark - self.buffer.len()) as u64
+    }
+
+    // 丢弃当前帧的状态, 生成带位置的错误
+    fn error(&mut self, kind: ErrorKind) -> Error {
+        let mut aligned = true;
+        if self.policy == ErrorPolicy::Resync {
+            // 第二版按照帧头中的长度跳过剩下的帧体
+            // 第一版只能跳过帧末尾超长的消息体, 名称和消息头出错时整个帧已经读完或者会连同消息体一起跳过
+            match (self.frame_end, &kind) {
+                (Some(end), _) => self.skip = end.saturating_sub(self.consumed()) as usize,
+                (None, ErrorKind::PayloadTooLarge { limit: _, got })
+                    if !matches!(self.state, Some(ServerState::HPubHeadersLength)) =>
+                {
+                    self.skip = *got
+                }
+                (None, ErrorKind::InvalidSubject) | (None, ErrorKind::InvalidHeaders) => {}
+                (None, _) => aligned = false,
+            }
+        }
+        self.frame_end = None;
+
+        let error = Error::new(kind, self.frame_start);
+        // 第一版不知道出错的帧在哪里结束, 继续解析只会把后面的字节当作新的帧, 和 Poison 一样不再解析
+        if !aligned {
+            self.poisoned = Some(error.clone());
+        }
+        self.reset();
+        error
+    }
+
+    // 消息体长度超过上限时直接报错, 不等待缓冲
+    fn check_length(&mut self) -> Result<(), Error> {
+        if self.length > self.max_message_length {
+            Err(self.error(ErrorKind::PayloadTooLarge {
+                limit: self.max_message_length,
+                got: self.length,
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
+    // 第二版的帧体只能读到帧头中声明的长度
+    fn available(&self) -> usize {
+        match self.frame_end {
+            Some(end) => self
+                .buffer
+                .len()
+                .min(end.saturating_sub(self.consumed()) as usize),
+            None => self.buffer.len(),
+        }
+    }
+
+    // 整个帧体都已经收到还解析不完, 说明声明的长度太短
+    fn check_body_end(&mut self) -> Option<Error> {
+        let end = self.frame_end?;
+        if self.buffer.len() as u64 >= end.saturating_sub(self.consumed()) {
+            let declared = (end - self.frame_start) as usize - ENVELOPE_SIZE;
+            Some(self.error(ErrorKind::LengthMismatch { declared }))
+        } else {
+            None
+        }
+    }
+
+    // 第二版按照帧头跳过整个帧, 第一版在 Resync 策略下推迟到读出消息体长度之后报错
+    fn check_headers(&mut self, result: Result<Headers, ErrorKind>) -> Result<(), Error> {
+        match result {
+            Ok(headers) => {
+                self.params.set_headers(headers);
+                Ok(())
+            }
+            Err(kind) if self.policy == ErrorPolicy::Resync && self.frame_end.is_none() => {
+                self.header_error = Some(kind);
+                Ok(())
+            }
+            Err(kind) => Err(self.error(kind)),
+        }
+    }
+
+    // 跳过被丢弃的消息体, 全部跳过之后返回true
+    fn discard(&mut self) -> bool {
+        let len = self.skip.min(self.buffer.len());
+        self.buffer.advance(len);
+        self.skip -= len;
+        self.skip == 0
+    }
+
+    // 整个帧读完之后再检查订阅名称, 出错时字节流仍然是对齐的
+    fn check_params(&mut self) -> Result<(), Error> {
+        if self.params.is_valid() {
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::InvalidSubject))
+        }
     }
 
     // 统一获取并订阅名称长度
     fn get_and_set_sub_name_length(&mut self) -> Option<()> {
-        if self.buffer.len() >= U8_SIZE {
+        if self.available() >= U8_SIZE {
             self.length = self.buffer.get_u8() as usize;
             Some(())
         } else {
@@ -229,9 +878,27 @@ impl Decode {
     }
 
     // 按照self.length获取内容
-    fn get_payload(&mut self) -> Option<BytesMut> {
-        if self.buffer.len() >= self.length {
-            Some(self.buffer.split_to(self.length))
+    fn get_payload(&mut self) -> Option<Bytes> {
+        if self.available() >= self.length {
+            Some(self.buffer.split_to(self.length).freeze())
+        } else {
+            None
+        }
+    }
+
+    // 获取订阅和取消订阅的标志位
+    fn get_flags(&mut self) -> Option<u8> {
+        if self.available() >= U8_SIZE {
+            Some(self.buffer.get_u8())
+        } else {
+            None
+        }
+    }
+
+    // 获取消息号
+    fn get_offset(&mut self) -> Option<u64> {
+        if self.available() >= U64_SIZE {
+            Some(self.buffer.get_u64())
         } else {
             None
         }
@@ -239,7 +906,7 @@ impl Decode {
 
     // 获取消息数量
     fn get_and_set_total(&mut self) -> Option<()> {
-        if self.buffer.len() >= U16_SIZE {
+        if self.available() >= U16_SIZE {
             self.params.set_total(self.buffer.get_u16());
             Some(())
         } else {
@@ -251,29 +918,57 @@ impl Decode {
 #[derive(Debug)]
 pub struct Iter<'a> {
     source: &'a mut Decode,
-}
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = Result<Message, Error>;
+    // 返回过错误之后本次迭代结束
+    done: bool,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'a> Iter<'a> {
+    // 第一版的帧格式
+    fn next_v1(&mut self) -> Option<Result<Message, Error>> {
         loop {
-            if !self.source.buffer.has_remaining() {
-                return None;
-            } else if let Some(state) = &self.source.state {
+            if let Some(state) = &self.source.state {
                 match state {
                     ServerState::ClientInfo => {
-                        if self.source.buffer.len() >= U32_SIZE {
-                            self.source.reset();
-                            return Some(Ok(Message::Info(Box::new(Info {
-                                version: self.source.buffer.get_u8(),
-                                support: self.source.buffer.get_u16(),
-                                max_message_size: self.source.buffer.get_u8(),
-                            }))));
+                        if self.source.available() >= U32_SIZE {
+                            let version = self.source.buffer.get_u8();
+                            let support = self.source.buffer.get_u16();
+                            let max_message_size = self.source.buffer.get_u8();
+                            self.source.params =
+                                Transition::info(version, support, max_message_size);
+
+                            // 开启认证时后面带上用户名和证明
+                            if support & Support::Auth {
+                                self.source.state = Some(ServerState::ClientInfoUserLength);
+                            } else {
+                                let message = self.source.params.return_params();
+                                self.source.reset();
+                                return Some(Ok(message));
+                            }
                         } else {
                             return None;
                         }
                     }
+                    ServerState::ClientInfoUserLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::ClientInfoUser);
+                    }
+                    ServerState::ClientInfoUser => {
+                        let user = self.source.get_payload()?;
+                        self.source.params.set_user(user);
+                        self.source.state = Some(ServerState::ClientInfoProofLength);
+                    }
+                    ServerState::ClientInfoProofLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::ClientInfoProof);
+                    }
+                    ServerState::ClientInfoProof => {
+                        let proof = self.source.get_payload()?;
+                        self.source.params.set_payload(proof);
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
                     ServerState::Ping => {
                         self.source.reset();
                         return Some(Ok(Message::Ping));
@@ -283,17 +978,32 @@ impl<'a> Iterator for Iter<'a> {
                         return Some(Ok(Message::Pong));
                     }
                     ServerState::Err => {
-                        if self.source.buffer.len() >= U16_SIZE {
+                        // 第一版没有错误码
+                        let code_len = match self.source.version {
+                            Version::V1 => 0,
+                            Version::V2 => U16_SIZE,
+                        };
+                        if self.source.available() >= code_len + U16_SIZE {
+                            let code = match self.source.version {
+                                Version::V1 => ErrorCode::Unknown(0),
+                                Version::V2 => ErrorCode::from_u16(self.source.buffer.get_u16()),
+                            };
+                            self.source.params = Transition::err(code);
                             self.source.length = self.source.buffer.get_u16() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
                             self.source.state = Some(ServerState::ErrContent);
                         } else {
                             return None;
                         }
                     }
                     ServerState::ErrContent => {
-                        let err_msg = self.source.get_payload()?;
+                        let msg = self.source.get_payload()?;
+                        self.source.params.set_payload(msg);
+                        let message = self.source.params.return_params();
                         self.source.reset();
-                        return Some(Ok(Message::Err(Box::new(Erro { msg: err_msg }))));
+                        return Some(Ok(message));
                     }
                     ServerState::Pub => {
                         self.source.params = Transition::r#pub();
@@ -309,8 +1019,17 @@ impl<'a> Iterator for Iter<'a> {
                         self.source.state = Some(ServerState::PubMsgLength);
                     }
                     ServerState::PubMsgLength => {
-                        if self.source.buffer.len() >= U32_SIZE {
+                        if self.source.available() >= U32_SIZE {
                             self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Some(kind) = self.source.header_error.take() {
+                                let length = self.source.length;
+                                let error = self.source.error(kind);
+                                self.source.skip = length;
+                                return Some(Err(error));
+                            }
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
                             self.source.state = Some(ServerState::PubMsg);
                         } else {
                             return None;
@@ -319,15 +1038,122 @@ impl<'a> Iterator for Iter<'a> {
                     ServerState::PubMsg => {
                         let msg = self.source.get_payload()?;
                         self.source.params.set_pub_msg(msg);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
                         let message = self.source.params.return_params();
                         self.source.reset();
-                        return Some(message);
+                        return Some(Ok(message));
                     }
-                    ServerState::Ack => {
-                        return None;
+                    ServerState::HPub => {
+                        self.source.params = Transition::r#pub();
+                        self.source.state = Some(ServerState::HPubSubNameLength);
+                    }
+                    ServerState::HPubSubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::HPubSubName);
+                    }
+                    ServerState::HPubSubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        self.source.state = Some(ServerState::HPubHeadersLength);
+                    }
+                    ServerState::HPubHeadersLength => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ServerState::HPubHeaders);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ServerState::HPubHeaders => {
+                        let block = self.source.get_payload()?;
+                        if let Err(e) = self.source.check_headers(Headers::decode(&block)) {
+                            return Some(Err(e));
+                        }
+                        // 之后的内容和不带消息头的发布相同
+                        self.source.state = Some(ServerState::PubMsgLength);
                     }
                     ServerState::Offset => {
-                        return None;
+                        let offset = self.source.get_offset()?;
+                        self.source.params = Transition::offset(offset);
+                        self.source.state = Some(ServerState::OffsetSubNameLength);
+                    }
+                    ServerState::OffsetSubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::OffsetSubName);
+                    }
+                    ServerState::OffsetSubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
+                    ServerState::Ack => {
+                        let offset = self.source.get_offset()?;
+                        self.source.params = Transition::ack(offset);
+                        self.source.state = Some(ServerState::AckSubNameLength);
+                    }
+                    ServerState::AckSubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::AckSubName);
+                    }
+                    ServerState::AckSubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
+                    ServerState::Nack => {
+                        if self.source.available() >= U64_SIZE + U32_SIZE {
+                            let offset = self.source.buffer.get_u64();
+                            let delay = Duration::from_millis(self.source.buffer.get_u32() as u64);
+                            self.source.params = Transition::nack(offset, delay);
+                            self.source.state = Some(ServerState::NackSubNameLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ServerState::NackSubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::NackSubName);
+                    }
+                    ServerState::NackSubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        self.source.state = Some(ServerState::NackReasonLength);
+                    }
+                    ServerState::NackReasonLength => {
+                        if self.source.available() >= U16_SIZE {
+                            self.source.length = self.source.buffer.get_u16() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ServerState::NackReason);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ServerState::NackReason => {
+                        let reason = self.source.get_payload()?;
+                        self.source.params.set_payload(reason);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
                     }
                     ServerState::TurnPull => {
                         self.source.reset();
@@ -342,7 +1168,8 @@ impl<'a> Iterator for Iter<'a> {
                         return Some(Ok(Message::Ok));
                     }
                     ServerState::Sub => {
-                        self.source.params = Transition::sub();
+                        let flags = self.source.get_flags()?;
+                        self.source.params = Transition::sub(flags);
                         self.source.state = Some(ServerState::SubNameLength);
                     }
                     ServerState::SubNameLength => {
@@ -352,12 +1179,152 @@ impl<'a> Iterator for Iter<'a> {
                     ServerState::SubName => {
                         let sub_name = self.source.get_payload()?;
                         self.source.params.set_sub_name(sub_name);
+                        if self.source.params.has_queue() {
+                            self.source.state = Some(ServerState::SubQueueLength);
+                            continue;
+                        }
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
+                    ServerState::SubQueueLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::SubQueue);
+                    }
+                    ServerState::SubQueue => {
+                        let queue = self.source.get_payload()?;
+                        self.source.params.set_queue(queue);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
+                    ServerState::Request => {
+                        let id = self.source.get_offset()?;
+                        self.source.params = Transition::request(id);
+                        self.source.state = Some(ServerState::RequestSubNameLength);
+                    }
+                    ServerState::RequestSubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::RequestSubName);
+                    }
+                    ServerState::RequestSubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        self.source.state = Some(ServerState::RequestReplyToLength);
+                    }
+                    ServerState::RequestReplyToLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::RequestReplyTo);
+                    }
+                    ServerState::RequestReplyTo => {
+                        let reply_to = self.source.get_payload()?;
+                        self.source.params.set_reply_to(reply_to);
+                        self.source.state = Some(ServerState::RequestPayloadLength);
+                    }
+                    ServerState::RequestPayloadLength => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ServerState::RequestPayload);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ServerState::RequestPayload => {
+                        let payload = self.source.get_payload()?;
+                        self.source.params.set_payload(payload);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
                         let message = self.source.params.return_params();
                         self.source.reset();
-                        return Some(message);
+                        return Some(Ok(message));
+                    }
+                    ServerState::Reply => {
+                        let id = self.source.get_offset()?;
+                        self.source.params = Transition::reply(id);
+                        self.source.state = Some(ServerState::ReplySubNameLength);
+                    }
+                    ServerState::ReplySubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::ReplySubName);
+                    }
+                    ServerState::ReplySubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        self.source.state = Some(ServerState::ReplyPayloadLength);
+                    }
+                    ServerState::ReplyPayloadLength => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ServerState::ReplyPayload);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ServerState::ReplyPayload => {
+                        let payload = self.source.get_payload()?;
+                        self.source.params.set_payload(payload);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
+                    ServerState::Fetch => {
+                        if self.source.available() >= U32_SIZE * 3 {
+                            let batch = self.source.buffer.get_u32();
+                            let max_bytes = self.source.buffer.get_u32();
+                            let expires =
+                                Duration::from_millis(self.source.buffer.get_u32() as u64);
+                            self.source.params = Transition::fetch(batch, max_bytes, expires);
+                            self.source.state = Some(ServerState::FetchSubNameLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ServerState::FetchSubNameLength => {
+                        self.source.get_and_set_sub_name_length()?;
+                        self.source.state = Some(ServerState::FetchSubName);
+                    }
+                    ServerState::FetchSubName => {
+                        let sub_name = self.source.get_payload()?;
+                        self.source.params.set_sub_name(sub_name);
+                        if let Err(e) = self.source.check_params() {
+                            return Some(Err(e));
+                        }
+                        let message = self.source.params.return_params();
+                        self.source.reset();
+                        return Some(Ok(message));
+                    }
+                    ServerState::Credit => {
+                        if self.source.available() >= U32_SIZE {
+                            let credit = self.source.buffer.get_u32();
+                            self.source.reset();
+                            return Some(Ok(Message::Credit(credit)));
+                        } else {
+                            return None;
+                        }
                     }
                     ServerState::UnSub => {
-                        self.source.params = Transition::unsub();
+                        // 第一版没有回复标志
+                        let reply = match self.source.version {
+                            Version::V1 => false,
+                            Version::V2 => self.source.get_flags()? != 0,
+                        };
+                        self.source.params = Transition::unsub(reply);
                         self.source.state = Some(ServerState::UnSubTotal);
                     }
                     ServerState::UnSubTotal => {
@@ -374,21 +1341,117 @@ impl<'a> Iterator for Iter<'a> {
                         self.source.params.fetch_add_one();
 
                         if self.source.params.is_enough() {
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
                             let unsub = self.source.params.return_params();
                             self.source.reset();
-                            return Some(unsub);
+                            return Some(Ok(unsub));
                         } else {
                             self.source.state = Some(ServerState::UnSubNameLength);
                         }
                     }
                 }
+            } else if !self.source.buffer.has_remaining() {
+                return None;
             } else {
+                self.source.frame_start = self.source.consumed();
+                let byte = self.source.buffer.get_u8();
+                match byte.try_into() {
+                    Ok(state) => self.source.state = Some(state),
+                    Err(_) => {
+                        let kind = if is_frame_type(byte) {
+                            ErrorKind::UnexpectedFrame(byte)
+                        } else {
+                            ErrorKind::UnknownFrameType(byte)
+                        };
+                        return Some(Err(self.source.error(kind)));
+                    }
+                }
+            }
+        }
+    }
+
+    // 第二版的帧格式, 帧体仍然按照第一版的格式解析
+    fn next_v2(&mut self) -> Option<Result<Message, Error>> {
+        loop {
+            if self.source.state.is_none() {
+                if !self.source.discard() || self.source.buffer.len() < ENVELOPE_SIZE {
+                    return None;
+                }
+
+                self.source.frame_start = self.source.consumed();
                 let byte = self.source.buffer.get_u8();
+                let flags = self.source.buffer.get_u8();
+                let length = self.source.buffer.get_u32() as usize;
+                self.source.frame_end = Some(self.source.consumed() + length as u64);
+
+                if flags != 0 {
+                    return Some(Err(self.source.error(ErrorKind::UnsupportedFlags(flags))));
+                }
+
                 match byte.try_into() {
                     Ok(state) => self.source.state = Some(state),
-                    Err(_) => return Some(Err(Error::Parse)),
+                    Err(_) if is_frame_type(byte) => {
+                        return Some(Err(self.source.error(ErrorKind::UnexpectedFrame(byte))));
+                    }
+                    // 更新的版本中才有的帧类型, 直接跳过
+                    Err(_) => {
+                        self.source.skip = length;
+                        self.source.frame_end = None;
+                        continue;
+                    }
                 }
             }
+
+            // 帧体只能读到声明的长度为止, 不会读到下一帧
+            let message = match self.next_v1() {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.source.check_body_end().map(Err),
+            };
+
+            if let Some(end) = self.source.frame_end.take() {
+                // 忽略更新的版本在帧体后面追加的字段
+                self.source.skip = (end - self.source.consumed()) as usize;
+            }
+            return Some(Ok(message));
+        }
+    }
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if let Some(error) = &self.source.poisoned {
+            self.done = true;
+            return Some(Err(error.clone()));
+        }
+
+        self.source.mark = self.source.buffer.len();
+        let item = if self.source.discard() {
+            match self.source.version {
+                Version::V1 => self.next_v1(),
+                Version::V2 => self.next_v2(),
+            }
+        } else {
+            None
+        };
+        self.source.position = self.source.consumed();
+
+        if let Some(Err(error)) = &item {
+            self.done = true;
+            if self.source.policy == ErrorPolicy::Poison {
+                self.source.poisoned = Some(error.clone());
+            }
         }
+        item
     }
 }
+
+impl<'a> FusedIterator for Iter<'a> {}
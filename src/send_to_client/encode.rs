@@ -1,24 +1,40 @@
+use super::decode::Pub;
+use crate::common::{encode, truncate_str, Frame, U16_SIZE, U32_SIZE, U64_SIZE, U8_SIZE};
+use crate::error::ErrorCode;
+use crate::headers::Headers;
 use crate::state::{
-    Support, STATE_ERR, STATE_MSG, STATE_OK, STATE_PING, STATE_PONG, STATE_SERVER_INFO,
+    FetchStatus, Support, STATE_ACK, STATE_ERR, STATE_FETCH_DONE, STATE_HMSG, STATE_MSG,
+    STATE_OFFSET, STATE_OK, STATE_PING, STATE_PONG, STATE_REPLY, STATE_REQUEST, STATE_SERVER_INFO,
+    STATE_SUB_ACK,
 };
-use bytes::{BufMut, BytesMut};
+use crate::version::Version;
+use bytes::buf::ext::{BufExt, Chain};
+use bytes::{BufMut, Bytes, BytesMut};
 
 use std::default::Default;
-use std::u32::MAX as u32_MAX;
 
 #[derive(Debug)]
 pub struct ServerConfig {
     version: u8,
+
+    // 本地可以接受的最低版本, 不在握手中发送
+    min_version: u8,
     support: u16,
     max_message_length: u32,
+
+    // 开启认证时发给客户端的随机数, 每个连接不同
+    nonce: Bytes,
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self {
-            version: 1,
+            // 第二版需要调用 set_version 主动开启
+            version: Version::V1.as_u8(),
+            min_version: Version::V1.as_u8(),
             support: 0,
-            max_message_length: u32_MAX,
+            max_message_length: u32::MAX,
+            nonce: Bytes::new(),
         }
     }
 }
@@ -28,6 +44,10 @@ impl ServerConfig {
         self.version = version;
     }
 
+    pub fn set_min_version(&mut self, min_version: u8) {
+        self.min_version = min_version;
+    }
+
     pub fn support_push(&mut self) {
         self.support |= Support::Push;
     }
@@ -44,19 +64,66 @@ impl ServerConfig {
         self.support |= Support::Compress;
     }
 
+    pub fn support_auth(&mut self) {
+        self.support |= Support::Auth;
+    }
+
     pub fn max_message_length(&mut self, max_message_length: u32) {
         self.max_message_length = max_message_length;
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::with_capacity(9);
+    // 服务器连接会自动生成, 一般不需要手动设置
+    pub fn set_nonce(&mut self, nonce: Bytes) {
+        self.nonce = nonce;
+    }
+
+    pub fn get_version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn get_min_version(&self) -> u8 {
+        self.min_version
+    }
 
-        buff.put_u8(STATE_SERVER_INFO);
+    pub fn get_support(&self) -> u16 {
+        self.support
+    }
+
+    pub fn get_max_message_length(&self) -> u32 {
+        self.max_message_length
+    }
+
+    pub fn get_nonce(&self) -> &Bytes {
+        &self.nonce
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl Frame for ServerConfig {
+    fn kind(&self) -> u8 {
+        STATE_SERVER_INFO
+    }
+
+    fn body_len(&self) -> usize {
+        let len = U8_SIZE + U16_SIZE + U32_SIZE;
+        if self.support & Support::Auth {
+            len + U8_SIZE + self.nonce.len()
+        } else {
+            len
+        }
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
         buff.put_u8(self.version);
         buff.put_u16(self.support);
         buff.put_u32(self.max_message_length);
-
-        buff
+        if self.support & Support::Auth {
+            buff.put_u8(self.nonce.len() as u8);
+            buff.put_slice(&self.nonce);
+        }
     }
 }
 
@@ -87,23 +154,70 @@ impl Ok {
     }
 }
 
+// 没有帧体的帧
 #[derive(Debug)]
-pub struct Err {
-    msg: &'static str,
+struct Bare(u8);
+
+impl Frame for Bare {
+    fn kind(&self) -> u8 {
+        self.0
+    }
+
+    fn body_len(&self) -> usize {
+        0
+    }
+
+    fn encode_body(&self, _buff: &mut BytesMut) {}
+}
+
+#[derive(Debug)]
+pub struct Err<'a> {
+    code: ErrorCode,
+    msg: &'a str,
+}
+
+impl<'a> Err<'a> {
+    // 默认不带文字
+    pub fn new(code: ErrorCode) -> Self {
+        Self { code, msg: "" }
+    }
+
+    // 说明最长 u16::MAX 字节, 超出的部分被截掉
+    pub fn with_msg(mut self, msg: &'a str) -> Self {
+        self.msg = truncate_str(msg, u16::MAX as usize);
+        self
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
 }
 
-impl Err {
-    pub fn new(msg: &'static str) -> Self {
-        debug_assert!(msg.len() < (std::u16::MAX as usize));
-        Self { msg }
+impl<'a> Frame for Err<'a> {
+    fn kind(&self) -> u8 {
+        STATE_ERR
+    }
+
+    fn body_len(&self) -> usize {
+        self.body_len_with(Version::V1)
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        self.encode_body_with(Version::V1, buff);
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::with_capacity(self.msg.len() + 2 + 1);
-        buff.put_u8(STATE_ERR);
+    fn body_len_with(&self, version: Version) -> usize {
+        let code_len = if version == Version::V2 { U16_SIZE } else { 0 };
+        code_len + U16_SIZE + self.msg.len()
+    }
+
+    // 第一版没有错误码
+    fn encode_body_with(&self, version: Version, buff: &mut BytesMut) {
+        if version == Version::V2 {
+            buff.put_u16(self.code.as_u16());
+        }
         buff.put_u16(self.msg.len() as u16);
         buff.extend_from_slice(self.msg.as_bytes());
-        buff
     }
 }
 
@@ -112,6 +226,8 @@ pub struct Msg<'a> {
     sub_name: &'a [u8],
     msg: &'a [u8],
     offset: u64,
+    sid: u32,
+    headers: Option<&'a Headers>,
 }
 
 impl<'a> Msg<'a> {
@@ -120,19 +236,374 @@ impl<'a> Msg<'a> {
             sub_name,
             offset,
             msg,
+            sid: 0,
+            headers: None,
         }
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::with_capacity(self.msg.len() + self.sub_name.len() + 14);
+    // 订阅号, 0表示没有分配, 只在第二版中发送
+    pub fn with_sid(mut self, sid: u32) -> Self {
+        self.sid = sid;
+        self
+    }
 
-        buff.put_u8(STATE_MSG);
+    // 直接转发客户端发布的消息, 借用解析出来的名称和内容, 不需要拷贝
+    pub fn from_pub(offset: u64, r#pub: &'a Pub) -> Self {
+        Self::new(offset, &r#pub.name, &r#pub.msg).with_headers(&r#pub.headers)
+    }
+
+    // 消息头为空时仍然编码成不带消息头的帧
+    pub fn with_headers(mut self, headers: &'a Headers) -> Self {
+        self.headers = Some(headers).filter(|headers| !headers.is_empty());
+        self
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+
+    // 消息内容不拷贝, 帧头和内容可以一起交给 write_vectored
+    pub fn encode_chain(&self) -> Chain<Bytes, &'a [u8]> {
+        self.encode_chain_with(Version::V1)
+    }
+
+    pub fn encode_chain_with(&self, version: Version) -> Chain<Bytes, &'a [u8]> {
+        let mut head = BytesMut::with_capacity(self.encoded_len_with(version) - self.msg.len());
+        self.encode_header(version, &mut head);
+        self.encode_head(version, &mut head);
+        head.freeze().chain(self.msg)
+    }
+
+    // 消息内容之前的部分
+    fn encode_head(&self, version: Version, buff: &mut BytesMut) {
         buff.put_u64(self.offset);
+        if version == Version::V2 {
+            buff.put_u32(self.sid);
+        }
         buff.put_u8(self.sub_name.len() as u8);
-        buff.extend_from_slice(&self.sub_name);
+        buff.extend_from_slice(self.sub_name);
+        if let Some(headers) = self.headers {
+            buff.put_u32(headers.encoded_len() as u32);
+            headers.encode_into(buff);
+        }
         buff.put_u32(self.msg.len() as u32);
-        buff.extend_from_slice(&self.msg);
+    }
+}
+
+impl<'a> Frame for Msg<'a> {
+    fn kind(&self) -> u8 {
+        if self.headers.is_some() {
+            STATE_HMSG
+        } else {
+            STATE_MSG
+        }
+    }
+
+    fn body_len(&self) -> usize {
+        self.body_len_with(Version::V1)
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        self.encode_body_with(Version::V1, buff);
+    }
+
+    fn body_len_with(&self, version: Version) -> usize {
+        let sid_len = if version == Version::V2 { U32_SIZE } else { 0 };
+        let headers_len = self
+            .headers
+            .map_or(0, |headers| U32_SIZE + headers.encoded_len());
+        U64_SIZE + sid_len + U8_SIZE + self.sub_name.len() + headers_len + U32_SIZE + self.msg.len()
+    }
+
+    fn encode_body_with(&self, version: Version, buff: &mut BytesMut) {
+        self.encode_head(version, buff);
+        buff.extend_from_slice(self.msg);
+    }
+}
+
+#[derive(Debug)]
+pub struct Offset<'a> {
+    offset: u64,
+    sub_name: &'a [u8],
+}
+
+impl<'a> Offset<'a> {
+    pub fn new(offset: u64, sub_name: &'a [u8]) -> Self {
+        Self { offset, sub_name }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Offset<'a> {
+    fn kind(&self) -> u8 {
+        STATE_OFFSET
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.offset);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name);
+    }
+}
+
+#[derive(Debug)]
+pub struct Ack<'a> {
+    offset: u64,
+    sub_name: &'a [u8],
+}
+
+impl<'a> Ack<'a> {
+    pub fn new(offset: u64, sub_name: &'a [u8]) -> Self {
+        Self { offset, sub_name }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Ack<'a> {
+    fn kind(&self) -> u8 {
+        STATE_ACK
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.offset);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name);
+    }
+}
+
+#[derive(Debug)]
+pub struct Request<'a> {
+    id: u64,
+    sub_name: &'a [u8],
+    reply_to: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    pub fn new(id: u64, sub_name: &'a [u8], reply_to: &'a [u8], payload: &'a [u8]) -> Self {
+        Self {
+            id,
+            sub_name,
+            reply_to,
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Request<'a> {
+    fn kind(&self) -> u8 {
+        STATE_REQUEST
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE
+            + U8_SIZE
+            + self.sub_name.len()
+            + U8_SIZE
+            + self.reply_to.len()
+            + U32_SIZE
+            + self.payload.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.id);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name);
+        buff.put_u8(self.reply_to.len() as u8);
+        buff.extend_from_slice(self.reply_to);
+        buff.put_u32(self.payload.len() as u32);
+        buff.extend_from_slice(self.payload);
+    }
+}
+
+#[derive(Debug)]
+pub struct Reply<'a> {
+    id: u64,
+    sub_name: &'a [u8],
+    payload: &'a [u8],
+}
+
+impl<'a> Reply<'a> {
+    pub fn new(id: u64, sub_name: &'a [u8], payload: &'a [u8]) -> Self {
+        Self {
+            id,
+            sub_name,
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Reply<'a> {
+    fn kind(&self) -> u8 {
+        STATE_REPLY
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U8_SIZE + self.sub_name.len() + U32_SIZE + self.payload.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.id);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name);
+        buff.put_u32(self.payload.len() as u32);
+        buff.extend_from_slice(self.payload);
+    }
+}
+
+#[derive(Debug)]
+pub struct SubAck<'a> {
+    sid: u32,
+    sub_name: &'a [u8],
+}
+
+impl<'a> SubAck<'a> {
+    pub fn new(sid: u32, sub_name: &'a [u8]) -> Self {
+        Self { sid, sub_name }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for SubAck<'a> {
+    fn kind(&self) -> u8 {
+        STATE_SUB_ACK
+    }
+
+    fn body_len(&self) -> usize {
+        U32_SIZE + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u32(self.sid);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name);
+    }
+}
+
+#[derive(Debug)]
+pub struct FetchDone<'a> {
+    sub_name: &'a [u8],
+    status: FetchStatus,
+    count: u32,
+}
+
+impl<'a> FetchDone<'a> {
+    pub fn new(sub_name: &'a [u8], status: FetchStatus, count: u32) -> Self {
+        Self {
+            sub_name,
+            status,
+            count,
+        }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for FetchDone<'a> {
+    fn kind(&self) -> u8 {
+        STATE_FETCH_DONE
+    }
+
+    fn body_len(&self) -> usize {
+        U8_SIZE + U32_SIZE + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u8(self.status.as_u8());
+        buff.put_u32(self.count);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name);
+    }
+}
+
+// 服务器可以发送的所有帧
+#[derive(Debug)]
+pub enum ServerFrame<'a> {
+    Info(&'a ServerConfig),
+    Ping,
+    Pong,
+    Ok,
+    Err(Err<'a>),
+    Msg(Msg<'a>),
+    Offset(Offset<'a>),
+    Ack(Ack<'a>),
+    Request(Request<'a>),
+    Reply(Reply<'a>),
+    SubAck(SubAck<'a>),
+    FetchDone(FetchDone<'a>),
+}
+
+impl<'a> ServerFrame<'a> {
+    fn frame(&self) -> &dyn Frame {
+        match self {
+            ServerFrame::Info(config) => *config,
+            ServerFrame::Ping => &Bare(STATE_PING),
+            ServerFrame::Pong => &Bare(STATE_PONG),
+            ServerFrame::Ok => &Bare(STATE_OK),
+            ServerFrame::Err(err) => err,
+            ServerFrame::Msg(msg) => msg,
+            ServerFrame::Offset(offset) => offset,
+            ServerFrame::Ack(ack) => ack,
+            ServerFrame::Request(request) => request,
+            ServerFrame::Reply(reply) => reply,
+            ServerFrame::SubAck(sub_ack) => sub_ack,
+            ServerFrame::FetchDone(fetch_done) => fetch_done,
+        }
+    }
+
+    // 编码后的总长度, 包括类型
+    pub fn encoded_len(&self) -> usize {
+        self.frame().encoded_len()
+    }
+
+    // 追加到已有的缓冲后面
+    pub fn encode_into(&self, buff: &mut BytesMut) {
+        self.frame().encode_into(buff);
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self.frame())
+    }
+
+    // 按照协商出来的版本编码
+    pub fn encoded_len_with(&self, version: Version) -> usize {
+        self.frame().encoded_len_with(version)
+    }
+
+    pub fn encode_into_with(&self, version: Version, buff: &mut BytesMut) {
+        self.frame().encode_into_with(version, buff);
+    }
 
+    pub fn encode_with(&self, version: Version) -> BytesMut {
+        let mut buff = BytesMut::with_capacity(self.encoded_len_with(version));
+        self.encode_into_with(version, &mut buff);
         buff
     }
 }
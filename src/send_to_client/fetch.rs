@@ -0,0 +1,147 @@
+use super::decode::Fetch;
+use crate::clock::{Clock, SystemClock};
+use crate::state::FetchStatus;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::Instant;
+
+// 拉取结束时回复给客户端的内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Done {
+    pub sub_name: Bytes,
+    pub status: FetchStatus,
+    pub count: u32,
+}
+
+#[derive(Debug)]
+struct Pull {
+    batch: u32,
+    max_bytes: u32,
+    count: u32,
+    bytes: u64,
+    deadline: Instant,
+}
+
+impl Pull {
+    fn is_full(&self) -> bool {
+        self.count >= self.batch || (self.max_bytes != 0 && self.bytes >= self.max_bytes as u64)
+    }
+
+    fn done(self, sub_name: Bytes) -> Done {
+        let status = if self.count == 0 {
+            FetchStatus::NoMessages
+        } else {
+            FetchStatus::BatchComplete
+        };
+        Done {
+            sub_name,
+            status,
+            count: self.count,
+        }
+    }
+}
+
+// 服务器端一个连接上未完成的拉取, 每个订阅名称同时只有一个
+// 数量或者字节数达到上限时结束, 否则等到超时, 用来实现长轮询
+#[derive(Debug)]
+pub struct Fetches<C = SystemClock> {
+    clock: C,
+    pulls: HashMap<Bytes, Pull>,
+}
+
+impl Default for Fetches<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Fetches<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<C> Fetches<C>
+where
+    C: Clock,
+{
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            pulls: HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pulls.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pulls.is_empty()
+    }
+
+    pub fn is_pending(&self, sub_name: &[u8]) -> bool {
+        self.pulls.contains_key(sub_name)
+    }
+
+    // 同名的拉取还没结束时由新的代替, 返回需要结束的旧拉取
+    pub fn start(&mut self, fetch: &Fetch) -> Option<Done> {
+        let pull = Pull {
+            batch: fetch.batch,
+            max_bytes: fetch.max_bytes,
+            count: 0,
+            bytes: 0,
+            deadline: self.clock.now() + fetch.expires,
+        };
+        self.pulls
+            .insert(fetch.sub_name.clone(), pull)
+            .map(|old| old.done(fetch.sub_name.clone()))
+    }
+
+    // 这条消息能不能放进未完成的拉取, 第一条消息不受字节数限制
+    pub fn accepts(&self, sub_name: &[u8], len: usize) -> bool {
+        match self.pulls.get(sub_name) {
+            Some(pull) => {
+                pull.max_bytes == 0
+                    || pull.count == 0
+                    || pull.bytes + len as u64 <= pull.max_bytes as u64
+            }
+            None => false,
+        }
+    }
+
+    // 发出一条消息之后调用, 拉取满了时返回结束的内容
+    pub fn deliver(&mut self, sub_name: &[u8], len: usize) -> Option<Done> {
+        let pull = self.pulls.get_mut(sub_name)?;
+        pull.count += 1;
+        pull.bytes += len as u64;
+        if pull.is_full() {
+            self.finish(sub_name)
+        } else {
+            None
+        }
+    }
+
+    // 提前结束, 比如已经没有可以发出的消息
+    pub fn finish(&mut self, sub_name: &[u8]) -> Option<Done> {
+        let (sub_name, pull) = self.pulls.remove_entry(sub_name)?;
+        Some(pull.done(sub_name))
+    }
+
+    // 最早超时的时间, 没有未完成的拉取时为 None
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pulls.values().map(|pull| pull.deadline).min()
+    }
+
+    // 每次返回一个已经超时的拉取
+    pub fn poll_timeout(&mut self) -> Option<Done> {
+        let now = self.clock.now();
+        let sub_name = self
+            .pulls
+            .iter()
+            .filter(|(_, pull)| pull.deadline <= now)
+            .min_by_key(|(_, pull)| pull.deadline)
+            .map(|(sub_name, _)| sub_name.clone())?;
+        self.finish(&sub_name)
+    }
+}
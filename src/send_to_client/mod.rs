@@ -1,2 +1,6 @@
+pub mod connection;
 pub mod decode;
 pub mod encode;
+pub mod fetch;
+pub mod redelivery;
+pub mod router;
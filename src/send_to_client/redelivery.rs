@@ -0,0 +1,260 @@
+use crate::clock::{Clock, SystemClock};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const DEFAULT_ACK_WAIT: Duration = Duration::from_secs(30);
+const DEFAULT_BACKOFF: Duration = Duration::from_secs(1);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+const DEFAULT_MAX_DELIVER: u32 = 5;
+const DEFAULT_DEAD_LETTER: &[u8] = b"dead_letter";
+
+// 需要重新投递或者转到死信的消息
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delivery {
+    pub offset: u64,
+    pub sub_name: Bytes,
+    pub payload: Bytes,
+
+    // 之前已经投递的次数
+    pub deliveries: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    // 按照原来的消息号再发一次
+    Redeliver(Delivery),
+
+    // 投递次数用完, 发到死信的订阅名称
+    DeadLetter { subject: Bytes, delivery: Delivery },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Timer {
+    // 等待应答
+    AckWait,
+
+    // 等待重新投递
+    Retry,
+
+    // 等待转到死信
+    Dead,
+}
+
+#[derive(Debug)]
+struct Entry {
+    sub_name: Bytes,
+    payload: Bytes,
+    deliveries: u32,
+    timer: Timer,
+    deadline: Instant,
+}
+
+impl Entry {
+    fn delivery(self, offset: u64) -> Delivery {
+        Delivery {
+            offset,
+            sub_name: self.sub_name,
+            payload: self.payload,
+            deliveries: self.deliveries,
+        }
+    }
+}
+
+// 服务器端已经发出还没有应答的消息, 按消息号记录
+// 超过应答等待时间或者被拒绝之后按指数退避重新投递, 次数用完之后转到死信
+#[derive(Debug)]
+pub struct Redelivery<C = SystemClock> {
+    clock: C,
+    entries: HashMap<u64, Entry>,
+    ack_wait: Duration,
+    backoff: Duration,
+    max_backoff: Duration,
+    max_deliver: u32,
+    dead_letter: Bytes,
+}
+
+impl Default for Redelivery<SystemClock> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Redelivery<SystemClock> {
+    pub fn new() -> Self {
+        Self::with_clock(SystemClock)
+    }
+}
+
+impl<C> Redelivery<C>
+where
+    C: Clock,
+{
+    pub fn with_clock(clock: C) -> Self {
+        Self {
+            clock,
+            entries: HashMap::new(),
+            ack_wait: DEFAULT_ACK_WAIT,
+            backoff: DEFAULT_BACKOFF,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            max_deliver: DEFAULT_MAX_DELIVER,
+            dead_letter: Bytes::from_static(DEFAULT_DEAD_LETTER),
+        }
+    }
+
+    pub fn with_ack_wait(mut self, ack_wait: Duration) -> Self {
+        self.ack_wait = ack_wait;
+        self
+    }
+
+    // 第 n 次失败之后等待 backoff * 2^(n-1), 不超过 max_backoff
+    pub fn with_backoff(mut self, backoff: Duration, max_backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    // 0 表示不限制投递次数
+    pub fn with_max_deliver(mut self, max_deliver: u32) -> Self {
+        self.max_deliver = max_deliver;
+        self
+    }
+
+    pub fn with_dead_letter(mut self, subject: &[u8]) -> Self {
+        self.dead_letter = Bytes::copy_from_slice(subject);
+        self
+    }
+
+    pub fn get_ack_wait(&self) -> Duration {
+        self.ack_wait
+    }
+
+    pub fn get_max_deliver(&self) -> u32 {
+        self.max_deliver
+    }
+
+    pub fn get_dead_letter(&self) -> &Bytes {
+        &self.dead_letter
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_tracked(&self, offset: u64) -> bool {
+        self.entries.contains_key(&offset)
+    }
+
+    // 第一次发出消息之后调用
+    pub fn track(&mut self, offset: u64, sub_name: &[u8], payload: Bytes) {
+        let entry = Entry {
+            sub_name: Bytes::copy_from_slice(sub_name),
+            payload,
+            deliveries: 1,
+            timer: Timer::AckWait,
+            deadline: self.clock.now() + self.ack_wait,
+        };
+        self.entries.insert(offset, entry);
+    }
+
+    // 收到应答, 不再重新投递
+    pub fn ack(&mut self, offset: u64) -> bool {
+        self.entries.remove(&offset).is_some()
+    }
+
+    // 收到拒绝, delay 为 0 时按照退避时间重新投递, 已经在等待重新投递的消息不受影响
+    pub fn nack(&mut self, offset: u64, delay: Duration) -> bool {
+        let now = self.clock.now();
+        let (backoff, max_deliver) = (self.backoff_for(offset), self.max_deliver);
+        match self.entries.get_mut(&offset) {
+            Some(entry) if entry.timer == Timer::AckWait => {
+                let delay = if delay == Duration::from_secs(0) {
+                    backoff
+                } else {
+                    delay
+                };
+                fail(entry, now, delay, max_deliver);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // 取消订阅之后不再重新投递这个名称下的消息
+    pub fn remove_sub(&mut self, sub_name: &[u8]) {
+        self.entries.retain(|_, entry| entry.sub_name != sub_name);
+    }
+
+    // 最早需要处理的时间, 没有记录时为 None
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.entries.values().map(|entry| entry.deadline).min()
+    }
+
+    // 每次返回一个需要处理的消息, 重新投递之后重新开始等待应答
+    pub fn poll(&mut self) -> Option<Action> {
+        let now = self.clock.now();
+        loop {
+            let offset = self
+                .entries
+                .iter()
+                .filter(|(_, entry)| entry.deadline <= now)
+                .min_by_key(|(_, entry)| entry.deadline)
+                .map(|(offset, _)| *offset)?;
+
+            let (backoff, max_deliver) = (self.backoff_for(offset), self.max_deliver);
+            let entry = self.entries.get_mut(&offset)?;
+            match entry.timer {
+                // 等待应答超时算一次失败, 从超时的时间开始退避
+                Timer::AckWait => {
+                    let deadline = entry.deadline;
+                    fail(entry, deadline, backoff, max_deliver);
+                }
+                Timer::Retry => {
+                    let delivery = Delivery {
+                        offset,
+                        sub_name: entry.sub_name.clone(),
+                        payload: entry.payload.clone(),
+                        deliveries: entry.deliveries,
+                    };
+                    entry.deliveries += 1;
+                    entry.timer = Timer::AckWait;
+                    entry.deadline = now + self.ack_wait;
+                    return Some(Action::Redeliver(delivery));
+                }
+                Timer::Dead => {
+                    let entry = self.entries.remove(&offset)?;
+                    return Some(Action::DeadLetter {
+                        subject: self.dead_letter.clone(),
+                        delivery: entry.delivery(offset),
+                    });
+                }
+            }
+        }
+    }
+
+    fn backoff_for(&self, offset: u64) -> Duration {
+        let failures = self
+            .entries
+            .get(&offset)
+            .map_or(1, |entry| entry.deliveries.max(1));
+        let shift = (failures - 1).min(31);
+        self.backoff
+            .checked_mul(1 << shift)
+            .map_or(self.max_backoff, |backoff| backoff.min(self.max_backoff))
+    }
+}
+
+// 一次投递失败, 次数用完时立即转到死信
+fn fail(entry: &mut Entry, now: Instant, delay: Duration, max_deliver: u32) {
+    if max_deliver != 0 && entry.deliveries >= max_deliver {
+        entry.timer = Timer::Dead;
+        entry.deadline = now;
+    } else {
+        entry.timer = Timer::Retry;
+        entry.deadline = now + delay;
+    }
+}
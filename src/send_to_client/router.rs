@@ -0,0 +1,204 @@
+use bytes::Bytes;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{BuildHasher, Hash, Hasher};
+
+// 队列组内选择成员的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Policy {
+    #[default]
+    RoundRobin,
+    Random,
+    // 选择未确认消息最少的成员, 相同时轮流选择
+    LeastPending,
+}
+
+#[derive(Debug)]
+struct Group<K> {
+    members: Vec<K>,
+    next: usize,
+}
+
+#[derive(Debug)]
+struct Subject<K> {
+    // 没有队列组的订阅者, 每条消息都会收到
+    members: Vec<K>,
+    groups: BTreeMap<Bytes, Group<K>>,
+}
+
+impl<K> Subject<K> {
+    fn is_empty(&self) -> bool {
+        self.members.is_empty() && self.groups.is_empty()
+    }
+}
+
+// 服务器端的订阅路由, K 为连接的标识
+// 每条消息发给所有普通订阅者, 每个队列组只选一个成员
+#[derive(Debug)]
+pub struct Router<K> {
+    policy: Policy,
+    subjects: HashMap<Bytes, Subject<K>>,
+    pending: HashMap<K, usize>,
+    seed: u64,
+}
+
+impl<K> Router<K>
+where
+    K: Copy + Eq + Hash,
+{
+    pub fn new(policy: Policy) -> Self {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(0);
+        Self {
+            policy,
+            subjects: HashMap::new(),
+            pending: HashMap::new(),
+            // xorshift 的状态不能为0
+            seed: hasher.finish() | 1,
+        }
+    }
+
+    // 固定随机数种子, 方便测试
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed | 1;
+        self
+    }
+
+    pub fn get_policy(&self) -> Policy {
+        self.policy
+    }
+
+    // 重复订阅返回 false
+    pub fn subscribe(&mut self, member: K, sub_name: &[u8], queue: Option<&[u8]>) -> bool {
+        let subject = self
+            .subjects
+            .entry(Bytes::copy_from_slice(sub_name))
+            .or_insert_with(|| Subject {
+                members: Vec::new(),
+                groups: BTreeMap::new(),
+            });
+        let members = match queue {
+            Some(queue) => {
+                &mut subject
+                    .groups
+                    .entry(Bytes::copy_from_slice(queue))
+                    .or_insert_with(|| Group {
+                        members: Vec::new(),
+                        next: 0,
+                    })
+                    .members
+            }
+            None => &mut subject.members,
+        };
+        if members.contains(&member) {
+            return false;
+        }
+        members.push(member);
+        true
+    }
+
+    // 取消这个连接在这个名称上的所有订阅, 包括队列组
+    pub fn unsubscribe(&mut self, member: K, sub_name: &[u8]) -> bool {
+        let subject = match self.subjects.get_mut(sub_name) {
+            Some(subject) => subject,
+            None => return false,
+        };
+        let removed = unsubscribe(subject, member);
+        if subject.is_empty() {
+            self.subjects.remove(sub_name);
+        }
+        removed
+    }
+
+    // 连接断开时调用
+    pub fn remove(&mut self, member: K) {
+        self.subjects.retain(|_, subject| {
+            unsubscribe(subject, member);
+            !subject.is_empty()
+        });
+        self.pending.remove(&member);
+    }
+
+    pub fn is_subscribed(&self, sub_name: &[u8]) -> bool {
+        self.subjects.contains_key(sub_name)
+    }
+
+    // 选出这条消息的接收者, 被选中的成员未确认数量加1
+    pub fn route(&mut self, sub_name: &[u8]) -> Vec<K> {
+        let Self {
+            policy,
+            subjects,
+            pending,
+            seed,
+        } = self;
+        let subject = match subjects.get_mut(sub_name) {
+            Some(subject) => subject,
+            None => return Vec::new(),
+        };
+
+        let mut targets = subject.members.clone();
+        for group in subject.groups.values_mut() {
+            let len = group.members.len();
+            let index = match policy {
+                Policy::RoundRobin => group.next % len,
+                Policy::Random => (xorshift(seed) % len as u64) as usize,
+                Policy::LeastPending => (0..len)
+                    .map(|i| (group.next + i) % len)
+                    .min_by_key(|&i| pending.get(&group.members[i]).copied().unwrap_or(0))
+                    .unwrap(),
+            };
+            group.next = index + 1;
+            targets.push(group.members[index]);
+        }
+
+        targets
+            .iter()
+            .for_each(|member| *pending.entry(*member).or_insert(0) += 1);
+        targets
+    }
+
+    // 收到应答后调用
+    pub fn ack(&mut self, member: K) {
+        if let Some(count) = self.pending.get_mut(&member) {
+            *count = count.saturating_sub(1);
+        }
+    }
+
+    pub fn pending(&self, member: K) -> usize {
+        self.pending.get(&member).copied().unwrap_or(0)
+    }
+}
+
+fn unsubscribe<K>(subject: &mut Subject<K>, member: K) -> bool
+where
+    K: Copy + Eq,
+{
+    let mut removed = remove_member(&mut subject.members, member);
+    subject.groups.retain(|_, group| {
+        removed |= remove_member(&mut group.members, member);
+        !group.members.is_empty()
+    });
+    removed
+}
+
+fn remove_member<K>(members: &mut Vec<K>, member: K) -> bool
+where
+    K: Eq,
+{
+    match members.iter().position(|m| *m == member) {
+        Some(index) => {
+            members.remove(index);
+            true
+        }
+        None => false,
+    }
+}
+
+fn xorshift(state: &mut u64) -> u64 {
+    let mut x = *state;
+    x ^= x << 13;
+    x ^= x >> 7;
+    x ^= x << 17;
+    *state = x;
+    x
+}
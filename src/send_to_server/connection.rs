@@ -0,0 +1,555 @@
+use super::decode::{
+    Ack, Decode, Erro, Error as DecodeError, ErrorCode, FetchDone, Info, Message, Msg, Offset,
+    Reply, Request, SubAck,
+};
+use super::encode::{self, ClientConfig, ClientFrame, Credit, Fetch, Pub, Sub, UnSub};
+use crate::capabilities::{Capabilities, NegotiateError};
+use crate::clock::{Clock, SystemClock};
+use crate::headers::Headers;
+use crate::mode::{DeliveryMode, ModeError};
+use crate::state::{Mode, Phase};
+use crate::version::{Version, VersionError};
+use bytes::{Bytes, BytesMut};
+use std::collections::{HashMap, VecDeque};
+use std::convert::AsRef;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+
+    #[error(transparent)]
+    Negotiate(#[from] NegotiateError),
+
+    #[error(transparent)]
+    Version(#[from] VersionError),
+
+    #[error("unexpected {frame} frame while {phase:?}")]
+    UnexpectedFrame { frame: &'static str, phase: Phase },
+
+    #[error("connection is not established")]
+    NotConnected,
+
+    #[error("a mode switch is already waiting for ok")]
+    SwitchPending,
+
+    #[error("{0:?} mode was not negotiated")]
+    ModeNotAllowed(Mode),
+
+    #[error("server did not answer the switch to {0:?} mode in time")]
+    SwitchTimeout(Mode),
+
+    #[error("fetch requires pull mode")]
+    NotPullMode,
+
+    #[error("unsubscribe reply requires version 2")]
+    UnSubReplyNotSupported,
+}
+
+#[derive(Debug)]
+pub enum Event {
+    Connected(Box<Info>),
+    Msg(Box<Msg>),
+    Offset(Box<Offset>),
+    Ack(Box<Ack>),
+    Request(Box<Request>),
+    Reply(Box<Reply>),
+
+    // 服务器确认了订阅
+    Subscribed(Box<SubAck>),
+
+    // 服务器确认了取消订阅
+    Unsubscribed,
+
+    // 一次拉取结束
+    FetchDone(Box<FetchDone>),
+    Err(Box<Erro>),
+    ModeChanged(Mode),
+    Pong,
+}
+
+// 等待服务器回复 ok, err 或者订阅确认的请求, 服务器按照收到的顺序回复
+#[derive(Debug)]
+enum Pending {
+    Sub(Bytes),
+    UnSub,
+    Turn(Mode),
+}
+
+impl Pending {
+    // 只有对应的错误码才是在拒绝这个请求
+    fn is_rejected_by(&self, code: ErrorCode) -> bool {
+        match self {
+            Pending::Sub(_) => code == ErrorCode::AlreadySubscribed,
+            Pending::UnSub => code == ErrorCode::NotSubscribed,
+            Pending::Turn(_) => code == ErrorCode::ModeNotSupported,
+        }
+    }
+}
+
+// 客户端连接状态机, 不做任何io
+// 调用者把收到的字节交给 receive, 从 poll_event 取事件, 从 poll_transmit 取要发送的字节
+#[derive(Debug)]
+pub struct ClientConnection<C = SystemClock> {
+    clock: C,
+    decode: Decode,
+    config: ClientConfig,
+    phase: Phase,
+    version: Version,
+    capabilities: Capabilities,
+    delivery: DeliveryMode,
+    pending: VecDeque<Pending>,
+
+    // 订阅号对应的订阅名称
+    sids: HashMap<u32, Bytes>,
+    send: BytesMut,
+}
+
+impl ClientConnection<SystemClock> {
+    pub fn new(config: ClientConfig) -> Self {
+        Self::with_clock(config, SystemClock)
+    }
+}
+
+impl<C> ClientConnection<C>
+where
+    C: Clock,
+{
+    // 时钟只用来判断切换模式的请求是否超时
+    pub fn with_clock(config: ClientConfig, clock: C) -> Self {
+        Self {
+            clock,
+            decode: Decode::new(1024),
+            config,
+            phase: Phase::Handshake,
+            version: Version::V1,
+            capabilities: Capabilities::empty(),
+            delivery: DeliveryMode::new(Capabilities::empty()),
+            pending: VecDeque::new(),
+            sids: HashMap::new(),
+            send: BytesMut::new(),
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.phase == Phase::Established
+    }
+
+    // 切换完成前仍然是原来的模式
+    pub fn mode(&self) -> Mode {
+        self.delivery.mode()
+    }
+
+    pub fn delivery(&self) -> &DeliveryMode {
+        &self.delivery
+    }
+
+    // 等待服务器回复切换请求的时间
+    pub fn set_switch_timeout(&mut self, timeout: Duration) {
+        self.delivery.set_timeout(timeout);
+    }
+
+    // 切换请求超时的时间, 没有等待中的请求时为 None
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.delivery.next_deadline()
+    }
+
+    // 切换请求超时后无法知道服务器处在哪个模式, 只能关闭连接
+    pub fn poll_timeout(&mut self) -> Result<(), Error> {
+        match self.delivery.poll_timeout(self.clock.now()) {
+            Some(mode) => {
+                self.phase = Phase::Closed;
+                Err(Error::SwitchTimeout(mode))
+            }
+            None => Ok(()),
+        }
+    }
+
+    // 握手完成后协商出来的版本
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    // 握手完成后双方都支持的服务
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    // 按照 Msg 中的订阅号找到订阅名称, 只有确认过的订阅才有订阅号
+    pub fn subscription(&self, sid: u32) -> Option<&Bytes> {
+        self.sids.get(&sid)
+    }
+
+    pub fn receive<R>(&mut self, buff: R)
+    where
+        R: AsRef<[u8]>,
+    {
+        if self.phase != Phase::Closed {
+            self.decode.set_buff(buff);
+        }
+    }
+
+    pub fn poll_event(&mut self) -> Option<Result<Event, Error>> {
+        loop {
+            if self.phase == Phase::Closed {
+                return None;
+            }
+
+            let result = match self.decode.iter().next()? {
+                Ok(message) => self.handle(message),
+                Err(e) => Err(Error::from(e)),
+            };
+
+            match result {
+                Ok(Some(event)) => return Some(Ok(event)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.phase = Phase::Closed;
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    // 取出所有待发送的字节
+    pub fn poll_transmit(&mut self) -> Option<BytesMut> {
+        if self.send.is_empty() {
+            None
+        } else {
+            Some(self.send.split())
+        }
+    }
+
+    pub fn ping(&mut self) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ClientFrame::Ping);
+        Ok(())
+    }
+
+    pub fn subscribe(&mut self, name: &str) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ClientFrame::Sub(Sub::new(name)));
+        Ok(())
+    }
+
+    // 服务器会回复订阅号或者错误
+    pub fn subscribe_with_reply(&mut self, name: &str) -> Result<(), Error> {
+        self.established()?;
+        self.pending
+            .push_back(Pending::Sub(Bytes::copy_from_slice(name.as_bytes())));
+        self.queue(ClientFrame::Sub(Sub::new(name).with_reply()));
+        Ok(())
+    }
+
+    // 同一个队列组中只有一个成员收到消息
+    pub fn queue_subscribe(&mut self, name: &str, queue: &str) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ClientFrame::Sub(Sub::new(name).with_queue(queue)));
+        Ok(())
+    }
+
+    pub fn queue_subscribe_with_reply(&mut self, name: &str, queue: &str) -> Result<(), Error> {
+        self.established()?;
+        self.pending
+            .push_back(Pending::Sub(Bytes::copy_from_slice(name.as_bytes())));
+        self.queue(ClientFrame::Sub(
+            Sub::new(name).with_queue(queue).with_reply(),
+        ));
+        Ok(())
+    }
+
+    pub fn unsubscribe(&mut self, name_list: &[&str]) -> Result<(), Error> {
+        self.established()?;
+        self.forget(name_list);
+        let mut unsub = UnSub::new();
+        name_list
+            .iter()
+            .for_each(|name| unsub.push(name.as_bytes()));
+        self.queue(ClientFrame::UnSub(unsub));
+        Ok(())
+    }
+
+    // 服务器会回复ok或者错误, 第一版的取消订阅没有回复标志
+    pub fn unsubscribe_with_reply(&mut self, name_list: &[&str]) -> Result<(), Error> {
+        self.established()?;
+        if self.version == Version::V1 {
+            return Err(Error::UnSubReplyNotSupported);
+        }
+        self.forget(name_list);
+        let mut unsub = UnSub::new().with_reply();
+        name_list
+            .iter()
+            .for_each(|name| unsub.push(name.as_bytes()));
+        self.pending.push_back(Pending::UnSub);
+        self.queue(ClientFrame::UnSub(unsub));
+        Ok(())
+    }
+
+    // 取消订阅后不再按订阅号分发
+    fn forget(&mut self, name_list: &[&str]) {
+        self.sids
+            .retain(|_, name| !name_list.iter().any(|item| item.as_bytes() == &name[..]));
+    }
+
+    pub fn publish<A>(&mut self, sub_name: &str, payload: A) -> Result<(), Error>
+    where
+        A: AsRef<[u8]>,
+    {
+        self.established()?;
+        self.queue(ClientFrame::Pub(Pub::new(sub_name, payload.as_ref())));
+        Ok(())
+    }
+
+    pub fn publish_with_headers<A>(
+        &mut self,
+        sub_name: &str,
+        headers: &Headers,
+        payload: A,
+    ) -> Result<(), Error>
+    where
+        A: AsRef<[u8]>,
+    {
+        self.established()?;
+        self.queue(ClientFrame::Pub(
+            Pub::new(sub_name, payload.as_ref()).with_headers(headers),
+        ));
+        Ok(())
+    }
+
+    pub fn offset(&mut self, offset: u64, sub_name: &str) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ClientFrame::Offset(encode::Offset::new(offset, sub_name)));
+        Ok(())
+    }
+
+    pub fn ack(&mut self, offset: u64, sub_name: &str) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ClientFrame::Ack(encode::Ack::new(offset, sub_name)));
+        Ok(())
+    }
+
+    // 拒绝一条消息, delay 为 None 时由服务器决定什么时候重新投递
+    pub fn nack(
+        &mut self,
+        offset: u64,
+        sub_name: &str,
+        delay: Option<Duration>,
+        reason: &str,
+    ) -> Result<(), Error> {
+        self.established()?;
+        let nack = encode::Nack::new(offset, sub_name)
+            .with_delay(delay.unwrap_or_default())
+            .with_reason(reason);
+        self.queue(ClientFrame::Nack(nack));
+        Ok(())
+    }
+
+    // 应答会发到 reply_to, 调用者需要先订阅这个收件箱
+    pub fn request<A>(
+        &mut self,
+        id: u64,
+        sub_name: &str,
+        reply_to: &str,
+        payload: A,
+    ) -> Result<(), Error>
+    where
+        A: AsRef<[u8]>,
+    {
+        self.established()?;
+        self.queue(ClientFrame::Request(encode::Request::new(
+            id,
+            sub_name,
+            reply_to,
+            payload.as_ref(),
+        )));
+        Ok(())
+    }
+
+    pub fn reply<A>(&mut self, id: u64, sub_name: &str, payload: A) -> Result<(), Error>
+    where
+        A: AsRef<[u8]>,
+    {
+        self.established()?;
+        self.queue(ClientFrame::Reply(encode::Reply::new(
+            id,
+            sub_name,
+            payload.as_ref(),
+        )));
+        Ok(())
+    }
+
+    // 拉取一批消息, 服务器发完消息之后回复拉取结束
+    // expires 为没有足够的消息时服务器等待的时间, 用来实现长轮询
+    pub fn fetch(
+        &mut self,
+        sub_name: &str,
+        batch: u32,
+        max_bytes: u32,
+        expires: Duration,
+    ) -> Result<(), Error> {
+        self.established()?;
+        if self.delivery.mode() != Mode::Pull {
+            return Err(Error::NotPullMode);
+        }
+        self.queue(ClientFrame::Fetch(
+            Fetch::new(sub_name, batch)
+                .with_max_bytes(max_bytes)
+                .with_expires(expires),
+        ));
+        Ok(())
+    }
+
+    // 允许服务器在 max_task_size 之外再推送 credit 条消息
+    pub fn grant_credit(&mut self, credit: u32) -> Result<(), Error> {
+        self.established()?;
+        self.queue(ClientFrame::Credit(Credit::new(credit)));
+        Ok(())
+    }
+
+    pub fn turn_push(&mut self) -> Result<(), Error> {
+        self.turn(Mode::Push, ClientFrame::TurnPush)
+    }
+
+    pub fn turn_pull(&mut self) -> Result<(), Error> {
+        self.turn(Mode::Pull, ClientFrame::TurnPull)
+    }
+
+    fn turn(&mut self, mode: Mode, frame: ClientFrame<'_>) -> Result<(), Error> {
+        self.established()?;
+        self.delivery
+            .request(mode, self.clock.now())
+            .map_err(|e| match e {
+                ModeError::NotAllowed(mode) => Error::ModeNotAllowed(mode),
+                _ => Error::SwitchPending,
+            })?;
+        self.pending.push_back(Pending::Turn(mode));
+        self.queue(frame);
+        Ok(())
+    }
+
+    // 握手完成前使用第一版格式
+    fn queue(&mut self, frame: ClientFrame<'_>) {
+        frame.encode_into_with(self.version, &mut self.send);
+    }
+
+    fn established(&self) -> Result<(), Error> {
+        if self.phase == Phase::Established {
+            Ok(())
+        } else {
+            Err(Error::NotConnected)
+        }
+    }
+
+    fn unexpected(&self, message: &Message) -> Error {
+        Error::UnexpectedFrame {
+            frame: frame_name(message),
+            phase: self.phase,
+        }
+    }
+
+    fn handle(&mut self, message: Message) -> Result<Option<Event>, Error> {
+        if self.phase == Phase::Handshake {
+            return match message {
+                Message::Info(info) => {
+                    // 版本或者服务协商失败时不再发送客户端信息
+                    self.version = Version::negotiate(
+                        self.config.get_min_version(),
+                        self.config.get_version(),
+                        info.version,
+                    )?;
+                    self.capabilities = Capabilities::negotiate(
+                        info.capabilities(),
+                        Capabilities::from_bits(self.config.get_support()),
+                    )?;
+                    self.delivery.set_capabilities(self.capabilities);
+
+                    // 回复的客户端信息中带上选定的版本
+                    self.config.set_version(self.version.as_u8());
+                    // 开启认证时用服务器的随机数计算证明
+                    self.config.set_nonce(info.nonce.clone());
+                    ClientFrame::Info(&self.config).encode_into(&mut self.send);
+                    self.decode.set_version(self.version);
+                    // 服务器不会转发超过自己上限的消息
+                    self.decode
+                        .set_max_message_length(info.max_message_length as usize);
+                    self.phase = Phase::Established;
+                    Ok(Some(Event::Connected(info)))
+                }
+                // 握手阶段服务器也可能直接报错
+                Message::Err(erro) => Ok(Some(Event::Err(erro))),
+                message => Err(self.unexpected(&message)),
+            };
+        }
+
+        match message {
+            Message::Ping => {
+                self.queue(ClientFrame::Pong);
+                Ok(None)
+            }
+            Message::Pong => Ok(Some(Event::Pong)),
+            Message::Msg(msg) => Ok(Some(Event::Msg(msg))),
+            Message::Offset(offset) => Ok(Some(Event::Offset(offset))),
+            Message::Ack(ack) => Ok(Some(Event::Ack(ack))),
+            Message::Request(request) => Ok(Some(Event::Request(request))),
+            Message::Reply(reply) => Ok(Some(Event::Reply(reply))),
+            Message::FetchDone(fetch_done) => Ok(Some(Event::FetchDone(fetch_done))),
+            Message::Err(erro) => {
+                // 最早的请求被拒绝, 切换模式被拒绝时保持原来的模式
+                // 其他的错误与等待回复的请求无关, 第一版没有错误码, 只能都当作拒绝
+                let rejected = match self.pending.front() {
+                    Some(pending) => {
+                        self.version == Version::V1 || pending.is_rejected_by(erro.code)
+                    }
+                    None => false,
+                };
+                if rejected {
+                    if let Some(Pending::Turn(_)) = self.pending.pop_front() {
+                        let _ = self.delivery.reject();
+                    }
+                }
+                Ok(Some(Event::Err(erro)))
+            }
+            Message::Ok => match self.pending.pop_front() {
+                // ok 必须回答的是最近一次的切换请求
+                Some(Pending::Turn(mode)) if self.delivery.confirm() == Ok(mode) => {
+                    Ok(Some(Event::ModeChanged(mode)))
+                }
+                Some(Pending::UnSub) => Ok(Some(Event::Unsubscribed)),
+                _ => Err(self.unexpected(&Message::Ok)),
+            },
+            Message::SubAck(sub_ack) => match self.pending.pop_front() {
+                Some(Pending::Sub(name)) if name == sub_ack.sub_name => {
+                    self.sids.insert(sub_ack.sid, name);
+                    Ok(Some(Event::Subscribed(sub_ack)))
+                }
+                _ => Err(self.unexpected(&Message::SubAck(sub_ack))),
+            },
+            message => Err(self.unexpected(&message)),
+        }
+    }
+}
+
+fn frame_name(message: &Message) -> &'static str {
+    match message {
+        Message::Info(_) => "server info",
+        Message::Ping => "ping",
+        Message::Pong => "pong",
+        Message::TurnPush => "turn push",
+        Message::TurnPull => "turn pull",
+        Message::Ok => "ok",
+        Message::Err(_) => "err",
+        Message::Msg(_) => "msg",
+        Message::Offset(_) => "offset",
+        Message::Ack(_) => "ack",
+        Message::Request(_) => "request",
+        Message::Reply(_) => "reply",
+        Message::SubAck(_) => "sub ack",
+        Message::FetchDone(_) => "fetch done",
+    }
+}
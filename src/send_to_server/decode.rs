@@ -1,34 +1,96 @@
-use crate::common::{U16_SIZE, U32_SIZE, U64_SIZE, U8_SIZE};
-use crate::state::ClientState;
-use bytes::{Buf, BytesMut};
+use crate::capabilities::Capabilities;
+use crate::common::{ENVELOPE_SIZE, U16_SIZE, U32_SIZE, U64_SIZE, U8_SIZE};
+use crate::error::is_valid_subject;
+use crate::headers::Headers;
+use crate::state::{is_frame_type, ClientState, FetchStatus, Support};
+use crate::version::Version;
+use bytes::{Buf, Bytes, BytesMut};
 use std::convert::{AsRef, TryInto};
-use std::iter::Iterator;
+use std::iter::{FusedIterator, Iterator};
 use std::mem::swap;
-use thiserror::Error;
 
-#[derive(Debug, Error)]
-pub enum Error {
-    #[error("parse error")]
-    Parse,
-}
+pub use crate::error::{Error, ErrorCode, ErrorKind, ErrorPolicy};
 
 #[derive(Debug)]
 pub struct Info {
     pub version: u8,
     pub support: u16,
     pub max_message_length: u32,
+
+    // 开启认证时才有, 用来计算证明
+    pub nonce: Bytes,
+}
+
+impl Info {
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_bits(self.support)
+    }
 }
 
 #[derive(Debug)]
 pub struct Erro {
-    pub msg: BytesMut,
+    // 第一版没有错误码, 为 Unknown(0)
+    pub code: ErrorCode,
+
+    // 可以为空
+    pub msg: Bytes,
 }
 
 #[derive(Debug)]
 pub struct Msg {
     pub offset: u64,
-    pub payload: BytesMut,
-    pub sub_name: BytesMut,
+
+    // 服务器分配的订阅号, 用来代替订阅名称分发消息, 第一版中为0
+    pub sid: u32,
+    pub payload: Bytes,
+    pub sub_name: Bytes,
+
+    // 不带消息头的消息为空
+    pub headers: Headers,
+}
+
+#[derive(Debug)]
+pub struct Offset {
+    pub offset: u64,
+    pub sub_name: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Ack {
+    pub offset: u64,
+    pub sub_name: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Request {
+    pub id: u64,
+    pub sub_name: Bytes,
+    pub reply_to: Bytes,
+    pub payload: Bytes,
+}
+
+#[derive(Debug)]
+pub struct Reply {
+    pub id: u64,
+    pub sub_name: Bytes,
+    pub payload: Bytes,
+}
+
+// 服务器确认订阅
+#[derive(Debug)]
+pub struct SubAck {
+    pub sid: u32,
+    pub sub_name: Bytes,
+}
+
+// 一次拉取结束
+#[derive(Debug)]
+pub struct FetchDone {
+    pub sub_name: Bytes,
+    pub status: FetchStatus,
+
+    // 这次拉取中发出的消息数量
+    pub count: u32,
 }
 
 #[derive(Debug)]
@@ -41,15 +103,61 @@ pub enum Message {
     Ok,
     Err(Box<Erro>),
     Msg(Box<Msg>),
+    Offset(Box<Offset>),
+    Ack(Box<Ack>),
+    Request(Box<Request>),
+    Reply(Box<Reply>),
+    SubAck(Box<SubAck>),
+    FetchDone(Box<FetchDone>),
 }
 
 #[derive(Debug)]
 enum Transition {
     None,
+    Info {
+        version: u8,
+        support: u16,
+        max_message_length: u32,
+        nonce: Bytes,
+    },
+    Err {
+        code: ErrorCode,
+        msg: Bytes,
+    },
     Msg {
         offset: u64,
-        payload: BytesMut,
-        sub_name: BytesMut,
+        sid: u32,
+        payload: Bytes,
+        sub_name: Bytes,
+        headers: Headers,
+    },
+    Offset {
+        offset: u64,
+        sub_name: Bytes,
+    },
+    Ack {
+        offset: u64,
+        sub_name: Bytes,
+    },
+    Request {
+        id: u64,
+        sub_name: Bytes,
+        reply_to: Bytes,
+        payload: Bytes,
+    },
+    Reply {
+        id: u64,
+        sub_name: Bytes,
+        payload: Bytes,
+    },
+    SubAck {
+        sid: u32,
+        sub_name: Bytes,
+    },
+    FetchDone {
+        sub_name: Bytes,
+        status: FetchStatus,
+        count: u32,
     },
 }
 
@@ -57,59 +165,339 @@ impl Transition {
     fn msg() -> Self {
         Transition::Msg {
             offset: 0,
-            payload: BytesMut::new(),
-            sub_name: BytesMut::new(),
+            sid: 0,
+            payload: Bytes::new(),
+            sub_name: Bytes::new(),
+            headers: Headers::new(),
+        }
+    }
+
+    fn err(code: ErrorCode) -> Self {
+        Transition::Err {
+            code,
+            msg: Bytes::new(),
         }
     }
 
-    fn set_msg_offset(&mut self, offset: u64) {
+    fn sub_ack(sid: u32) -> Self {
+        Transition::SubAck {
+            sid,
+            sub_name: Bytes::new(),
+        }
+    }
+
+    fn fetch_done(status: FetchStatus, count: u32) -> Self {
+        Transition::FetchDone {
+            sub_name: Bytes::new(),
+            status,
+            count,
+        }
+    }
+
+    fn set_sid(&mut self, new_sid: u32) {
         if let Transition::Msg {
-            offset: non_offset,
+            offset: _,
+            sid,
             payload: _,
             sub_name: _,
+            headers: _,
         } = self
         {
-            *non_offset = offset;
+            *sid = new_sid;
         }
     }
 
-    fn set_msg_subname(&mut self, sub_name: BytesMut) {
+    fn set_headers(&mut self, new_headers: Headers) {
         if let Transition::Msg {
             offset: _,
+            sid: _,
             payload: _,
-            sub_name: non_subname,
+            sub_name: _,
+            headers,
         } = self
         {
-            *non_subname = sub_name;
+            *headers = new_headers;
         }
     }
 
-    fn set_msg_payload(&mut self, payload: BytesMut) {
-        if let Transition::Msg {
-            offset: _,
-            payload: non_payload,
+    fn offset() -> Self {
+        Transition::Offset {
+            offset: 0,
+            sub_name: Bytes::new(),
+        }
+    }
+
+    fn ack() -> Self {
+        Transition::Ack {
+            offset: 0,
+            sub_name: Bytes::new(),
+        }
+    }
+
+    fn request(id: u64) -> Self {
+        Transition::Request {
+            id,
+            sub_name: Bytes::new(),
+            reply_to: Bytes::new(),
+            payload: Bytes::new(),
+        }
+    }
+
+    fn reply(id: u64) -> Self {
+        Transition::Reply {
+            id,
+            sub_name: Bytes::new(),
+            payload: Bytes::new(),
+        }
+    }
+
+    fn set_offset(&mut self, offset: u64) {
+        match self {
+            Transition::Msg {
+                offset: non_offset,
+                sid: _,
+                payload: _,
+                sub_name: _,
+                headers: _,
+            } => {
+                *non_offset = offset;
+            }
+            Transition::Offset {
+                offset: non_offset,
+                sub_name: _,
+            } => {
+                *non_offset = offset;
+            }
+            Transition::Ack {
+                offset: non_offset,
+                sub_name: _,
+            } => {
+                *non_offset = offset;
+            }
+            _ => {}
+        }
+    }
+
+    fn set_subname(&mut self, sub_name: Bytes) {
+        match self {
+            Transition::Msg {
+                offset: _,
+                sid: _,
+                payload: _,
+                sub_name: non_subname,
+                headers: _,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::Offset {
+                offset: _,
+                sub_name: non_subname,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::Ack {
+                offset: _,
+                sub_name: non_subname,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::Request {
+                id: _,
+                sub_name: non_subname,
+                reply_to: _,
+                payload: _,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::Reply {
+                id: _,
+                sub_name: non_subname,
+                payload: _,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::SubAck {
+                sid: _,
+                sub_name: non_subname,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::FetchDone {
+                sub_name: non_subname,
+                status: _,
+                count: _,
+            } => {
+                *non_subname = sub_name;
+            }
+            Transition::None
+            | Transition::Err { code: _, msg: _ }
+            | Transition::Info {
+                version: _,
+                support: _,
+                max_message_length: _,
+                nonce: _,
+            } => {}
+        }
+    }
+
+    fn set_reply_to(&mut self, reply_to: Bytes) {
+        if let Transition::Request {
+            id: _,
             sub_name: _,
+            reply_to: non_reply_to,
+            payload: _,
         } = self
         {
-            *non_payload = payload;
+            *non_reply_to = reply_to;
         }
     }
 
-    fn return_params(&mut self) -> Result<Message, Error> {
+    fn set_payload(&mut self, payload: Bytes) {
+        match self {
+            Transition::Err {
+                code: _,
+                msg: non_payload,
+            }
+            | Transition::Info {
+                version: _,
+                support: _,
+                max_message_length: _,
+                nonce: non_payload,
+            }
+            | Transition::Msg {
+                offset: _,
+                sid: _,
+                payload: non_payload,
+                sub_name: _,
+                headers: _,
+            }
+            | Transition::Request {
+                id: _,
+                sub_name: _,
+                reply_to: _,
+                payload: non_payload,
+            }
+            | Transition::Reply {
+                id: _,
+                sub_name: _,
+                payload: non_payload,
+            } => {
+                *non_payload = payload;
+            }
+            _ => {}
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            Transition::None => true,
+            Transition::Err { code: _, msg: _ } => true,
+            Transition::Info {
+                version: _,
+                support: _,
+                max_message_length: _,
+                nonce: _,
+            } => true,
+            Transition::Msg {
+                offset: _,
+                sid: _,
+                payload: _,
+                sub_name,
+                headers: _,
+            }
+            | Transition::Offset {
+                offset: _,
+                sub_name,
+            }
+            | Transition::Ack {
+                offset: _,
+                sub_name,
+            }
+            | Transition::Reply {
+                id: _,
+                sub_name,
+                payload: _,
+            }
+            | Transition::SubAck { sid: _, sub_name }
+            | Transition::FetchDone {
+                sub_name,
+                status: _,
+                count: _,
+            } => is_valid_subject(sub_name),
+            Transition::Request {
+                id: _,
+                sub_name,
+                reply_to,
+                payload: _,
+            } => is_valid_subject(sub_name) && is_valid_subject(reply_to),
+        }
+    }
+
+    fn return_params(&mut self) -> Message {
         let mut item = Transition::None;
         swap(self, &mut item);
 
         match item {
-            Self::None => Err(Error::Parse),
+            Self::None => unreachable!("frame finished without params"),
+            Self::Err { code, msg } => Message::Err(Box::new(Erro { code, msg })),
+            Self::Info {
+                version,
+                support,
+                max_message_length,
+                nonce,
+            } => Message::Info(Box::new(Info {
+                version,
+                support,
+                max_message_length,
+                nonce,
+            })),
             Self::Msg {
                 offset,
+                sid,
                 payload,
                 sub_name,
-            } => Ok(Message::Msg(Box::new(Msg {
+                headers,
+            } => Message::Msg(Box::new(Msg {
                 offset,
+                sid,
                 payload,
                 sub_name,
-            }))),
+                headers,
+            })),
+            Self::Offset { offset, sub_name } => {
+                Message::Offset(Box::new(Offset { offset, sub_name }))
+            }
+            Self::Ack { offset, sub_name } => Message::Ack(Box::new(Ack { offset, sub_name })),
+            Self::Request {
+                id,
+                sub_name,
+                reply_to,
+                payload,
+            } => Message::Request(Box::new(Request {
+                id,
+                sub_name,
+                reply_to,
+                payload,
+            })),
+            Self::Reply {
+                id,
+                sub_name,
+                payload,
+            } => Message::Reply(Box::new(Reply {
+                id,
+                sub_name,
+                payload,
+            })),
+            Self::SubAck { sid, sub_name } => Message::SubAck(Box::new(SubAck { sid, sub_name })),
+            Self::FetchDone {
+                sub_name,
+                status,
+                count,
+            } => Message::FetchDone(Box::new(FetchDone {
+                sub_name,
+                status,
+                count,
+            })),
         }
     }
 }
@@ -120,6 +508,33 @@ pub struct Decode {
     state: Option<ClientState>,
     length: usize,
     params: Transition,
+    version: Version,
+
+    // 单个消息体允许的最大长度, 超过时直接报错而不是等待缓冲
+    max_message_length: usize,
+
+    // 之前已经解析掉的字节数, 用来定位出错的帧
+    position: u64,
+
+    // 本次迭代开始时缓冲的长度
+    mark: usize,
+
+    // 当前帧的类型字节所在的位置
+    frame_start: u64,
+
+    // 第二版中当前帧结束的位置
+    frame_end: Option<u64>,
+
+    policy: ErrorPolicy,
+
+    // Poison 策略下第一次出错的原因
+    poisoned: Option<Error>,
+
+    // Resync 策略下还需要丢弃的字节数
+    skip: usize,
+
+    // 第一版消息头出错时先读出消息体的长度, 再连同消息体一起跳过
+    header_error: Option<ErrorKind>,
 }
 
 impl Decode {
@@ -129,13 +544,59 @@ impl Decode {
             state: None,
             length: 0,
             params: Transition::None,
+            version: Version::V1,
+            max_message_length: u32::MAX as usize,
+            position: 0,
+            mark: 0,
+            frame_start: 0,
+            frame_end: None,
+            policy: ErrorPolicy::Poison,
+            poisoned: None,
+            skip: 0,
+            header_error: None,
         }
     }
 
-    pub fn get_mut_buff(&mut self) -> &BytesMut {
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    // 握手完成后按照协商出来的版本解析之后的帧
+    pub fn set_version(&mut self, version: Version) {
+        self.version = version;
+    }
+
+    pub fn error_policy(&self) -> ErrorPolicy {
+        self.policy
+    }
+
+    pub fn set_error_policy(&mut self, policy: ErrorPolicy) {
+        self.policy = policy;
+    }
+
+    // 已经出错并且不再解析
+    pub fn poisoned(&self) -> Option<&Error> {
+        self.poisoned.as_ref()
+    }
+
+    pub fn max_message_length(&self) -> usize {
+        self.max_message_length
+    }
+
+    // 设置协商好的消息长度上限, 对Pub, Msg和Err帧生效
+    pub fn set_max_message_length(&mut self, max_message_length: usize) {
+        self.max_message_length = max_message_length;
+    }
+
+    pub fn get_mut_buffer(&mut self) -> &mut BytesMut {
         &mut self.buffer
     }
 
+    #[deprecated(note = "use get_mut_buffer, the same accessor as the server side decoder")]
+    pub fn get_mut_buff(&mut self) -> &mut BytesMut {
+        self.get_mut_buffer()
+    }
+
     pub fn set_buff<R>(&mut self, buff: R)
     where
         R: AsRef<[u8]>,
@@ -147,35 +608,174 @@ impl Decode {
         self.state = None;
         self.length = 0;
         self.params = Transition::None;
+        self.header_error = None;
+    }
+
+    // 缓冲中还有没解析完的帧, 字节流结束时说明最后一帧被截断
+    pub fn is_partial(&self) -> bool {
+        !self.buffer.is_empty() || self.state.is_some() || self.skip > 0
     }
 
     pub fn iter(&mut self) -> Iter<'_> {
-        Iter { source: self }
+        Iter {
+            source: self,
+            done: false,
+        }
+    }
+
+    // 当前在整个字节流中的位置
+    fn consumed(&self) -> u64 {
+        self.position + (self.mark - self.buffer.len()) as u64
+    }
+
+    // 丢弃当前帧的状态, 生成带位置的错误
+    fn error(&mut self, kind: ErrorKind) -> Error {
+        let mut aligned = true;
+        if self.policy == ErrorPolicy::Resync {
+            // 第二版按照帧头中的长度跳过剩下的帧体
+            // 第一版只能跳过帧末尾超长的消息体, 名称和消息头出错时整个帧已经读完或者会连同消息体一起跳过
+            match (self.frame_end, &kind) {
+                (Some(end), _) => self.skip = end.saturating_sub(self.consumed()) as usize,
+                (None, ErrorKind::PayloadTooLarge { limit: _, got })
+                    if !matches!(self.state, Some(ClientState::HMsgHeadersLength)) =>
+                {
+                    self.skip = *got
+                }
+                (None, ErrorKind::InvalidSubject) | (None, ErrorKind::InvalidHeaders) => {}
+                (None, _) => aligned = false,
+            }
+        }
+        self.frame_end = None;
+
+        let error = Error::new(kind, self.frame_start);
+        // 第一版不知道出错的帧在哪里结束, 继续解析只会把后面的字节当作新的帧, 和 Poison 一样不再解析
+        if !aligned {
+            self.poisoned = Some(error.clone());
+        }
+        self.reset();
+        error
+    }
+
+    // 消息体长度超过上限时直接报错, 不等待缓冲
+    fn check_length(&mut self) -> Result<(), Error> {
+        if self.length > self.max_message_length {
+            Err(self.error(ErrorKind::PayloadTooLarge {
+                limit: self.max_message_length,
+                got: self.length,
+            }))
+        } else {
+            Ok(())
+        }
+    }
+
+    // 第二版的帧体只能读到帧头中声明的长度
+    fn available(&self) -> usize {
+        match self.frame_end {
+            Some(end) => self
+                .buffer
+                .len()
+                .min(end.saturating_sub(self.consumed()) as usize),
+            None => self.buffer.len(),
+        }
+    }
+
+    // 整个帧体都已经收到还解析不完, 说明声明的长度太短
+    fn check_body_end(&mut self) -> Option<Error> {
+        let end = self.frame_end?;
+        if self.buffer.len() as u64 >= end.saturating_sub(self.consumed()) {
+            let declared = (end - self.frame_start) as usize - ENVELOPE_SIZE;
+            Some(self.error(ErrorKind::LengthMismatch { declared }))
+        } else {
+            None
+        }
+    }
+
+    // 第二版按照帧头跳过整个帧, 第一版在 Resync 策略下推迟到读出消息体长度之后报错
+    fn check_headers(&mut self, result: Result<Headers, ErrorKind>) -> Result<(), Error> {
+        match result {
+            Ok(headers) => {
+                self.params.set_headers(headers);
+                Ok(())
+            }
+            Err(kind) if self.policy == ErrorPolicy::Resync && self.frame_end.is_none() => {
+                self.header_error = Some(kind);
+                Ok(())
+            }
+            Err(kind) => Err(self.error(kind)),
+        }
+    }
+
+    // 跳过被丢弃的消息体, 全部跳过之后返回true
+    fn discard(&mut self) -> bool {
+        let len = self.skip.min(self.buffer.len());
+        self.buffer.advance(len);
+        self.skip -= len;
+        self.skip == 0
+    }
+
+    // 整个帧读完之后再检查订阅名称, 出错时字节流仍然是对齐的
+    fn check_params(&mut self) -> Result<(), Error> {
+        if self.params.is_valid() {
+            Ok(())
+        } else {
+            Err(self.error(ErrorKind::InvalidSubject))
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Iter<'a> {
     source: &'a mut Decode,
-}
 
-impl<'a> Iterator for Iter<'a> {
-    type Item = Result<Message, Error>;
+    // 返回过错误之后本次迭代结束
+    done: bool,
+}
 
-    fn next(&mut self) -> Option<Self::Item> {
+impl<'a> Iter<'a> {
+    // 第一版的帧格式
+    fn next_v1(&mut self) -> Option<Result<Message, Error>> {
         loop {
-            if !self.source.buffer.has_remaining() {
-                return None;
-            } else if let Some(state) = &self.source.state {
+            if let Some(state) = &self.source.state {
                 match state {
                     ClientState::ServerInfo => {
-                        if self.source.buffer.len() > 6 {
+                        if self.source.available() > 6 {
+                            let version = self.source.buffer.get_u8();
+                            let support = self.source.buffer.get_u16();
+                            let max_message_length = self.source.buffer.get_u32();
+                            self.source.params = Transition::Info {
+                                version,
+                                support,
+                                max_message_length,
+                                nonce: Bytes::new(),
+                            };
+
+                            // 开启认证时后面带上随机数
+                            if support & Support::Auth {
+                                self.source.state = Some(ClientState::ServerInfoNonceLength);
+                            } else {
+                                let info = self.source.params.return_params();
+                                self.source.reset();
+                                return Some(Ok(info));
+                            }
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::ServerInfoNonceLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::ServerInfoNonce);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::ServerInfoNonce => {
+                        if self.source.available() >= self.source.length {
+                            let nonce = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_payload(nonce);
+                            let info = self.source.params.return_params();
                             self.source.reset();
-                            return Some(Ok(Message::Info(Box::new(Info {
-                                version: self.source.buffer.get_u8(),
-                                support: self.source.buffer.get_u16(),
-                                max_message_length: self.source.buffer.get_u32(),
-                            }))));
+                            return Some(Ok(info));
                         } else {
                             return None;
                         }
@@ -196,25 +796,32 @@ impl<'a> Iterator for Iter<'a> {
                         self.source.reset();
                         return Some(Ok(Message::TurnPull));
                     }
-                    ClientState::Ack => {
-                        return None;
-                    }
                     ClientState::Msg => {
                         self.source.params = Transition::msg();
                         self.source.state = Some(ClientState::MsgOffset);
                     }
                     ClientState::MsgOffset => {
-                        if self.source.buffer.len() >= U64_SIZE {
-                            self.source
-                                .params
-                                .set_msg_offset(self.source.buffer.get_u64());
+                        if self.source.available() >= U64_SIZE {
+                            self.source.params.set_offset(self.source.buffer.get_u64());
+                            // 订阅号只在第二版中发送
+                            self.source.state = match self.source.version {
+                                Version::V1 => Some(ClientState::MsgSubLength),
+                                Version::V2 => Some(ClientState::MsgSid),
+                            };
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::MsgSid => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.params.set_sid(self.source.buffer.get_u32());
                             self.source.state = Some(ClientState::MsgSubLength);
                         } else {
                             return None;
                         }
                     }
                     ClientState::MsgSubLength => {
-                        if self.source.buffer.len() >= U8_SIZE {
+                        if self.source.available() >= U8_SIZE {
                             self.source.length = self.source.buffer.get_u8() as usize;
                             self.source.state = Some(ClientState::MsgSubName);
                         } else {
@@ -222,50 +829,385 @@ impl<'a> Iterator for Iter<'a> {
                         }
                     }
                     ClientState::MsgSubName => {
-                        if self.source.buffer.len() >= self.source.length {
-                            self.source
-                                .params
-                                .set_msg_subname(self.source.buffer.split_to(self.source.length));
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
                             self.source.state = Some(ClientState::MsgLength);
                         } else {
                             return None;
                         }
                     }
                     ClientState::MsgLength => {
-                        if self.source.buffer.len() >= U32_SIZE {
+                        if self.source.available() >= U32_SIZE {
                             self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Some(kind) = self.source.header_error.take() {
+                                let length = self.source.length;
+                                let error = self.source.error(kind);
+                                self.source.skip = length;
+                                return Some(Err(error));
+                            }
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
                             self.source.state = Some(ClientState::MsgPayload);
                         } else {
                             return None;
                         }
                     }
                     ClientState::MsgPayload => {
-                        if self.source.buffer.len() >= self.source.length {
-                            let payload = self.source.buffer.split_to(self.source.length);
-                            self.source.params.set_msg_payload(payload);
+                        if self.source.available() >= self.source.length {
+                            let payload = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_payload(payload);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
                             let msg = self.source.params.return_params();
                             self.source.reset();
-                            return Some(msg);
+                            return Some(Ok(msg));
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::HMsg => {
+                        self.source.params = Transition::msg();
+                        self.source.state = Some(ClientState::HMsgOffset);
+                    }
+                    ClientState::HMsgOffset => {
+                        if self.source.available() >= U64_SIZE {
+                            self.source.params.set_offset(self.source.buffer.get_u64());
+                            // 订阅号只在第二版中发送
+                            self.source.state = match self.source.version {
+                                Version::V1 => Some(ClientState::HMsgSubLength),
+                                Version::V2 => Some(ClientState::HMsgSid),
+                            };
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::HMsgSid => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.params.set_sid(self.source.buffer.get_u32());
+                            self.source.state = Some(ClientState::HMsgSubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::HMsgSubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::HMsgSubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::HMsgSubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            self.source.state = Some(ClientState::HMsgHeadersLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::HMsgHeadersLength => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ClientState::HMsgHeaders);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::HMsgHeaders => {
+                        if self.source.available() >= self.source.length {
+                            let block = self.source.buffer.split_to(self.source.length);
+                            if let Err(e) = self.source.check_headers(Headers::decode(&block)) {
+                                return Some(Err(e));
+                            }
+                            // 之后的内容和不带消息头的消息相同
+                            self.source.state = Some(ClientState::MsgLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::SubAck => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.params = Transition::sub_ack(self.source.buffer.get_u32());
+                            self.source.state = Some(ClientState::SubAckSubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::SubAckSubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::SubAckSubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::SubAckSubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
+                            let sub_ack = self.source.params.return_params();
+                            self.source.reset();
+                            return Some(Ok(sub_ack));
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::FetchDone => {
+                        if self.source.available() >= U8_SIZE + U32_SIZE {
+                            let byte = self.source.buffer.get_u8();
+                            let count = self.source.buffer.get_u32();
+                            let status = match FetchStatus::from_u8(byte) {
+                                Some(status) => status,
+                                None => {
+                                    let kind = ErrorKind::UnknownFetchStatus(byte);
+                                    return Some(Err(self.source.error(kind)));
+                                }
+                            };
+                            self.source.params = Transition::fetch_done(status, count);
+                            self.source.state = Some(ClientState::FetchDoneSubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::FetchDoneSubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::FetchDoneSubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::FetchDoneSubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
+                            let fetch_done = self.source.params.return_params();
+                            self.source.reset();
+                            return Some(Ok(fetch_done));
                         } else {
                             return None;
                         }
                     }
                     ClientState::Offset => {
-                        return None;
+                        if self.source.available() >= U64_SIZE {
+                            self.source.params = Transition::offset();
+                            self.source.params.set_offset(self.source.buffer.get_u64());
+                            self.source.state = Some(ClientState::OffsetSubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::OffsetSubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::OffsetSubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::OffsetSubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
+                            let offset = self.source.params.return_params();
+                            self.source.reset();
+                            return Some(Ok(offset));
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::Ack => {
+                        if self.source.available() >= U64_SIZE {
+                            self.source.params = Transition::ack();
+                            self.source.params.set_offset(self.source.buffer.get_u64());
+                            self.source.state = Some(ClientState::AckSubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::AckSubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::AckSubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::AckSubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
+                            let ack = self.source.params.return_params();
+                            self.source.reset();
+                            return Some(Ok(ack));
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::Request => {
+                        if self.source.available() >= U64_SIZE {
+                            self.source.params = Transition::request(self.source.buffer.get_u64());
+                            self.source.state = Some(ClientState::RequestSubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::RequestSubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::RequestSubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::RequestSubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            self.source.state = Some(ClientState::RequestReplyToLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::RequestReplyToLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::RequestReplyTo);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::RequestReplyTo => {
+                        if self.source.available() >= self.source.length {
+                            let reply_to = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_reply_to(reply_to);
+                            self.source.state = Some(ClientState::RequestLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::RequestLength => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ClientState::RequestPayload);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::RequestPayload => {
+                        if self.source.available() >= self.source.length {
+                            let payload = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_payload(payload);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
+                            let request = self.source.params.return_params();
+                            self.source.reset();
+                            return Some(Ok(request));
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::Reply => {
+                        if self.source.available() >= U64_SIZE {
+                            self.source.params = Transition::reply(self.source.buffer.get_u64());
+                            self.source.state = Some(ClientState::ReplySubLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::ReplySubLength => {
+                        if self.source.available() >= U8_SIZE {
+                            self.source.length = self.source.buffer.get_u8() as usize;
+                            self.source.state = Some(ClientState::ReplySubName);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::ReplySubName => {
+                        if self.source.available() >= self.source.length {
+                            let sub_name = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_subname(sub_name);
+                            self.source.state = Some(ClientState::ReplyLength);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::ReplyLength => {
+                        if self.source.available() >= U32_SIZE {
+                            self.source.length = self.source.buffer.get_u32() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
+                            self.source.state = Some(ClientState::ReplyPayload);
+                        } else {
+                            return None;
+                        }
+                    }
+                    ClientState::ReplyPayload => {
+                        if self.source.available() >= self.source.length {
+                            let payload = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_payload(payload);
+                            if let Err(e) = self.source.check_params() {
+                                return Some(Err(e));
+                            }
+                            let reply = self.source.params.return_params();
+                            self.source.reset();
+                            return Some(Ok(reply));
+                        } else {
+                            return None;
+                        }
                     }
                     ClientState::Err => {
-                        if self.source.buffer.len() >= U16_SIZE {
+                        // 第一版没有错误码
+                        let code_len = match self.source.version {
+                            Version::V1 => 0,
+                            Version::V2 => U16_SIZE,
+                        };
+                        if self.source.available() >= code_len + U16_SIZE {
+                            let code = match self.source.version {
+                                Version::V1 => ErrorCode::Unknown(0),
+                                Version::V2 => ErrorCode::from_u16(self.source.buffer.get_u16()),
+                            };
+                            self.source.params = Transition::err(code);
                             self.source.length = self.source.buffer.get_u16() as usize;
+                            if let Err(e) = self.source.check_length() {
+                                return Some(Err(e));
+                            }
                             self.source.state = Some(ClientState::ErrContent);
                         } else {
                             return None;
                         }
                     }
                     ClientState::ErrContent => {
-                        if self.source.buffer.len() >= self.source.length {
-                            let msg = self.source.buffer.split_to(self.source.length);
+                        if self.source.available() >= self.source.length {
+                            let msg = self.source.buffer.split_to(self.source.length).freeze();
+                            self.source.params.set_payload(msg);
+                            let message = self.source.params.return_params();
                             self.source.reset();
-                            return Some(Ok(Message::Err(Box::new(Erro { msg }))));
+                            return Some(Ok(message));
                         } else {
                             return None;
                         }
@@ -275,14 +1217,106 @@ impl<'a> Iterator for Iter<'a> {
                         return Some(Ok(Message::Ok));
                     }
                 }
+            } else if !self.source.buffer.has_remaining() {
+                return None;
             } else {
+                self.source.frame_start = self.source.consumed();
+                let byte = self.source.buffer.get_u8();
+                match byte.try_into() {
+                    Ok(state) => self.source.state = Some(state),
+                    Err(_) => {
+                        let kind = if is_frame_type(byte) {
+                            ErrorKind::UnexpectedFrame(byte)
+                        } else {
+                            ErrorKind::UnknownFrameType(byte)
+                        };
+                        return Some(Err(self.source.error(kind)));
+                    }
+                }
+            }
+        }
+    }
+
+    // 第二版的帧格式, 帧体仍然按照第一版的格式解析
+    fn next_v2(&mut self) -> Option<Result<Message, Error>> {
+        loop {
+            if self.source.state.is_none() {
+                if !self.source.discard() || self.source.buffer.len() < ENVELOPE_SIZE {
+                    return None;
+                }
+
+                self.source.frame_start = self.source.consumed();
                 let byte = self.source.buffer.get_u8();
+                let flags = self.source.buffer.get_u8();
+                let length = self.source.buffer.get_u32() as usize;
+                self.source.frame_end = Some(self.source.consumed() + length as u64);
+
+                if flags != 0 {
+                    return Some(Err(self.source.error(ErrorKind::UnsupportedFlags(flags))));
+                }
 
                 match byte.try_into() {
                     Ok(state) => self.source.state = Some(state),
-                    Err(_e) => return Some(Err(Error::Parse)),
+                    Err(_) if is_frame_type(byte) => {
+                        return Some(Err(self.source.error(ErrorKind::UnexpectedFrame(byte))));
+                    }
+                    // 更新的版本中才有的帧类型, 直接跳过
+                    Err(_) => {
+                        self.source.skip = length;
+                        self.source.frame_end = None;
+                        continue;
+                    }
                 }
             }
+
+            // 帧体只能读到声明的长度为止, 不会读到下一帧
+            let message = match self.next_v1() {
+                Some(Ok(message)) => message,
+                Some(Err(e)) => return Some(Err(e)),
+                None => return self.source.check_body_end().map(Err),
+            };
+
+            if let Some(end) = self.source.frame_end.take() {
+                // 忽略更新的版本在帧体后面追加的字段
+                self.source.skip = (end - self.source.consumed()) as usize;
+            }
+            return Some(Ok(message));
         }
     }
 }
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = Result<Message, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if let Some(error) = &self.source.poisoned {
+            self.done = true;
+            return Some(Err(error.clone()));
+        }
+
+        self.source.mark = self.source.buffer.len();
+        let item = if self.source.discard() {
+            match self.source.version {
+                Version::V1 => self.next_v1(),
+                Version::V2 => self.next_v2(),
+            }
+        } else {
+            None
+        };
+        self.source.position = self.source.consumed();
+
+        if let Some(Err(error)) = &item {
+            self.done = true;
+            if self.source.policy == ErrorPolicy::Poison {
+                self.source.poisoned = Some(error.clone());
+            }
+        }
+        item
+    }
+}
+
+impl<'a> FusedIterator for Iter<'a> {}
@@ -1,24 +1,43 @@
+use crate::auth::{Credentials, PROOF_LEN};
+use crate::common::{encode, truncate_str, Frame, U16_SIZE, U32_SIZE, U64_SIZE, U8_SIZE};
+use crate::error::ErrorCode;
+use crate::headers::Headers;
 use crate::state::{
-    Support, STATE_CLIENT_INFO, STATE_ERR, STATE_OK, STATE_PING, STATE_PONG, STATE_PUB, STATE_SUB,
-    STATE_TURN_PULL, STATE_TURN_PUSH, STATE_UNSUB,
+    Support, STATE_ACK, STATE_CLIENT_INFO, STATE_CREDIT, STATE_ERR, STATE_FETCH, STATE_HPUB,
+    STATE_NACK, STATE_OFFSET, STATE_OK, STATE_PING, STATE_PONG, STATE_PUB, STATE_REPLY,
+    STATE_REQUEST, STATE_SUB, STATE_TURN_PULL, STATE_TURN_PUSH, STATE_UNSUB, SUB_QUEUE, SUB_REPLY,
 };
-use bytes::{BufMut, BytesMut};
+use crate::version::Version;
+use bytes::buf::ext::{BufExt, Chain};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::convert::TryFrom;
 use std::default::Default;
-use std::u8::MAX as u8_MAX;
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct ClientConfig {
     version: u8,
+
+    // 本地可以接受的最低版本, 不在握手中发送
+    min_version: u8,
     support: u16,
     max_task_size: u8,
+
+    // 开启认证时用服务器的随机数计算证明, 凭证本身不发送
+    credentials: Option<Credentials>,
+    nonce: Bytes,
 }
 
 impl Default for ClientConfig {
     fn default() -> Self {
         Self {
-            version: 1,
+            // 第二版需要调用 set_version 主动开启
+            version: Version::V1.as_u8(),
+            min_version: Version::V1.as_u8(),
             support: 0,
-            max_task_size: u8_MAX,
+            max_task_size: u8::MAX,
+            credentials: None,
+            nonce: Bytes::new(),
         }
     }
 }
@@ -28,6 +47,10 @@ impl ClientConfig {
         self.version = version;
     }
 
+    pub fn set_min_version(&mut self, min_version: u8) {
+        self.min_version = min_version;
+    }
+
     pub fn support_push(&mut self) {
         self.support |= Support::Push;
     }
@@ -48,15 +71,85 @@ impl ClientConfig {
         self.max_task_size = max_task_size;
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::with_capacity(5);
+    pub fn set_token(&mut self, token: &str) {
+        self.support |= Support::Auth;
+        self.credentials = Some(Credentials::Token(token.as_bytes().to_vec()));
+    }
 
-        buff.put_u8(STATE_CLIENT_INFO);
+    pub fn set_user(&mut self, user: &str, password: &str) {
+        self.support |= Support::Auth;
+        self.credentials = Some(Credentials::User {
+            user: user.as_bytes().to_vec(),
+            password: password.as_bytes().to_vec(),
+        });
+    }
+
+    // 客户端连接收到服务器信息之后设置
+    pub fn set_nonce(&mut self, nonce: Bytes) {
+        self.nonce = nonce;
+    }
+
+    pub fn get_version(&self) -> u8 {
+        self.version
+    }
+
+    pub fn get_min_version(&self) -> u8 {
+        self.min_version
+    }
+
+    pub fn get_support(&self) -> u16 {
+        self.support
+    }
+
+    pub fn get_max_task_size(&self) -> u8 {
+        self.max_task_size
+    }
+
+    pub fn get_credentials(&self) -> Option<&Credentials> {
+        self.credentials.as_ref()
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl Frame for ClientConfig {
+    fn kind(&self) -> u8 {
+        STATE_CLIENT_INFO
+    }
+
+    fn body_len(&self) -> usize {
+        let len = U8_SIZE + U16_SIZE + U8_SIZE;
+        match (self.support & Support::Auth, &self.credentials) {
+            (true, Some(credentials)) => {
+                len + U8_SIZE + credentials.user().len() + U8_SIZE + PROOF_LEN
+            }
+            (true, None) => len + U8_SIZE + U8_SIZE,
+            (false, _) => len,
+        }
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
         buff.put_u8(self.version);
         buff.put_u16(self.support);
         buff.put_u8(self.max_task_size);
-
-        buff
+        if self.support & Support::Auth {
+            // 没有凭证时用户名和证明都为空, 由服务器拒绝
+            match &self.credentials {
+                Some(credentials) => {
+                    let user = credentials.user();
+                    buff.put_u8(user.len() as u8);
+                    buff.put_slice(user);
+                    buff.put_u8(PROOF_LEN as u8);
+                    buff.put_slice(&credentials.proof(&self.nonce));
+                }
+                None => {
+                    buff.put_u8(0);
+                    buff.put_u8(0);
+                }
+            }
+        }
     }
 }
 
@@ -105,44 +198,131 @@ impl Ok {
     }
 }
 
+// 没有帧体的帧
 #[derive(Debug)]
-pub struct Err {
-    msg: &'static str,
+struct Bare(u8);
+
+impl Frame for Bare {
+    fn kind(&self) -> u8 {
+        self.0
+    }
+
+    fn body_len(&self) -> usize {
+        0
+    }
+
+    fn encode_body(&self, _buff: &mut BytesMut) {}
+}
+
+#[derive(Debug)]
+pub struct Err<'a> {
+    code: ErrorCode,
+    msg: &'a str,
+}
+
+impl<'a> Err<'a> {
+    // 默认不带文字
+    pub fn new(code: ErrorCode) -> Self {
+        Self { code, msg: "" }
+    }
+
+    // 说明最长 u16::MAX 字节, 超出的部分被截掉
+    pub fn with_msg(mut self, msg: &'a str) -> Self {
+        self.msg = truncate_str(msg, u16::MAX as usize);
+        self
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
 }
 
-impl Err {
-    pub fn new(msg: &'static str) -> Self {
-        debug_assert!(msg.len() < (std::u16::MAX as usize));
-        Self { msg }
+impl<'a> Frame for Err<'a> {
+    fn kind(&self) -> u8 {
+        STATE_ERR
+    }
+
+    fn body_len(&self) -> usize {
+        self.body_len_with(Version::V1)
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        self.encode_body_with(Version::V1, buff);
+    }
+
+    fn body_len_with(&self, version: Version) -> usize {
+        let code_len = if version == Version::V2 { U16_SIZE } else { 0 };
+        code_len + U16_SIZE + self.msg.len()
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::with_capacity(self.msg.len() + 2 + 1);
-        buff.put_u8(STATE_ERR);
+    // 第一版没有错误码
+    fn encode_body_with(&self, version: Version, buff: &mut BytesMut) {
+        if version == Version::V2 {
+            buff.put_u16(self.code.as_u16());
+        }
         buff.put_u16(self.msg.len() as u16);
         buff.extend_from_slice(self.msg.as_bytes());
-        buff
     }
 }
 
 #[derive(Debug)]
 pub struct Sub<'a> {
+    reply: bool,
     name: &'a str,
+    queue: Option<&'a str>,
 }
 
 impl<'a> Sub<'a> {
     pub fn new(name: &'a str) -> Self {
-        Self { name }
+        Self {
+            reply: false,
+            name,
+            queue: None,
+        }
+    }
+
+    // 加入队列组, 同一组中只有一个成员收到消息
+    pub fn with_queue(mut self, queue: &'a str) -> Self {
+        self.queue = Some(queue);
+        self
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::with_capacity(self.name.len() + 3);
+    // 要求服务器确认订阅
+    pub fn with_reply(mut self) -> Self {
+        self.reply = true;
+        self
+    }
 
-        buff.put_u8(STATE_SUB);
-        buff.put_u8(self.name.as_bytes().len() as u8);
-        buff.extend_from_slice(self.name.as_bytes());
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
 
-        buff
+impl<'a> Frame for Sub<'a> {
+    fn kind(&self) -> u8 {
+        STATE_SUB
+    }
+
+    fn body_len(&self) -> usize {
+        let queue_len = self.queue.map_or(0, |queue| U8_SIZE + queue.len());
+        U8_SIZE + U8_SIZE + self.name.len() + queue_len
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        let mut flags = 0;
+        if self.reply {
+            flags |= SUB_REPLY;
+        }
+        if self.queue.is_some() {
+            flags |= SUB_QUEUE;
+        }
+        buff.put_u8(flags);
+        buff.put_u8(self.name.len() as u8);
+        buff.extend_from_slice(self.name.as_bytes());
+        if let Some(queue) = self.queue {
+            buff.put_u8(queue.len() as u8);
+            buff.extend_from_slice(queue.as_bytes());
+        }
     }
 }
 
@@ -153,6 +333,7 @@ where
 {
     sub_name: &'a str,
     payload: A,
+    headers: Option<&'a Headers>,
 }
 
 impl<'a, A> Pub<'a, A>
@@ -160,50 +341,498 @@ where
     A: AsRef<[u8]>,
 {
     pub fn new(sub_name: &'a str, payload: A) -> Self {
-        Self { sub_name, payload }
+        Self {
+            sub_name,
+            payload,
+            headers: None,
+        }
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::new();
+    // 消息头为空时仍然编码成不带消息头的帧
+    pub fn with_headers(mut self, headers: &'a Headers) -> Self {
+        self.headers = Some(headers).filter(|headers| !headers.is_empty());
+        self
+    }
 
-        buff.put_u8(STATE_PUB);
-        buff.put_u8(self.sub_name.as_bytes().len() as u8);
-        buff.extend_from_slice(self.sub_name.as_bytes());
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+
+    // 发布内容不拷贝, 帧头和内容可以一起交给 write_vectored
+    pub fn encode_chain(&self) -> Chain<Bytes, &[u8]> {
+        self.encode_chain_with(Version::V1)
+    }
 
+    pub fn encode_chain_with(&self, version: Version) -> Chain<Bytes, &[u8]> {
+        let payload = self.payload.as_ref();
+        let mut head = BytesMut::with_capacity(self.encoded_len_with(version) - payload.len());
+        self.encode_header(version, &mut head);
+        self.encode_head(&mut head);
+        head.freeze().chain(payload)
+    }
+
+    // 发布内容之前的部分
+    fn encode_head(&self, buff: &mut BytesMut) {
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+        if let Some(headers) = self.headers {
+            buff.put_u32(headers.encoded_len() as u32);
+            headers.encode_into(buff);
+        }
         buff.put_u32(self.payload.as_ref().len() as u32);
-        buff.extend_from_slice(self.payload.as_ref());
+    }
+}
 
-        buff
+impl<'a, A> Frame for Pub<'a, A>
+where
+    A: AsRef<[u8]>,
+{
+    fn kind(&self) -> u8 {
+        if self.headers.is_some() {
+            STATE_HPUB
+        } else {
+            STATE_PUB
+        }
+    }
+
+    fn body_len(&self) -> usize {
+        let headers_len = self
+            .headers
+            .map_or(0, |headers| U32_SIZE + headers.encoded_len());
+        U8_SIZE + self.sub_name.len() + headers_len + U32_SIZE + self.payload.as_ref().len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        self.encode_head(buff);
+        buff.extend_from_slice(self.payload.as_ref());
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct UnSub<'a> {
+    reply: bool,
     name_list: Vec<&'a [u8]>,
 }
 
 impl<'a> UnSub<'a> {
     pub fn new() -> Self {
         UnSub {
+            reply: false,
             name_list: Vec::new(),
         }
     }
 
-    pub fn push(&mut self, name: & 'a [u8]) {
+    // 要求服务器确认取消订阅, 只在第二版中发送
+    pub fn with_reply(mut self) -> Self {
+        self.reply = true;
+        self
+    }
+
+    pub fn push(&mut self, name: &'a [u8]) {
         self.name_list.push(name);
     }
 
-    pub fn encode(self) -> BytesMut {
-        let mut buff = BytesMut::new();
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for UnSub<'a> {
+    fn kind(&self) -> u8 {
+        STATE_UNSUB
+    }
+
+    fn body_len(&self) -> usize {
+        self.body_len_with(Version::V1)
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        self.encode_body_with(Version::V1, buff);
+    }
 
-        buff.put_u8(STATE_UNSUB);
+    fn body_len_with(&self, version: Version) -> usize {
+        let reply_len = if version == Version::V2 { U8_SIZE } else { 0 };
+        self.name_list
+            .iter()
+            .fold(reply_len + U16_SIZE, |len, item| len + U8_SIZE + item.len())
+    }
+
+    fn encode_body_with(&self, version: Version, buff: &mut BytesMut) {
+        if version == Version::V2 {
+            buff.put_u8(self.reply as u8);
+        }
         buff.put_u16(self.name_list.len() as u16);
 
-        self.name_list.into_iter().for_each(|item| {
+        self.name_list.iter().for_each(|item| {
             buff.put_u8(item.len() as u8);
             buff.extend_from_slice(item);
         });
+    }
+}
+
+#[derive(Debug)]
+pub struct Offset<'a> {
+    offset: u64,
+    sub_name: &'a str,
+}
+
+impl<'a> Offset<'a> {
+    pub fn new(offset: u64, sub_name: &'a str) -> Self {
+        Self { offset, sub_name }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Offset<'a> {
+    fn kind(&self) -> u8 {
+        STATE_OFFSET
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.offset);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+    }
+}
+
+#[derive(Debug)]
+pub struct Ack<'a> {
+    offset: u64,
+    sub_name: &'a str,
+}
+
+impl<'a> Ack<'a> {
+    pub fn new(offset: u64, sub_name: &'a str) -> Self {
+        Self { offset, sub_name }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Ack<'a> {
+    fn kind(&self) -> u8 {
+        STATE_ACK
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.offset);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+    }
+}
+
+// 拒绝一条消息, 服务器稍后重新投递
+#[derive(Debug)]
+pub struct Nack<'a> {
+    offset: u64,
+    sub_name: &'a str,
+    delay: Duration,
+    reason: &'a str,
+}
+
+impl<'a> Nack<'a> {
+    // 默认由服务器决定等待时间, 不带原因
+    pub fn new(offset: u64, sub_name: &'a str) -> Self {
+        Self {
+            offset,
+            sub_name,
+            delay: Duration::from_secs(0),
+            reason: "",
+        }
+    }
+
+    // 重新投递前的等待时间, 按毫秒发送
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    // 原因最长 u16::MAX 字节, 超出的部分被截掉
+    pub fn with_reason(mut self, reason: &'a str) -> Self {
+        self.reason = truncate_str(reason, u16::MAX as usize);
+        self
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Nack<'a> {
+    fn kind(&self) -> u8 {
+        STATE_NACK
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U32_SIZE + U8_SIZE + self.sub_name.len() + U16_SIZE + self.reason.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.offset);
+        buff.put_u32(u32::try_from(self.delay.as_millis()).unwrap_or(u32::MAX));
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+        buff.put_u16(self.reason.len() as u16);
+        buff.extend_from_slice(self.reason.as_bytes());
+    }
+}
+
+#[derive(Debug)]
+pub struct Request<'a> {
+    id: u64,
+    sub_name: &'a str,
+    reply_to: &'a str,
+    payload: &'a [u8],
+}
+
+impl<'a> Request<'a> {
+    pub fn new(id: u64, sub_name: &'a str, reply_to: &'a str, payload: &'a [u8]) -> Self {
+        Self {
+            id,
+            sub_name,
+            reply_to,
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Request<'a> {
+    fn kind(&self) -> u8 {
+        STATE_REQUEST
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE
+            + U8_SIZE
+            + self.sub_name.len()
+            + U8_SIZE
+            + self.reply_to.len()
+            + U32_SIZE
+            + self.payload.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.id);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+        buff.put_u8(self.reply_to.len() as u8);
+        buff.extend_from_slice(self.reply_to.as_bytes());
+        buff.put_u32(self.payload.len() as u32);
+        buff.extend_from_slice(self.payload);
+    }
+}
+
+#[derive(Debug)]
+pub struct Reply<'a> {
+    id: u64,
+    sub_name: &'a str,
+    payload: &'a [u8],
+}
+
+impl<'a> Reply<'a> {
+    pub fn new(id: u64, sub_name: &'a str, payload: &'a [u8]) -> Self {
+        Self {
+            id,
+            sub_name,
+            payload,
+        }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Reply<'a> {
+    fn kind(&self) -> u8 {
+        STATE_REPLY
+    }
+
+    fn body_len(&self) -> usize {
+        U64_SIZE + U8_SIZE + self.sub_name.len() + U32_SIZE + self.payload.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u64(self.id);
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+        buff.put_u32(self.payload.len() as u32);
+        buff.extend_from_slice(self.payload);
+    }
+}
+
+// 拉模式下请求一批消息, 服务器发完之后回复拉取结束
+#[derive(Debug)]
+pub struct Fetch<'a> {
+    sub_name: &'a str,
+    batch: u32,
+    max_bytes: u32,
+    expires: Duration,
+}
+
+impl<'a> Fetch<'a> {
+    // 默认不限制字节数, 没有消息时立即结束
+    pub fn new(sub_name: &'a str, batch: u32) -> Self {
+        Self {
+            sub_name,
+            batch,
+            max_bytes: 0,
+            expires: Duration::from_secs(0),
+        }
+    }
+
+    pub fn with_max_bytes(mut self, max_bytes: u32) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    // 没有足够的消息时等待的时间, 按毫秒发送
+    pub fn with_expires(mut self, expires: Duration) -> Self {
+        self.expires = expires;
+        self
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl<'a> Frame for Fetch<'a> {
+    fn kind(&self) -> u8 {
+        STATE_FETCH
+    }
+
+    fn body_len(&self) -> usize {
+        U32_SIZE * 3 + U8_SIZE + self.sub_name.len()
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u32(self.batch);
+        buff.put_u32(self.max_bytes);
+        buff.put_u32(u32::try_from(self.expires.as_millis()).unwrap_or(u32::MAX));
+        buff.put_u8(self.sub_name.len() as u8);
+        buff.extend_from_slice(self.sub_name.as_bytes());
+    }
+}
+
+// 在 max_task_size 之外再允许服务器推送的消息数量
+#[derive(Debug)]
+pub struct Credit {
+    credit: u32,
+}
+
+impl Credit {
+    pub fn new(credit: u32) -> Self {
+        Self { credit }
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self)
+    }
+}
+
+impl Frame for Credit {
+    fn kind(&self) -> u8 {
+        STATE_CREDIT
+    }
+
+    fn body_len(&self) -> usize {
+        U32_SIZE
+    }
+
+    fn encode_body(&self, buff: &mut BytesMut) {
+        buff.put_u32(self.credit);
+    }
+}
+
+// 客户端可以发送的所有帧
+#[derive(Debug)]
+pub enum ClientFrame<'a> {
+    Info(&'a ClientConfig),
+    Ping,
+    Pong,
+    TurnPush,
+    TurnPull,
+    Ok,
+    Err(Err<'a>),
+    Sub(Sub<'a>),
+    Pub(Pub<'a, &'a [u8]>),
+    UnSub(UnSub<'a>),
+    Offset(Offset<'a>),
+    Ack(Ack<'a>),
+    Request(Request<'a>),
+    Reply(Reply<'a>),
+    Fetch(Fetch<'a>),
+    Credit(Credit),
+    Nack(Nack<'a>),
+}
+
+impl<'a> ClientFrame<'a> {
+    fn frame(&self) -> &dyn Frame {
+        match self {
+            ClientFrame::Info(config) => *config,
+            ClientFrame::Ping => &Bare(STATE_PING),
+            ClientFrame::Pong => &Bare(STATE_PONG),
+            ClientFrame::TurnPush => &Bare(STATE_TURN_PUSH),
+            ClientFrame::TurnPull => &Bare(STATE_TURN_PULL),
+            ClientFrame::Ok => &Bare(STATE_OK),
+            ClientFrame::Err(err) => err,
+            ClientFrame::Sub(sub) => sub,
+            ClientFrame::Pub(r#pub) => r#pub,
+            ClientFrame::UnSub(unsub) => unsub,
+            ClientFrame::Offset(offset) => offset,
+            ClientFrame::Ack(ack) => ack,
+            ClientFrame::Request(request) => request,
+            ClientFrame::Reply(reply) => reply,
+            ClientFrame::Fetch(fetch) => fetch,
+            ClientFrame::Credit(credit) => credit,
+            ClientFrame::Nack(nack) => nack,
+        }
+    }
+
+    // 编码后的总长度, 包括类型
+    pub fn encoded_len(&self) -> usize {
+        self.frame().encoded_len()
+    }
+
+    // 追加到已有的缓冲后面
+    pub fn encode_into(&self, buff: &mut BytesMut) {
+        self.frame().encode_into(buff);
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        encode(self.frame())
+    }
+
+    // 按照协商出来的版本编码
+    pub fn encoded_len_with(&self, version: Version) -> usize {
+        self.frame().encoded_len_with(version)
+    }
+
+    pub fn encode_into_with(&self, version: Version, buff: &mut BytesMut) {
+        self.frame().encode_into_with(version, buff);
+    }
 
+    pub fn encode_with(&self, version: Version) -> BytesMut {
+        let mut buff = BytesMut::with_capacity(self.encoded_len_with(version));
+        self.encode_into_with(version, &mut buff);
         buff
     }
 }
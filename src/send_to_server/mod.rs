@@ -1,2 +1,4 @@
+pub mod connection;
 pub mod decode;
 pub mod encode;
+pub mod request;
@@ -0,0 +1,157 @@
+use super::decode::{Message, Msg, Reply};
+use crate::clock::{Clock, SystemClock};
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+// 默认的应答超时
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 发出请求时需要的编号和收件箱
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ticket {
+    pub id: u64,
+    pub inbox: String,
+}
+
+// 收到的应答
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub id: u64,
+    pub payload: Bytes,
+}
+
+// 客户端未完成的请求
+// 每个请求的收件箱为 "{prefix}.{id}", 应答可以是 Reply 帧, 也可以是发到收件箱的 Msg
+#[derive(Debug)]
+pub struct Requests<C = SystemClock> {
+    clock: C,
+    prefix: String,
+    timeout: Duration,
+    next_id: u64,
+    pending: HashMap<u64, Instant>,
+}
+
+impl Requests<SystemClock> {
+    pub fn new<P>(prefix: P) -> Self
+    where
+        P: Into<String>,
+    {
+        Self::with_clock(prefix, SystemClock)
+    }
+}
+
+impl<C> Requests<C>
+where
+    C: Clock,
+{
+    pub fn with_clock<P>(prefix: P, clock: C) -> Self
+    where
+        P: Into<String>,
+    {
+        Self {
+            clock,
+            prefix: prefix.into(),
+            timeout: DEFAULT_TIMEOUT,
+            next_id: 0,
+            pending: HashMap::new(),
+        }
+    }
+
+    // 只影响之后发出的请求
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub fn get_timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn is_pending(&self, id: u64) -> bool {
+        self.pending.contains_key(&id)
+    }
+
+    // 分配一个新的请求
+    pub fn start(&mut self) -> Ticket {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.pending.insert(id, self.clock.now() + self.timeout);
+
+        Ticket {
+            id,
+            inbox: self.inbox(id),
+        }
+    }
+
+    pub fn inbox(&self, id: u64) -> String {
+        format!("{}.{}", self.prefix, id)
+    }
+
+    pub fn cancel(&mut self, id: u64) -> bool {
+        self.pending.remove(&id).is_some()
+    }
+
+    // 最早超时的时间
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending.values().min().copied()
+    }
+
+    // 取出一个已经超时的请求, 没有则返回 None
+    pub fn poll_timeout(&mut self) -> Option<u64> {
+        let now = self.clock.now();
+        let id = self
+            .pending
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .min_by_key(|(id, deadline)| (**deadline, **id))
+            .map(|(id, _)| *id)?;
+        self.pending.remove(&id);
+        Some(id)
+    }
+
+    // 只处理发给未完成请求的 Msg 和 Reply
+    pub fn receive(&mut self, message: &Message) -> Option<Response> {
+        match message {
+            Message::Msg(msg) => self.receive_msg(msg),
+            Message::Reply(reply) => self.receive_reply(reply),
+            _ => None,
+        }
+    }
+
+    pub fn receive_msg(&mut self, msg: &Msg) -> Option<Response> {
+        let id = self.parse_inbox(&msg.sub_name)?;
+        self.complete(id, &msg.payload)
+    }
+
+    // 编号和收件箱都要对上
+    pub fn receive_reply(&mut self, reply: &Reply) -> Option<Response> {
+        if self.parse_inbox(&reply.sub_name) != Some(reply.id) {
+            return None;
+        }
+        self.complete(reply.id, &reply.payload)
+    }
+
+    fn parse_inbox(&self, sub_name: &[u8]) -> Option<u64> {
+        let sub_name = std::str::from_utf8(sub_name).ok()?;
+        let id = sub_name
+            .strip_prefix(self.prefix.as_str())?
+            .strip_prefix('.')?;
+        id.parse().ok()
+    }
+
+    fn complete(&mut self, id: u64, payload: &Bytes) -> Option<Response> {
+        self.pending.remove(&id)?;
+        Some(Response {
+            id,
+            payload: payload.clone(),
+        })
+    }
+}
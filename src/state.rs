@@ -1,4 +1,4 @@
-use std::convert::{Into, TryInto};
+use std::convert::TryInto;
 use std::ops::{BitAnd, BitOrAssign};
 
 // 服务器信息
@@ -43,19 +43,83 @@ pub(crate) const STATE_TURN_PULL: u8 = 12;
 // 确认, 回答 turn_push 或 turn_pull
 pub(crate) const STATE_OK: u8 = 13;
 
+// 请求, 带上回复的订阅名称和请求号
+pub(crate) const STATE_REQUEST: u8 = 14;
+
+// 回复请求
+pub(crate) const STATE_REPLY: u8 = 15;
+
+// 带消息头的发布
+pub(crate) const STATE_HPUB: u8 = 16;
+
+// 带消息头的消息
+pub(crate) const STATE_HMSG: u8 = 17;
+
+// 订阅帧的标志位, 需要服务器确认
+pub(crate) const SUB_REPLY: u8 = 1;
+
+// 订阅帧的标志位, 订阅名称后面带有队列组名称
+pub(crate) const SUB_QUEUE: u8 = 2;
+
+// 确认订阅, 带上服务器分配的订阅号
+pub(crate) const STATE_SUB_ACK: u8 = 18;
+
+// 拉模式下请求一批消息
+pub(crate) const STATE_FETCH: u8 = 19;
+
+// 一次拉取结束, 带上结束的原因和发出的消息数量
+pub(crate) const STATE_FETCH_DONE: u8 = 20;
+
+// 客户端额外给出可以接收的消息数量
+pub(crate) const STATE_CREDIT: u8 = 21;
+
+// 客户端拒绝一条消息, 可以带上重新投递前的等待时间和原因
+pub(crate) const STATE_NACK: u8 = 22;
+
+// 帧类型是连续编号的, 用来区分不认识的类型和发错方向的类型
+pub(crate) fn is_frame_type(byte: u8) -> bool {
+    byte <= STATE_NACK
+}
+
 // 服务器解析协议状态
 #[derive(Debug)]
 pub(super) enum ServerState {
     // 客户端信息
     ClientInfo,
 
+    // 解析认证的用户名长度
+    ClientInfoUserLength,
+
+    // 解析认证的用户名
+    ClientInfoUser,
+
+    // 解析认证的证明长度
+    ClientInfoProofLength,
+
+    // 解析认证的证明
+    ClientInfoProof,
+
     Ping,
     Pong,
 
-    // 解析接受发布消息的消息号
+    // 解析请求拉取的消息号
     Offset,
+
+    // 解析请求拉取的订阅名称长度
+    OffsetSubNameLength,
+
+    // 解析请求拉取的订阅名称
+    OffsetSubName,
+
+    // 解析应答收到的消息号
     Ack,
 
+    // 解析应答的订阅名称长度
+    AckSubNameLength,
+
+    // 解析应答的订阅名称
+    AckSubName,
+
     // 解析错误
     Err,
 
@@ -80,7 +144,7 @@ pub(super) enum ServerState {
     // 解析发布内容
     PubMsg,
 
-    // 订阅
+    // 订阅, 先解析回复标志
     Sub,
 
     // 解析订阅名称的长度
@@ -89,7 +153,13 @@ pub(super) enum ServerState {
     // 解析订阅名称
     SubName,
 
-    // 解析取消订阅
+    // 解析队列组名称的长度
+    SubQueueLength,
+
+    // 解析队列组名称
+    SubQueue,
+
+    // 解析取消订阅, 先解析回复标志
     UnSub,
 
     // 解析取消订阅的数量
@@ -100,6 +170,84 @@ pub(super) enum ServerState {
 
     // 解析取消订阅名称
     UnSubName,
+
+    // 解析请求号
+    Request,
+
+    // 解析请求的订阅名称长度
+    RequestSubNameLength,
+
+    // 解析请求的订阅名称
+    RequestSubName,
+
+    // 解析回复的订阅名称长度
+    RequestReplyToLength,
+
+    // 解析回复的订阅名称
+    RequestReplyTo,
+
+    // 解析请求内容长度
+    RequestPayloadLength,
+
+    // 解析请求内容
+    RequestPayload,
+
+    // 解析回复的请求号
+    Reply,
+
+    // 解析回复的订阅名称长度
+    ReplySubNameLength,
+
+    // 解析回复的订阅名称
+    ReplySubName,
+
+    // 解析回复内容长度
+    ReplyPayloadLength,
+
+    // 解析回复内容
+    ReplyPayload,
+
+    // 解析带消息头的发布, 消息头之后和发布相同
+    HPub,
+
+    // 解析发布名称的长度
+    HPubSubNameLength,
+
+    // 解析发布名称
+    HPubSubName,
+
+    // 解析消息头长度
+    HPubHeadersLength,
+
+    // 解析消息头
+    HPubHeaders,
+
+    // 解析拉取的数量, 字节数上限和等待时间
+    Fetch,
+
+    // 解析拉取的订阅名称长度
+    FetchSubNameLength,
+
+    // 解析拉取的订阅名称
+    FetchSubName,
+
+    // 解析额外的消息数量
+    Credit,
+
+    // 解析拒绝的消息号和等待时间
+    Nack,
+
+    // 解析拒绝的订阅名称长度
+    NackSubNameLength,
+
+    // 解析拒绝的订阅名称
+    NackSubName,
+
+    // 解析拒绝原因的长度
+    NackReasonLength,
+
+    // 解析拒绝原因
+    NackReason,
 }
 
 impl TryInto<ServerState> for u8 {
@@ -119,6 +267,12 @@ impl TryInto<ServerState> for u8 {
             STATE_SUB => Ok(ServerState::Sub),
             STATE_PUB => Ok(ServerState::Pub),
             STATE_UNSUB => Ok(ServerState::UnSub),
+            STATE_REQUEST => Ok(ServerState::Request),
+            STATE_REPLY => Ok(ServerState::Reply),
+            STATE_HPUB => Ok(ServerState::HPub),
+            STATE_FETCH => Ok(ServerState::Fetch),
+            STATE_CREDIT => Ok(ServerState::Credit),
+            STATE_NACK => Ok(ServerState::Nack),
             _ => Err(()),
         }
     }
@@ -127,21 +281,53 @@ impl TryInto<ServerState> for u8 {
 #[derive(Debug)]
 pub(super) enum ClientState {
     ServerInfo,
+    ServerInfoNonceLength,
+    ServerInfoNonce,
     Ping,
     Pong,
     Msg,
     MsgOffset,
+    MsgSid,
     MsgSubLength,
     MsgSubName,
     MsgLength,
     MsgPayload,
     Offset,
+    OffsetSubLength,
+    OffsetSubName,
     Ack,
+    AckSubLength,
+    AckSubName,
     Err,
     ErrContent,
     TurnPush,
     TurnPull,
     Ok,
+    Request,
+    RequestSubLength,
+    RequestSubName,
+    RequestReplyToLength,
+    RequestReplyTo,
+    RequestLength,
+    RequestPayload,
+    Reply,
+    ReplySubLength,
+    ReplySubName,
+    ReplyLength,
+    ReplyPayload,
+    HMsg,
+    HMsgOffset,
+    HMsgSid,
+    HMsgSubLength,
+    HMsgSubName,
+    HMsgHeadersLength,
+    HMsgHeaders,
+    SubAck,
+    SubAckSubLength,
+    SubAckSubName,
+    FetchDone,
+    FetchDoneSubLength,
+    FetchDoneSubName,
 }
 
 impl TryInto<ClientState> for u8 {
@@ -159,6 +345,11 @@ impl TryInto<ClientState> for u8 {
             STATE_TURN_PULL => Ok(ClientState::TurnPull),
             STATE_TURN_PUSH => Ok(ClientState::TurnPush),
             STATE_OK => Ok(ClientState::Ok),
+            STATE_REQUEST => Ok(ClientState::Request),
+            STATE_REPLY => Ok(ClientState::Reply),
+            STATE_HMSG => Ok(ClientState::HMsg),
+            STATE_SUB_ACK => Ok(ClientState::SubAck),
+            STATE_FETCH_DONE => Ok(ClientState::FetchDone),
             _ => Err(()),
         }
     }
@@ -168,23 +359,53 @@ const SUPPORT_PUSH: u16 = 1;
 const SUPPORT_PULL: u16 = 2;
 const SUPPORT_TLS: u16 = 4;
 const SUPPORT_COMPRESS: u16 = 8;
+const SUPPORT_AUTH: u16 = 16;
 
 #[repr(u16)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Support {
     Push = SUPPORT_PUSH,
     Pull = SUPPORT_PULL,
     Tls = SUPPORT_TLS,
     Compress = SUPPORT_COMPRESS,
+
+    // 握手时用随机数做挑战应答认证
+    Auth = SUPPORT_AUTH,
+}
+
+impl Support {
+    // 所有已知的服务, 按位从低到高
+    pub const ALL: [Support; 5] = [
+        Support::Push,
+        Support::Pull,
+        Support::Tls,
+        Support::Compress,
+        Support::Auth,
+    ];
+
+    pub const fn bit(self) -> u16 {
+        self as u16
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Support::Push => "push",
+            Support::Pull => "pull",
+            Support::Tls => "tls",
+            Support::Compress => "compress",
+            Support::Auth => "auth",
+        }
+    }
 }
 
 impl BitOrAssign<Support> for u16 {
     fn bitor_assign(&mut self, rhs: Support) {
         match rhs {
-            Support::Push => (*self |= SUPPORT_PUSH),
+            Support::Push => *self |= SUPPORT_PUSH,
             Support::Pull => *self |= SUPPORT_PULL,
             Support::Tls => *self |= SUPPORT_TLS,
             Support::Compress => *self |= SUPPORT_COMPRESS,
+            Support::Auth => *self |= SUPPORT_AUTH,
         }
     }
 }
@@ -197,6 +418,52 @@ impl BitAnd<Support> for u16 {
             Support::Pull => (self & SUPPORT_PULL) == SUPPORT_PULL,
             Support::Tls => (self & SUPPORT_TLS) == SUPPORT_TLS,
             Support::Compress => (self & SUPPORT_COMPRESS) == SUPPORT_COMPRESS,
+            Support::Auth => (self & SUPPORT_AUTH) == SUPPORT_AUTH,
+        }
+    }
+}
+
+// 投递模式, 由 turn_push / turn_pull 切换
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Push,
+    Pull,
+}
+
+// 拉取结束的原因
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FetchStatus {
+    // 等到超时也没有消息
+    NoMessages = 0,
+
+    // 数量或者字节数达到上限, 或者超时前已经发出了部分消息
+    BatchComplete = 1,
+}
+
+impl FetchStatus {
+    pub const fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub const fn from_u8(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(FetchStatus::NoMessages),
+            1 => Some(FetchStatus::BatchComplete),
+            _ => None,
         }
     }
 }
+
+// 连接所处的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    // 等待对方的握手信息
+    Handshake,
+
+    // 握手完成
+    Established,
+
+    // 出现协议错误后关闭
+    Closed,
+}
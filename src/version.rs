@@ -0,0 +1,78 @@
+use std::convert::TryFrom;
+use std::fmt;
+use thiserror::Error;
+
+// 版本不兼容时 Err 帧的内容
+pub const INCOMPATIBLE_VERSION: &str = "incompatible version";
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum VersionError {
+    #[error("unknown protocol version {0}")]
+    Unknown(u8),
+
+    #[error("no common protocol version, local speaks {min}..={max}, peer speaks {peer}")]
+    Incompatible { min: u8, max: u8, peer: u8 },
+}
+
+// 协议版本, 握手帧本身始终使用第一版格式
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Version {
+    // |type|body|
+    V1 = 1,
+
+    // |type|flags|u32 length|body|, 不认识的帧类型可以按长度跳过
+    V2 = 2,
+}
+
+impl Version {
+    // 本实现支持的最高版本
+    pub const LATEST: Version = Version::V2;
+
+    pub const fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    // 双方都只声明自己支持的最高版本, 并向下兼容到 min
+    // 取双方最高版本中较小的一个, 低于 min 或者本实现不认识时失败
+    pub fn negotiate(min: u8, max: u8, peer: u8) -> Result<Version, VersionError> {
+        let version = max.min(peer).min(Self::LATEST.as_u8());
+
+        if version < min {
+            Err(VersionError::Incompatible { min, max, peer })
+        } else {
+            Version::try_from(version)
+        }
+    }
+
+    // 服务器检查客户端选择的版本
+    pub fn accept(min: u8, max: u8, chosen: u8) -> Result<Version, VersionError> {
+        if chosen < min || chosen > max {
+            Err(VersionError::Incompatible {
+                min,
+                max,
+                peer: chosen,
+            })
+        } else {
+            Version::try_from(chosen)
+        }
+    }
+}
+
+impl TryFrom<u8> for Version {
+    type Error = VersionError;
+
+    fn try_from(version: u8) -> Result<Self, Self::Error> {
+        match version {
+            1 => Ok(Version::V1),
+            2 => Ok(Version::V2),
+            _ => Err(VersionError::Unknown(version)),
+        }
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.as_u8())
+    }
+}
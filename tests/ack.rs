@@ -0,0 +1,87 @@
+#[test]
+fn server_decode_ack() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::Ack;
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(Ack::new(9, "test").encode());
+
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Ack(ack) => {
+            assert_eq!(ack.offset, 9);
+            assert_eq!(&ack.sub_name, &b"test"[..]);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn server_decode_ack_chunk() {
+    use protocol::send_to_client::decode::{Decode, Message};
+
+    let mut decode = Decode::new(0);
+
+    for i in 0..100 {
+        decode.set_buff([6]);
+        assert!(decode.iter().next().is_none());
+
+        decode.set_buff(u64::to_be_bytes(i));
+        assert!(decode.iter().next().is_none());
+
+        decode.set_buff([4]);
+        assert!(decode.iter().next().is_none());
+
+        decode.set_buff(b"test");
+        match decode.iter().next().unwrap().unwrap() {
+            Message::Ack(ack) => {
+                assert_eq!(ack.offset, i);
+                assert_eq!(&ack.sub_name, &b"test"[..]);
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+}
+
+#[test]
+fn client_decode_ack() {
+    use protocol::send_to_client::encode::Ack;
+    use protocol::send_to_server::decode::{Decode, Message};
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(Ack::new(9, b"test").encode());
+
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Ack(ack) => {
+            assert_eq!(ack.offset, 9);
+            assert_eq!(&ack.sub_name, &b"test"[..]);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn client_decode_ack_chunk() {
+    use protocol::send_to_server::decode::{Decode, Message};
+
+    let mut decode = Decode::new(0);
+
+    for i in 0..100 {
+        decode.set_buff([6]);
+        assert!(decode.iter().next().is_none());
+
+        decode.set_buff(u64::to_be_bytes(i));
+        assert!(decode.iter().next().is_none());
+
+        decode.set_buff([4]);
+        assert!(decode.iter().next().is_none());
+
+        decode.set_buff(b"test");
+        match decode.iter().next().unwrap().unwrap() {
+            Message::Ack(ack) => {
+                assert_eq!(ack.offset, i);
+                assert_eq!(&ack.sub_name, &b"test"[..]);
+            }
+            message => panic!("unexpected message {:?}", message),
+        }
+    }
+}
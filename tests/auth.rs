@@ -0,0 +1,238 @@
+mod common;
+
+use bytes::Bytes;
+use protocol::auth::{sign, verify, Authenticator, StaticAuth, NONCE_LEN, PROOF_LEN};
+use protocol::error::ErrorCode;
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::send_to_client::connection::{Error, Event as ServerEvent, ServerConnection};
+use protocol::send_to_client::decode as server_decode;
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::connection::ClientConnection;
+use protocol::send_to_server::decode as client_decode;
+use protocol::send_to_server::encode::ClientConfig;
+use protocol::state::{Phase, Support};
+
+fn auth_server() -> ServerConnection<StaticAuth> {
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    server_config.support_auth();
+    let auth = StaticAuth::new()
+        .with_token("s3cret")
+        .with_user("alice", "passw0rd");
+    ServerConnection::with_authenticator(server_config, auth)
+}
+
+// 完成握手, 返回服务器的结果
+fn handshake(
+    server: &mut ServerConnection<StaticAuth>,
+    client_config: ClientConfig,
+) -> Option<Result<ServerEvent, Error>> {
+    common::handshake(server, &mut ClientConnection::new(client_config))
+}
+
+fn auth_client() -> ClientConfig {
+    let mut client_config = ClientConfig::default();
+    client_config.support_push();
+    client_config
+}
+
+#[test]
+fn hmac_sha256() {
+    // RFC 4231 第二组测试数据
+    let proof = sign(b"Jefe", b"what do ya want for nothing?");
+    assert_eq!(
+        &proof[..],
+        &b"\x5b\xdc\xc1\x46\xbf\x60\x75\x4e\x6a\x04\x24\x26\x08\x95\x75\xc7\x5a\x00\x3f\x08\x9d\x27\x39\x83\x9d\xec\x58\xb9\x64\xec\x38\x43"[..]
+    );
+    assert!(verify(b"Jefe", b"what do ya want for nothing?", &proof));
+    assert!(!verify(b"jefe", b"what do ya want for nothing?", &proof));
+    assert!(!verify(
+        b"Jefe",
+        b"what do ya want for nothing?",
+        &proof[..16]
+    ));
+}
+
+#[test]
+fn static_auth() {
+    let auth = StaticAuth::new().with_user("alice", "passw0rd");
+    let proof = sign(b"passw0rd", b"nonce");
+    assert!(auth.authenticate(b"nonce", b"alice", &proof));
+    assert!(!auth.authenticate(b"other", b"alice", &proof));
+    assert!(!auth.authenticate(b"nonce", b"bob", &proof));
+
+    // 没有设置令牌时令牌认证都失败
+    assert!(!auth.authenticate(b"nonce", b"", &sign(b"", b"nonce")));
+    assert!(!format!("{:?}", auth).contains("passw0rd"));
+}
+
+#[test]
+fn server_info_nonce() {
+    let mut server_config = ServerConfig::default();
+    server_config.support_auth();
+    server_config.set_nonce(Bytes::from_static(b"abcd"));
+    let buff = server_config.encode();
+    assert_eq!(&buff[..], &b"\x00\x01\x00\x10\xff\xff\xff\xff\x04abcd"[..]);
+    assert_eq!(
+        parse_frame(&buff).unwrap(),
+        (
+            FrameRef::ServerInfo {
+                version: 1,
+                support: 16,
+                max_message_length: u32::MAX,
+                nonce: b"abcd",
+            },
+            buff.len()
+        )
+    );
+
+    let mut decode = client_decode::Decode::new(0);
+    for chunk in buff.chunks(3) {
+        decode.set_buff(chunk);
+        if let Some(message) = decode.iter().next() {
+            match message.unwrap() {
+                client_decode::Message::Info(info) => assert_eq!(&info.nonce[..], b"abcd"),
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+    }
+}
+
+#[test]
+fn client_info_proof() {
+    let mut client_config = ClientConfig::default();
+    client_config.max_task_size(1);
+    client_config.set_user("alice", "passw0rd");
+    client_config.set_nonce(Bytes::from_static(b"abcd"));
+    let buff = client_config.encode();
+    let proof = sign(b"passw0rd", b"abcd");
+    assert_eq!(buff.len(), 5 + 1 + 5 + 1 + PROOF_LEN);
+    assert_eq!(
+        parse_frame(&buff).unwrap(),
+        (
+            FrameRef::ClientInfo {
+                version: 1,
+                support: 16,
+                max_message_size: 1,
+                user: b"alice",
+                proof: &proof,
+            },
+            buff.len()
+        )
+    );
+
+    let mut decode = server_decode::Decode::new(0);
+    let mut messages = Vec::new();
+    for chunk in buff.chunks(3) {
+        decode.set_buff(chunk);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+    match &messages[..] {
+        [server_decode::Message::Info(info)] => {
+            assert_eq!(&info.user[..], b"alice");
+            assert_eq!(&info.proof[..], &proof[..]);
+        }
+        messages => panic!("unexpected messages {:?}", messages),
+    }
+
+    // 凭证不出现在调试输出中
+    assert!(!format!("{:?}", client_config).contains("passw0rd"));
+}
+
+#[test]
+fn token_auth() {
+    let mut server = auth_server();
+    assert_eq!(server.nonce().len(), NONCE_LEN);
+
+    let mut client_config = auth_client();
+    client_config.set_token("s3cret");
+    match handshake(&mut server, client_config) {
+        Some(Ok(ServerEvent::Connected(info))) => {
+            assert!(info.user.is_empty());
+            assert!(info.capabilities().contains(Support::Auth));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert!(server.is_connected());
+    assert!(server.capabilities().contains(Support::Auth));
+}
+
+#[test]
+fn user_auth() {
+    let mut server = auth_server();
+    let mut client_config = auth_client();
+    client_config.set_user("alice", "passw0rd");
+    match handshake(&mut server, client_config) {
+        Some(Ok(ServerEvent::Connected(info))) => assert_eq!(&info.user[..], b"alice"),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn wrong_password() {
+    let mut server = auth_server();
+    let mut client_config = auth_client();
+    client_config.set_user("alice", "password");
+    assert!(matches!(
+        handshake(&mut server, client_config),
+        Some(Err(Error::AuthFailed))
+    ));
+    assert_eq!(server.phase(), Phase::Closed);
+
+    // 关闭前告诉客户端认证失败, 握手时还是第一版, 没有错误码
+    let mut decode = client_decode::Decode::new(0);
+    decode.set_buff(server.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        client_decode::Message::Err(erro) => {
+            assert_eq!(
+                &erro.msg[..],
+                ErrorCode::AuthFailure.description().as_bytes()
+            )
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn nonce_per_connection() {
+    // 用别的连接的随机数计算的证明不能通过
+    let other = auth_server();
+    let mut client_config = auth_client();
+    client_config.set_token("s3cret");
+    client_config.set_nonce(other.nonce().clone());
+
+    let mut server = auth_server();
+    assert_ne!(server.nonce(), other.nonce());
+    server.receive(client_config.encode());
+    assert!(matches!(server.poll_event(), Some(Err(Error::AuthFailed))));
+}
+
+#[test]
+fn missing_credentials() {
+    // 客户端没有开启认证
+    let mut server = auth_server();
+    assert!(matches!(
+        handshake(&mut server, auth_client()),
+        Some(Err(Error::AuthFailed))
+    ));
+    assert_eq!(server.phase(), Phase::Closed);
+}
+
+#[test]
+fn server_without_auth() {
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    let mut server = ServerConnection::new(server_config);
+
+    // 服务器没有开启认证时客户端不能要求认证
+    let mut client_config = auth_client();
+    client_config.set_token("s3cret");
+    let mut client = ClientConnection::new(client_config);
+    client.receive(server.poll_transmit().unwrap());
+    assert!(matches!(
+        client.poll_event(),
+        Some(Err(protocol::send_to_server::connection::Error::Negotiate(
+            _
+        )))
+    ));
+}
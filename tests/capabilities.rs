@@ -0,0 +1,145 @@
+use protocol::capabilities::{negotiate, Capabilities, NegotiateError};
+use protocol::send_to_client::connection::{Error as ServerError, ServerConnection};
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::connection::{ClientConnection, Error as ClientError};
+use protocol::send_to_server::encode::ClientConfig;
+use protocol::state::{Phase, Support};
+
+#[test]
+fn capabilities_set() {
+    let mut capabilities = Capabilities::empty();
+    assert!(capabilities.is_empty());
+
+    capabilities.insert(Support::Push);
+    capabilities.insert(Support::Tls);
+    assert!(capabilities.contains(Support::Push));
+    assert!(!capabilities.contains(Support::Pull));
+    assert_eq!(capabilities.bits(), 5);
+
+    capabilities.remove(Support::Push);
+    assert_eq!(capabilities, Capabilities::from(Support::Tls));
+
+    let collected = vec![Support::Pull, Support::Compress]
+        .into_iter()
+        .collect::<Capabilities>();
+    assert_eq!(collected.bits(), 10);
+    assert_eq!(
+        collected.iter().collect::<Vec<_>>(),
+        vec![Support::Pull, Support::Compress]
+    );
+}
+
+#[test]
+fn capabilities_unknown_bits() {
+    let capabilities = Capabilities::from_bits(0x8003);
+
+    assert_eq!(capabilities.bits(), 0x8003);
+    assert_eq!(capabilities.unknown_bits(), 0x8000);
+    assert_eq!(
+        capabilities.iter().collect::<Vec<_>>(),
+        vec![Support::Push, Support::Pull]
+    );
+    assert_eq!(u16::from(capabilities), 0x8003);
+}
+
+#[test]
+fn capabilities_display() {
+    assert_eq!(Capabilities::empty().to_string(), "none");
+    assert_eq!(Capabilities::from_bits(3).to_string(), "push|pull");
+    assert_eq!(Capabilities::from_bits(0x8004).to_string(), "tls|0x8000");
+    assert_eq!(Capabilities::from_bits(0x100).to_string(), "0x100");
+}
+
+#[test]
+fn capabilities_negotiate() {
+    let offered = Capabilities::from_bits(0x8007);
+    let demanded = Capabilities::from_bits(0x4001);
+
+    // 不认识的位不算作要求, 只保留双方都有的
+    assert_eq!(
+        Capabilities::negotiate(offered, demanded),
+        Ok(Capabilities::from(Support::Push))
+    );
+
+    assert_eq!(
+        Capabilities::negotiate(Support::Push.into(), Capabilities::from_bits(3)),
+        Err(NegotiateError::Unsupported(Support::Pull.into()))
+    );
+}
+
+#[test]
+fn capabilities_negotiate_info() {
+    use protocol::send_to_client::decode::{Decode as ServerDecode, Message as ServerMessage};
+    use protocol::send_to_server::decode::{Decode as ClientDecode, Message as ClientMessage};
+
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    server_config.support_tls();
+    let mut client_decode = ClientDecode::new(0);
+    client_decode.set_buff(server_config.encode());
+
+    let mut client_config = ClientConfig::default();
+    client_config.support_push();
+    let mut server_decode = ServerDecode::new(0);
+    server_decode.set_buff(client_config.encode());
+
+    match (
+        client_decode.iter().next().unwrap().unwrap(),
+        server_decode.iter().next().unwrap().unwrap(),
+    ) {
+        (ClientMessage::Info(server_info), ServerMessage::Info(client_info)) => {
+            assert_eq!(
+                negotiate(&server_info, &client_info),
+                Ok(Capabilities::from(Support::Push))
+            );
+        }
+        messages => panic!("unexpected messages {:?}", messages),
+    }
+}
+
+#[test]
+fn client_connection_reject_pull() {
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+
+    let mut client_config = ClientConfig::default();
+    client_config.support_pull();
+
+    let mut connection = ClientConnection::new(client_config);
+    connection.receive(server_config.encode());
+
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(ClientError::Negotiate(NegotiateError::Unsupported(_))))
+    ));
+    assert_eq!(connection.phase(), Phase::Closed);
+    assert!(connection.poll_transmit().is_none());
+}
+
+#[test]
+fn server_connection_reject_pull() {
+    use protocol::send_to_server::decode::{Decode, Message};
+
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+
+    let mut client_config = ClientConfig::default();
+    client_config.support_pull();
+
+    let mut connection = ServerConnection::new(server_config);
+    connection.receive(client_config.encode());
+
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(ServerError::Negotiate(NegotiateError::Unsupported(_))))
+    ));
+    assert_eq!(connection.phase(), Phase::Closed);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Info(_)))));
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(&erro.msg, &b"unsupported capabilities"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
@@ -0,0 +1,163 @@
+use protocol::send_to_client::decode::{Decode, Message};
+use protocol::send_to_client::encode::{Msg, Ok, Ping, Pong, ServerConfig};
+use protocol::send_to_server::connection::{ClientConnection, Error, Event};
+use protocol::send_to_server::encode::ClientConfig;
+use protocol::state::{Mode, Phase};
+
+fn connect() -> (ClientConnection, Decode) {
+    let mut client_config = ClientConfig::default();
+    client_config.support_push();
+    client_config.support_pull();
+    client_config.max_task_size(10);
+
+    let mut connection = ClientConnection::new(client_config);
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    server_config.support_pull();
+    connection.receive(server_config.encode());
+
+    match connection.poll_event().unwrap().unwrap() {
+        Event::Connected(info) => {
+            assert_eq!(info.version, 1);
+            assert_eq!(info.support, 3);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    // 握手后客户端要回复自己的信息
+    let mut decode = Decode::new(0);
+    decode.set_buff(connection.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Info(info) => {
+            assert_eq!(info.support, 3);
+            assert_eq!(info.max_message_size, 10);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    (connection, decode)
+}
+
+#[test]
+fn client_connection_handshake() {
+    let (connection, _) = connect();
+    assert!(connection.is_connected());
+    assert_eq!(connection.mode(), Mode::Push);
+}
+
+#[test]
+fn client_connection_handshake_chunk() {
+    let mut connection = ClientConnection::new(ClientConfig::default());
+    let server_info = ServerConfig::default().encode();
+
+    for byte in server_info.iter() {
+        assert!(connection.poll_event().is_none());
+        connection.receive([*byte]);
+    }
+
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Ok(Event::Connected(_)))
+    ));
+    assert_eq!(connection.phase(), Phase::Established);
+}
+
+#[test]
+fn client_connection_not_connected() {
+    let mut connection = ClientConnection::new(ClientConfig::default());
+
+    assert!(matches!(
+        connection.subscribe("test"),
+        Err(Error::NotConnected)
+    ));
+    assert!(matches!(connection.ping(), Err(Error::NotConnected)));
+    assert!(connection.poll_transmit().is_none());
+}
+
+#[test]
+fn client_connection_msg_before_info() {
+    let mut connection = ClientConnection::new(ClientConfig::default());
+    connection.receive(Msg::new(1, b"test", b"hello").encode());
+
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(Error::UnexpectedFrame {
+            frame: "msg",
+            phase: Phase::Handshake,
+        }))
+    ));
+    assert_eq!(connection.phase(), Phase::Closed);
+    assert!(connection.poll_event().is_none());
+}
+
+#[test]
+fn client_connection_answer_ping() {
+    let (mut connection, mut decode) = connect();
+
+    connection.receive(Ping::encode());
+    assert!(connection.poll_event().is_none());
+
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Pong))));
+
+    connection.ping().unwrap();
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Ping))));
+
+    connection.receive(Pong::encode());
+    assert!(matches!(connection.poll_event(), Some(Ok(Event::Pong))));
+}
+
+#[test]
+fn client_connection_msg() {
+    let (mut connection, mut decode) = connect();
+
+    connection.subscribe("test").unwrap();
+    decode.set_buff(connection.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Sub(sub) => assert_eq!(&sub.name, &b"test"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    connection.receive(Msg::new(3, b"test", b"hello").encode());
+    match connection.poll_event().unwrap().unwrap() {
+        Event::Msg(msg) => {
+            assert_eq!(msg.offset, 3);
+            assert_eq!(&msg.sub_name, &b"test"[..]);
+            assert_eq!(&msg.payload, &b"hello"[..]);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn client_connection_turn_pull() {
+    let (mut connection, mut decode) = connect();
+
+    connection.turn_pull().unwrap();
+    assert!(matches!(connection.turn_push(), Err(Error::SwitchPending)));
+
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::TurnPull))));
+
+    connection.receive(Ok::encode());
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Ok(Event::ModeChanged(Mode::Pull)))
+    ));
+    assert_eq!(connection.mode(), Mode::Pull);
+}
+
+#[test]
+fn client_connection_unexpected_ok() {
+    let (mut connection, _) = connect();
+
+    connection.receive(Ok::encode());
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(Error::UnexpectedFrame {
+            frame: "ok",
+            phase: Phase::Established,
+        }))
+    ));
+}
@@ -0,0 +1,149 @@
+#![cfg(feature = "tokio")]
+
+use bytes::BytesMut;
+use protocol::codec::{ClientCodec, Error, ServerCodec};
+use protocol::send_to_client::encode::{Msg, ServerConfig, ServerFrame};
+use protocol::send_to_server::encode::{ClientConfig, ClientFrame, Pub, Sub};
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn client_codec_round_trip() {
+    use protocol::send_to_server::decode::Message;
+
+    let mut server_codec = ServerCodec::new();
+    let mut client_codec = ClientCodec::new();
+    let mut buff = BytesMut::new();
+
+    let server_config = ServerConfig::default();
+    server_codec
+        .encode(ServerFrame::Info(&server_config), &mut buff)
+        .unwrap();
+    server_codec
+        .encode(ServerFrame::Msg(Msg::new(3, b"test", b"hello")), &mut buff)
+        .unwrap();
+    server_codec.encode(ServerFrame::Ping, &mut buff).unwrap();
+
+    assert!(matches!(
+        client_codec.decode(&mut buff).unwrap(),
+        Some(Message::Info(_))
+    ));
+    assert!(buff.is_empty());
+
+    match client_codec.decode(&mut buff).unwrap() {
+        Some(Message::Msg(msg)) => {
+            assert_eq!(msg.offset, 3);
+            assert_eq!(&msg.payload, &b"hello"[..]);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(
+        client_codec.decode(&mut buff).unwrap(),
+        Some(Message::Ping)
+    ));
+    assert!(client_codec.decode(&mut buff).unwrap().is_none());
+}
+
+#[test]
+fn server_codec_round_trip() {
+    use protocol::send_to_client::decode::Message;
+
+    let mut server_codec = ServerCodec::new();
+    let mut client_codec = ClientCodec::new();
+    let mut buff = BytesMut::new();
+
+    let client_config = ClientConfig::default();
+    client_codec
+        .encode(ClientFrame::Info(&client_config), &mut buff)
+        .unwrap();
+    client_codec
+        .encode(ClientFrame::Sub(Sub::new("test")), &mut buff)
+        .unwrap();
+    client_codec
+        .encode(ClientFrame::Pub(Pub::new("test", b"qweasd")), &mut buff)
+        .unwrap();
+
+    assert!(matches!(
+        server_codec.decode(&mut buff).unwrap(),
+        Some(Message::Info(_))
+    ));
+    assert!(matches!(
+        server_codec.decode(&mut buff).unwrap(),
+        Some(Message::Sub(_))
+    ));
+    match server_codec.decode(&mut buff).unwrap() {
+        Some(Message::Pub(r#pub)) => assert_eq!(&r#pub.msg, &b"qweasd"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn server_codec_chunk() {
+    use protocol::send_to_client::decode::Message;
+
+    let mut server_codec = ServerCodec::new();
+    let encoded = Pub::new("test", b"qweasd").encode();
+
+    for byte in encoded[..encoded.len() - 1].iter() {
+        let mut buff = BytesMut::from(&[*byte][..]);
+        assert!(server_codec.decode(&mut buff).unwrap().is_none());
+    }
+
+    let mut buff = BytesMut::from(&encoded[encoded.len() - 1..]);
+    assert!(matches!(
+        server_codec.decode(&mut buff).unwrap(),
+        Some(Message::Pub(_))
+    ));
+}
+
+#[test]
+fn server_codec_error() {
+    let mut server_codec = ServerCodec::new();
+    let mut buff = BytesMut::from(&[u8::MAX][..]);
+
+    assert!(server_codec.decode(&mut buff).is_err());
+}
+
+#[test]
+fn truncated_at_eof() {
+    let mut server_codec = ServerCodec::new();
+    let encoded = Pub::new("test", b"qweasd").encode();
+    let mut buff = BytesMut::from(&encoded[..encoded.len() - 1]);
+
+    assert!(server_codec.decode(&mut buff).unwrap().is_none());
+    assert!(matches!(
+        server_codec.decode_eof(&mut buff),
+        Err(Error::Truncated)
+    ));
+
+    // 完整的帧之后结束不算错误
+    let mut client_codec = ClientCodec::new();
+    let mut buff = BytesMut::new();
+    server_codec.encode(ServerFrame::Ping, &mut buff).unwrap();
+    assert!(client_codec.decode_eof(&mut buff).unwrap().is_some());
+    assert!(client_codec.decode_eof(&mut buff).unwrap().is_none());
+}
+
+#[test]
+fn codec_limits() {
+    use protocol::error::{ErrorKind, ErrorPolicy};
+    use protocol::send_to_client::decode::Message;
+
+    let mut server_codec = ServerCodec::new();
+    server_codec.set_max_message_length(4);
+    server_codec.set_error_policy(ErrorPolicy::Resync);
+
+    // 超长的消息跳过之后继续解析下一帧
+    let mut buff = Pub::new("test", b"qweasd").encode();
+    buff.extend_from_slice(&Pub::new("test", b"qwe").encode());
+    match server_codec.decode(&mut buff) {
+        Err(Error::Decode(e)) => assert!(matches!(
+            e.kind(),
+            ErrorKind::PayloadTooLarge { limit: 4, got: 6 }
+        )),
+        result => panic!("unexpected result {:?}", result),
+    }
+    match server_codec.decode(&mut buff).unwrap() {
+        Some(Message::Pub(r#pub)) => assert_eq!(&r#pub.msg, &b"qwe"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
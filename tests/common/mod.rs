@@ -0,0 +1,112 @@
+// 多个测试文件共用的辅助代码, 每个测试文件只用到其中一部分
+#![allow(dead_code)]
+
+use protocol::auth::Authenticator;
+use protocol::clock::{Clock, SystemClock};
+use protocol::send_to_client::connection::{
+    Error as ServerError, Event as ServerEvent, ServerConnection,
+};
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::connection::{ClientConnection, Event as ClientEvent};
+use protocol::send_to_server::encode::ClientConfig;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+// 手动推进的时钟, clone 出来的时钟共享同一个时间
+#[derive(Debug, Clone)]
+pub struct FakeClock {
+    start: Instant,
+    elapsed: Rc<Cell<Duration>>,
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: Rc::new(Cell::new(Duration::from_secs(0))),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.elapsed.set(self.elapsed.get() + duration);
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed.get()
+    }
+}
+
+// 交换握手帧, 客户端必须握手成功, 返回服务器的结果
+pub fn handshake<A, C>(
+    server: &mut ServerConnection<A>,
+    client: &mut ClientConnection<C>,
+) -> Option<Result<ServerEvent, ServerError>>
+where
+    A: Authenticator,
+    C: Clock,
+{
+    client.receive(server.poll_transmit().unwrap());
+    assert!(matches!(
+        client.poll_event(),
+        Some(Ok(ClientEvent::Connected(_)))
+    ));
+    server.receive(client.poll_transmit().unwrap());
+    server.poll_event()
+}
+
+pub fn connect_with_clock<C>(
+    server_config: ServerConfig,
+    client_config: ClientConfig,
+    clock: C,
+) -> (ServerConnection, ClientConnection<C>)
+where
+    C: Clock,
+{
+    let mut server = ServerConnection::new(server_config);
+    let mut client = ClientConnection::with_clock(client_config, clock);
+    assert!(matches!(
+        handshake(&mut server, &mut client),
+        Some(Ok(ServerEvent::Connected(_)))
+    ));
+    (server, client)
+}
+
+pub fn connect(
+    server_config: ServerConfig,
+    client_config: ClientConfig,
+) -> (ServerConnection, ClientConnection) {
+    connect_with_clock(server_config, client_config, SystemClock)
+}
+
+// 把客户端发出的帧交给服务器, 返回服务器的事件
+pub fn to_server<A, C>(
+    server: &mut ServerConnection<A>,
+    client: &mut ClientConnection<C>,
+) -> Vec<ServerEvent>
+where
+    A: Authenticator,
+    C: Clock,
+{
+    server.receive(client.poll_transmit().unwrap());
+    std::iter::from_fn(|| server.poll_event())
+        .map(Result::unwrap)
+        .collect()
+}
+
+// 把服务器发出的帧交给客户端, 返回客户端的事件
+pub fn to_client<A, C>(
+    server: &mut ServerConnection<A>,
+    client: &mut ClientConnection<C>,
+) -> Vec<ClientEvent>
+where
+    A: Authenticator,
+    C: Clock,
+{
+    client.receive(server.poll_transmit().unwrap());
+    std::iter::from_fn(|| client.poll_event())
+        .map(Result::unwrap)
+        .collect()
+}
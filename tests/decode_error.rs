@@ -0,0 +1,140 @@
+use bytes::{BufMut, BytesMut};
+
+#[test]
+fn server_unknown_frame_type() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, Message};
+    use protocol::send_to_server::encode::Ping;
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(Ping::encode());
+    decode.set_buff([u8::MAX]);
+
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Ping))));
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnknownFrameType(u8::MAX));
+    assert_eq!(error.offset(), 1);
+}
+
+#[test]
+fn server_unexpected_frame() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind};
+    use protocol::send_to_client::encode::Msg;
+
+    // 服务器不应该收到消息帧
+    let mut decode = Decode::new(0);
+    decode.set_buff(Msg::new(1, b"test", b"test").encode());
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnexpectedFrame(4));
+    assert_eq!(error.offset(), 0);
+}
+
+#[test]
+fn server_invalid_subject() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+    use protocol::send_to_server::encode::Sub;
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    let sub = Sub::new("test").encode();
+    decode.set_buff(&sub);
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Sub(_)))));
+
+    // 空的订阅名称
+    decode.set_buff([7, 0, 0]);
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::InvalidSubject);
+    assert_eq!(error.offset(), sub.len() as u64);
+
+    // 不是utf8的发布名称
+    let mut buff = BytesMut::new();
+    buff.put_u8(8);
+    buff.put_u8(2);
+    buff.extend_from_slice(&[0xff, 0xfe]);
+    buff.put_u32(0);
+    decode.set_buff(&buff);
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::InvalidSubject);
+    assert_eq!(error.offset(), sub.len() as u64 + 3);
+}
+
+#[test]
+fn server_error_offset_chunk() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, Message};
+    use protocol::send_to_server::encode::Pub;
+
+    let publish = Pub::new("test", "qweasd").encode();
+    let mut decode = Decode::new(0);
+
+    // 一个字节一个字节的送入, 位置仍然正确
+    for byte in publish.iter() {
+        decode.set_buff([*byte]);
+        if let Some(message) = decode.iter().next() {
+            assert!(matches!(message, Ok(Message::Pub(_))));
+        }
+    }
+
+    decode.set_buff([8, 4]);
+    assert!(decode.iter().next().is_none());
+    decode.set_buff(b"te");
+    assert!(decode.iter().next().is_none());
+    decode.set_buff([0xff, 0xff]);
+    assert!(decode.iter().next().is_none());
+
+    // 订阅名称在整个帧读完之后才检查
+    decode.set_buff([0, 0, 0, 0]);
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::InvalidSubject);
+    assert_eq!(error.offset(), publish.len() as u64);
+}
+
+#[test]
+fn client_unknown_frame_type() {
+    use protocol::send_to_server::decode::{Decode, ErrorKind};
+
+    let mut decode = Decode::new(0);
+    decode.set_buff([u8::MAX]);
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnknownFrameType(u8::MAX));
+    assert_eq!(error.offset(), 0);
+    assert_eq!(
+        error.to_string(),
+        "unknown frame type 255 in frame at byte 0"
+    );
+}
+
+#[test]
+fn client_unexpected_frame() {
+    use protocol::send_to_client::encode::Ping;
+    use protocol::send_to_server::decode::{Decode, ErrorKind, Message};
+    use protocol::send_to_server::encode::Sub;
+
+    // 客户端不应该收到订阅帧
+    let mut decode = Decode::new(0);
+    decode.set_buff(Ping::encode());
+    decode.set_buff(Sub::new("test").encode());
+
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Ping))));
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnexpectedFrame(7));
+    assert_eq!(error.offset(), 1);
+}
+
+#[test]
+fn client_invalid_subject() {
+    use protocol::send_to_server::decode::{Decode, ErrorKind};
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(4);
+    buff.put_u64(9);
+    buff.put_u8(0);
+    buff.put_u32(0);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(&buff);
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::InvalidSubject);
+    assert_eq!(error.offset(), 0);
+}
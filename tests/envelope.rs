@@ -0,0 +1,314 @@
+use bytes::{BufMut, BytesMut};
+use protocol::error::ErrorCode;
+use protocol::version::Version;
+
+#[test]
+fn envelope_encode() {
+    use protocol::send_to_server::encode::{ClientFrame, Sub};
+
+    let sub = ClientFrame::Sub(Sub::new("test"));
+    let buff = sub.encode_with(Version::V2);
+    assert_eq!(
+        &buff[..],
+        &[7, 0, 0, 0, 0, 6, 0, 4, b't', b'e', b's', b't'][..]
+    );
+    assert_eq!(buff.len(), sub.encoded_len_with(Version::V2));
+
+    // 第一版没有帧头
+    assert_eq!(sub.encode_with(Version::V1), sub.encode());
+    assert_eq!(
+        &ClientFrame::Ping.encode_with(Version::V2)[..],
+        &[2, 0, 0, 0, 0, 0]
+    );
+}
+
+#[test]
+fn envelope_client_frames() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::{Ack, ClientFrame, Err, Offset, Pub, Sub, UnSub};
+
+    let mut unsub = UnSub::new();
+    unsub.push(b"hello");
+    let frames = [
+        ClientFrame::Ping,
+        ClientFrame::TurnPull,
+        ClientFrame::Err(Err::new(ErrorCode::ProtocolViolation).with_msg("decode error")),
+        ClientFrame::Sub(Sub::new("test")),
+        ClientFrame::Pub(Pub::new("test", b"qweasd")),
+        ClientFrame::UnSub(unsub),
+        ClientFrame::Offset(Offset::new(1, "test")),
+        ClientFrame::Ack(Ack::new(2, "test")),
+    ];
+
+    let mut buff = BytesMut::new();
+    frames
+        .iter()
+        .for_each(|frame| frame.encode_into_with(Version::V2, &mut buff));
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+
+    // 一个字节一个字节的送入
+    let mut messages = Vec::new();
+    for byte in buff.iter() {
+        decode.set_buff([*byte]);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+
+    assert_eq!(messages.len(), frames.len());
+    assert!(matches!(messages[0], Message::Ping));
+    assert!(matches!(messages[1], Message::TurnPull));
+    assert!(matches!(messages[2], Message::Err(_)));
+    assert!(matches!(messages[3], Message::Sub(_)));
+    match &messages[4] {
+        Message::Pub(r#pub) => assert_eq!(&r#pub.msg, &b"qweasd"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(messages[5], Message::UnSub(_)));
+    assert!(matches!(messages[6], Message::Offset(_)));
+    assert!(matches!(messages[7], Message::Ack(_)));
+}
+
+#[test]
+fn envelope_skip_unknown_frame() {
+    use protocol::send_to_client::encode::{Msg, ServerFrame};
+    use protocol::send_to_server::decode::{Decode, Message};
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(200);
+    buff.put_u8(0);
+    buff.put_u32(3);
+    buff.extend_from_slice(&[1, 2, 3]);
+    ServerFrame::Msg(Msg::new(9, b"test", b"test")).encode_into_with(Version::V2, &mut buff);
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_buff(&buff[..4]);
+    assert!(decode.iter().next().is_none());
+    decode.set_buff(&buff[4..]);
+
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Msg(msg) => assert_eq!(msg.offset, 9),
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(decode.iter().next().is_none());
+}
+
+#[test]
+fn envelope_skip_trailing_fields() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::ClientFrame;
+
+    // 更新的版本在订阅帧后面追加了两个字节
+    let mut buff = BytesMut::new();
+    buff.put_u8(7);
+    buff.put_u8(0);
+    buff.put_u32(8);
+    buff.put_u8(0);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+    buff.extend_from_slice(&[0xff, 0xff]);
+    ClientFrame::Ping.encode_into_with(Version::V2, &mut buff);
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_buff(&buff);
+
+    let mut iter = decode.iter();
+    match iter.next().unwrap().unwrap() {
+        Message::Sub(sub) => assert_eq!(&sub.name, &b"test"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(iter.next(), Some(Ok(Message::Ping))));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn envelope_unsupported_flags() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+    use protocol::send_to_server::encode::ClientFrame;
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(2);
+    buff.put_u8(0x80);
+    buff.put_u32(4);
+    buff.extend_from_slice(&[0, 0, 0, 0]);
+    ClientFrame::Pong.encode_into_with(Version::V2, &mut buff);
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_buff(&buff);
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::UnsupportedFlags(0x80)
+    );
+    assert!(decode.poisoned().is_some());
+
+    // Resync 按照帧头的长度跳过整个帧
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    decode.set_buff(&buff);
+    assert!(decode.iter().next().unwrap().is_err());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Pong))));
+}
+
+#[test]
+fn envelope_length_mismatch() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind};
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(7);
+    buff.put_u8(0);
+    buff.put_u32(2);
+    buff.put_u8(0);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_buff(&buff);
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::LengthMismatch { declared: 2 });
+    assert_eq!(error.offset(), 0);
+}
+
+#[test]
+fn envelope_short_length_stays_in_frame() {
+    use protocol::frame::{parse_frame_with, ParseError};
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+
+    // 声明的长度太短, 后面紧跟着一个 ping
+    let mut buff = BytesMut::new();
+    buff.put_u8(7);
+    buff.put_u8(0);
+    buff.put_u32(2);
+    buff.put_u8(0);
+    buff.put_u8(4);
+    buff.extend_from_slice(&[2, 0, 0, 0, 0, 0]);
+
+    // 收到声明的帧体之后立即报错, 不等待更多的字节
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    decode.set_buff(&buff[..8]);
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::LengthMismatch { declared: 2 }
+    );
+    decode.set_buff(&buff[8..]);
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Ping))));
+
+    assert_eq!(
+        parse_frame_with(Version::V2, &buff),
+        Err(ParseError::Invalid(ErrorKind::LengthMismatch {
+            declared: 2
+        }))
+    );
+    assert_eq!(
+        parse_frame_with(Version::V2, &buff[..8]),
+        Err(ParseError::Invalid(ErrorKind::LengthMismatch {
+            declared: 2
+        }))
+    );
+    assert_eq!(
+        parse_frame_with(Version::V2, &buff[8..]).unwrap(),
+        (protocol::frame::FrameRef::Ping, 6)
+    );
+}
+
+#[test]
+fn envelope_short_length_client() {
+    use protocol::send_to_server::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+
+    // 订阅确认声明的长度不够放下订阅名称
+    let mut buff = BytesMut::new();
+    buff.put_u8(18);
+    buff.put_u8(0);
+    buff.put_u32(5);
+    buff.put_u32(1);
+    buff.put_u8(4);
+    buff.extend_from_slice(&[2, 0, 0, 0, 0, 0]);
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    let mut results = Vec::new();
+    for byte in buff.iter() {
+        decode.set_buff([*byte]);
+        results.extend(decode.iter());
+    }
+    match &results[..] {
+        [Err(error), Ok(Message::Ping)] => {
+            assert_eq!(error.kind(), &ErrorKind::LengthMismatch { declared: 5 });
+            assert_eq!(error.offset(), 0);
+        }
+        results => panic!("unexpected results {:?}", results),
+    }
+}
+
+#[test]
+fn envelope_resync_payload_too_large() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+    use protocol::send_to_server::encode::{ClientFrame, Pub};
+
+    let mut buff = BytesMut::new();
+    ClientFrame::Pub(Pub::new("test", b"qweasd")).encode_into_with(Version::V2, &mut buff);
+    ClientFrame::Ping.encode_into_with(Version::V2, &mut buff);
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    decode.set_max_message_length(4);
+    decode.set_buff(&buff);
+
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::PayloadTooLarge { limit: 4, got: 6 }
+    );
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Ping))));
+}
+
+#[test]
+fn envelope_connection() {
+    use protocol::send_to_client::connection::{Event as ServerEvent, ServerConnection};
+    use protocol::send_to_client::encode::ServerConfig;
+    use protocol::send_to_server::connection::{ClientConnection, Event as ClientEvent};
+    use protocol::send_to_server::encode::ClientConfig;
+
+    let mut server_config = ServerConfig::default();
+    server_config.set_version(2);
+    let mut server = ServerConnection::new(server_config);
+
+    let mut client_config = ClientConfig::default();
+    client_config.set_version(2);
+    let mut client = ClientConnection::new(client_config);
+
+    client.receive(server.poll_transmit().unwrap());
+    assert!(matches!(
+        client.poll_event(),
+        Some(Ok(ClientEvent::Connected(_)))
+    ));
+    assert_eq!(client.version(), Version::V2);
+
+    // 握手之后的帧使用第二版格式
+    client.subscribe("test").unwrap();
+    let buff = client.poll_transmit().unwrap();
+    assert_eq!(&buff[buff.len() - 12..buff.len() - 6], &[7, 0, 0, 0, 0, 6]);
+
+    server.receive(buff);
+    assert!(matches!(
+        server.poll_event(),
+        Some(Ok(ServerEvent::Connected(_)))
+    ));
+    assert_eq!(server.version(), Version::V2);
+    assert!(matches!(server.poll_event(), Some(Ok(ServerEvent::Sub(_)))));
+
+    server.send_msg(9, b"test", b"qweasd").unwrap();
+    client.receive(server.poll_transmit().unwrap());
+    match client.poll_event().unwrap().unwrap() {
+        ClientEvent::Msg(msg) => assert_eq!(&msg.payload, &b"qweasd"[..]),
+        event => panic!("unexpected event {:?}", event),
+    }
+}
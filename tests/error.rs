@@ -72,3 +72,140 @@ fn client_decode_error_chunk() {
         }
     }
 }
+
+#[test]
+fn encode_error_code() {
+    use protocol::error::ErrorCode;
+    use protocol::send_to_client::encode::{Err, ServerFrame};
+    use protocol::version::Version;
+
+    // 第二版才带上错误码
+    let err = ServerFrame::Err(Err::new(ErrorCode::SlowConsumer));
+    assert_eq!(
+        &err.encode_with(Version::V2)[..],
+        &b"\x0a\x00\x00\x00\x00\x04\x00\x0b\x00\x00"[..]
+    );
+    assert_eq!(&err.encode()[..], &b"\x0a\x00\x00"[..]);
+
+    let err = ServerFrame::Err(Err::new(ErrorCode::NotSubscribed).with_msg("not subscribed"));
+    assert_eq!(
+        &err.encode_with(Version::V2)[6..],
+        &b"\x00\x0a\x00\x0enot subscribed"[..]
+    );
+    assert_eq!(&err.encode()[..], &b"\x0a\x00\x0enot subscribed"[..]);
+}
+
+#[test]
+fn decode_error_code() {
+    use protocol::error::ErrorCode;
+    use protocol::send_to_client::encode::{Err, ServerFrame};
+    use protocol::send_to_server::decode::{Decode, Message};
+    use protocol::version::Version;
+
+    let err = ServerFrame::Err(Err::new(ErrorCode::AuthFailure));
+
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_buff(err.encode_with(Version::V2));
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => {
+            assert_eq!(erro.code, ErrorCode::AuthFailure);
+            assert!(erro.msg.is_empty());
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    // 第一版没有错误码
+    let mut decode = Decode::new(0);
+    decode.set_buff(err.encode());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(erro.code, ErrorCode::Unknown(0)),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn unknown_error_code() {
+    use protocol::error::ErrorCode;
+    use protocol::send_to_server::decode::{Decode, Message};
+    use protocol::version::Version;
+
+    // 不认识的错误码不算解析错误
+    let mut decode = Decode::new(0);
+    decode.set_version(Version::V2);
+    decode.set_buff(&b"\x0a\x00\x00\x00\x00\x07\x01\x00\x00\x03new"[..]);
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => {
+            assert_eq!(erro.code, ErrorCode::Unknown(256));
+            assert_eq!(erro.code.as_u16(), 256);
+            assert_eq!(&erro.msg[..], b"new");
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn error_code_round_trip() {
+    use protocol::error::ErrorCode;
+
+    for code in 0..=12 {
+        assert_eq!(ErrorCode::from_u16(code).as_u16(), code);
+    }
+    assert_eq!(ErrorCode::from_u16(0), ErrorCode::Unknown(0));
+    assert_eq!(
+        ErrorCode::ModeNotSupported.description(),
+        "mode not supported"
+    );
+}
+
+#[test]
+fn err_msg_truncated() {
+    use protocol::error::ErrorCode;
+    use protocol::send_to_client::decode::{Decode as ServerDecode, Message as ServerMessage};
+    use protocol::send_to_client::encode::{Err as ServerErr, Ping};
+    use protocol::send_to_server::decode::{Decode, Message};
+    use protocol::send_to_server::encode::Err;
+
+    // 正好 u16::MAX 字节的说明原样发送
+    let msg = "a".repeat(u16::MAX as usize);
+    let mut decode = Decode::new(0);
+    decode.set_buff(
+        ServerErr::new(ErrorCode::ProtocolViolation)
+            .with_msg(&msg)
+            .encode(),
+    );
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(&erro.msg[..], msg.as_bytes()),
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    // 超出的部分在字符边界截掉, 不会破坏后面的帧
+    let msg = "é".repeat(35000);
+    let mut buff = ServerErr::new(ErrorCode::ProtocolViolation)
+        .with_msg(&msg)
+        .encode();
+    buff.extend_from_slice(Ping::encode());
+    decode.set_buff(buff);
+    let mut iter = decode.iter();
+    match iter.next().unwrap().unwrap() {
+        Message::Err(erro) => {
+            assert_eq!(erro.msg.len(), u16::MAX as usize - 1);
+            assert!(std::str::from_utf8(&erro.msg).is_ok());
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(iter.next(), Some(Ok(Message::Ping))));
+
+    let mut decode = ServerDecode::new(0);
+    decode.set_buff(
+        Err::new(ErrorCode::ProtocolViolation)
+            .with_msg(&msg)
+            .encode(),
+    );
+    match decode.iter().next().unwrap().unwrap() {
+        ServerMessage::Err(erro) => {
+            assert_eq!(erro.msg.len(), u16::MAX as usize - 1)
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
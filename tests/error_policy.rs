@@ -0,0 +1,243 @@
+use bytes::{BufMut, BytesMut};
+
+#[test]
+fn server_poison() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy};
+    use protocol::send_to_server::encode::Ping;
+
+    let mut decode = Decode::new(0);
+    assert_eq!(decode.error_policy(), ErrorPolicy::Poison);
+
+    decode.set_buff([u8::MAX]);
+    decode.set_buff(Ping::encode());
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnknownFrameType(u8::MAX));
+    assert_eq!(decode.poisoned(), Some(&error));
+
+    // 后面的ping不再解析, 每次迭代都只返回同一个错误
+    for _ in 0..3 {
+        let mut iter = decode.iter();
+        assert_eq!(iter.next().unwrap().unwrap_err(), error);
+        assert!(iter.next().is_none());
+    }
+
+    decode.set_buff(Ping::encode());
+    let result = decode.iter().collect::<Vec<_>>();
+    assert_eq!(result.len(), 1);
+    assert_eq!(result[0].as_ref().unwrap_err(), &error);
+}
+
+#[test]
+fn client_poison() {
+    use protocol::send_to_client::encode::Ping;
+    use protocol::send_to_server::decode::{Decode, ErrorKind};
+
+    let mut decode = Decode::new(0);
+    decode.set_max_message_length(4);
+    decode.set_buff([10, 0, 5]);
+    decode.set_buff(b"error");
+    decode.set_buff(Ping::encode());
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(
+        error.kind(),
+        &ErrorKind::PayloadTooLarge { limit: 4, got: 5 }
+    );
+    assert_eq!(decode.iter().next().unwrap().unwrap_err(), error);
+}
+
+#[test]
+fn server_resync_unknown_frame_type() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+    use protocol::send_to_server::encode::{Ping, Pong};
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    decode.set_buff(Ping::encode());
+    decode.set_buff([u8::MAX, 200]);
+    decode.set_buff(Pong::encode());
+
+    let mut iter = decode.iter();
+    assert!(matches!(iter.next(), Some(Ok(Message::Ping))));
+    let error = iter.next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnknownFrameType(u8::MAX));
+    assert_eq!(error.offset(), 1);
+    assert!(iter.next().is_none());
+
+    // 第一版不知道这个帧在哪里结束, 和 Poison 一样不再解析
+    assert_eq!(decode.poisoned(), Some(&error));
+    assert_eq!(decode.iter().next().unwrap().unwrap_err(), error);
+}
+
+#[test]
+fn server_resync_junk_frame_type() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy};
+    use protocol::send_to_server::encode::Pong;
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+
+    // 不认识的类型后面恰好是ping的类型, 不能当作新的帧
+    decode.set_buff([u8::MAX, 2]);
+    decode.set_buff(Pong::encode());
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnknownFrameType(u8::MAX));
+    let results = decode.iter().collect::<Vec<_>>();
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].as_ref().unwrap_err(), &error);
+}
+
+#[test]
+fn client_resync_junk_frame_type() {
+    use protocol::send_to_client::encode::Ping;
+    use protocol::send_to_server::decode::{Decode, ErrorKind, ErrorPolicy};
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+
+    // 发布只能由客户端发送, 后面的ok不能当作新的帧
+    decode.set_buff([8, 13]);
+    decode.set_buff(Ping::encode());
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::UnexpectedFrame(8));
+    assert!(decode.iter().next().unwrap().is_err());
+    assert_eq!(decode.poisoned(), Some(&error));
+}
+
+#[test]
+fn server_resync_payload_too_large() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+    use protocol::send_to_server::encode::{Ping, Pub};
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+    decode.set_max_message_length(4);
+
+    let publish = Pub::new("test", "qweasd").encode();
+    decode.set_buff(&publish[..publish.len() - 3]);
+
+    // 不等待消息体就报错
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::PayloadTooLarge { limit: 4, got: 6 }
+    );
+    assert!(decode.iter().next().is_none());
+
+    // 剩下的消息体到达后被跳过
+    decode.set_buff(&publish[publish.len() - 3..]);
+    decode.set_buff(Ping::encode());
+    decode.set_buff(Pub::new("test", "qwe").encode());
+
+    let mut iter = decode.iter();
+    assert!(matches!(iter.next(), Some(Ok(Message::Ping))));
+    match iter.next().unwrap().unwrap() {
+        Message::Pub(r#pub) => assert_eq!(&r#pub.msg, &b"qwe"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn client_resync_invalid_subject() {
+    use protocol::send_to_client::encode::{Msg, Ping};
+    use protocol::send_to_server::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(4);
+    buff.put_u64(9);
+    buff.put_u8(1);
+    buff.put_u8(0xff);
+    buff.put_u32(4);
+    buff.extend_from_slice(b"test");
+    decode.set_buff(&buff);
+    decode.set_buff(Msg::new(10, b"test", b"test").encode());
+    decode.set_buff(Ping::encode());
+
+    // 名称不合法的帧已经完整读完, 后面的帧不受影响
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::InvalidSubject);
+    assert_eq!(error.offset(), 0);
+
+    let mut iter = decode.iter();
+    match iter.next().unwrap().unwrap() {
+        Message::Msg(msg) => assert_eq!(msg.offset, 10),
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(iter.next(), Some(Ok(Message::Ping))));
+    assert!(iter.next().is_none());
+}
+
+#[test]
+fn server_resync_invalid_headers() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+    use protocol::send_to_server::encode::{Ping, Pub};
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+
+    // |16|4 test|u32 3|\x00\x01\x00|u32 4|test|, 消息头中的名称为空
+    let mut buff = BytesMut::new();
+    buff.put_u8(16);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+    buff.put_u32(3);
+    buff.extend_from_slice(b"\x00\x01\x00");
+    buff.put_u32(4);
+    buff.extend_from_slice(b"test");
+    buff.extend_from_slice(&Pub::new("test", b"next").encode());
+    buff.extend_from_slice(Ping::encode());
+
+    // 第一版没有帧长度, 消息头出错之后也要跳过后面的消息体
+    let mut results = Vec::new();
+    for byte in buff.iter() {
+        decode.set_buff([*byte]);
+        results.extend(decode.iter());
+    }
+    match &results[..] {
+        [Err(error), Ok(Message::Pub(r#pub)), Ok(Message::Ping)] => {
+            assert_eq!(error.kind(), &ErrorKind::InvalidHeaders);
+            assert_eq!(error.offset(), 0);
+            assert_eq!(&r#pub.msg[..], b"next");
+        }
+        results => panic!("unexpected results {:?}", results),
+    }
+}
+
+#[test]
+fn client_resync_invalid_headers() {
+    use protocol::send_to_client::encode::{Msg, Ping};
+    use protocol::send_to_server::decode::{Decode, ErrorKind, ErrorPolicy, Message};
+
+    let mut decode = Decode::new(0);
+    decode.set_error_policy(ErrorPolicy::Resync);
+
+    // |17|offset|4 test|u32 3|\x00\x01\x00|u32 4|test|
+    let mut buff = BytesMut::new();
+    buff.put_u8(17);
+    buff.put_u64(1);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+    buff.put_u32(3);
+    buff.extend_from_slice(b"\x00\x01\x00");
+    buff.put_u32(4);
+    buff.extend_from_slice(b"test");
+    decode.set_buff(&buff);
+    decode.set_buff(Msg::new(2, b"test", b"test").encode());
+    decode.set_buff(Ping::encode());
+
+    let error = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(error.kind(), &ErrorKind::InvalidHeaders);
+    assert_eq!(error.offset(), 0);
+
+    let mut iter = decode.iter();
+    match iter.next().unwrap().unwrap() {
+        Message::Msg(msg) => assert_eq!(msg.offset, 2),
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(matches!(iter.next(), Some(Ok(Message::Ping))));
+}
@@ -0,0 +1,259 @@
+mod common;
+
+use common::{to_client, to_server, FakeClock};
+use protocol::clock::Clock;
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::send_to_client::connection::{Event as ServerEvent, ServerConnection};
+use protocol::send_to_client::decode as server_decode;
+use protocol::send_to_client::encode::{FetchDone, ServerConfig};
+use protocol::send_to_client::fetch::{Done, Fetches};
+use protocol::send_to_server::connection::{ClientConnection, Error, Event as ClientEvent};
+use protocol::send_to_server::decode::{Decode, ErrorKind, Message};
+use protocol::send_to_server::encode::{ClientConfig, Fetch};
+use protocol::state::FetchStatus;
+use std::time::Duration;
+
+fn fetch(batch: u32, max_bytes: u32, expires: u64) -> server_decode::Fetch {
+    let buff = Fetch::new("test", batch)
+        .with_max_bytes(max_bytes)
+        .with_expires(Duration::from_millis(expires))
+        .encode();
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(buff);
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Fetch(fetch) => *fetch,
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn fetch_encode() {
+    let buff = Fetch::new("test", 10)
+        .with_max_bytes(1024)
+        .with_expires(Duration::from_millis(500))
+        .encode();
+    assert_eq!(
+        &buff[..],
+        &b"\x13\x00\x00\x00\x0a\x00\x00\x04\x00\x00\x00\x01\xf4\x04test"[..]
+    );
+}
+
+#[test]
+fn fetch_decode_in_chunks() {
+    let mut buff = Fetch::new("test", 10)
+        .with_expires(Duration::from_secs(30))
+        .encode();
+    buff.extend_from_slice(&Fetch::new("other", 1).encode());
+
+    let mut decode = server_decode::Decode::new(0);
+    let mut messages = Vec::new();
+    for chunk in buff.chunks(3) {
+        decode.set_buff(chunk);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+
+    assert_eq!(messages.len(), 2);
+    match &messages[0] {
+        server_decode::Message::Fetch(fetch) => {
+            assert_eq!(&fetch.sub_name[..], b"test");
+            assert_eq!(fetch.batch, 10);
+            assert_eq!(fetch.max_bytes, 0);
+            assert_eq!(fetch.expires, Duration::from_secs(30));
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    match &messages[1] {
+        server_decode::Message::Fetch(fetch) => {
+            assert_eq!(&fetch.sub_name[..], b"other");
+            assert_eq!(fetch.expires, Duration::from_secs(0));
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn fetch_done_decode() {
+    let buff = FetchDone::new(b"test", FetchStatus::BatchComplete, 3).encode();
+    assert_eq!(&buff[..], &b"\x14\x01\x00\x00\x00\x03\x04test"[..]);
+
+    let mut decode = Decode::new(0);
+    for chunk in buff.chunks(2) {
+        decode.set_buff(chunk);
+        if let Some(message) = decode.iter().next() {
+            match message.unwrap() {
+                Message::FetchDone(done) => {
+                    assert_eq!(&done.sub_name[..], b"test");
+                    assert_eq!(done.status, FetchStatus::BatchComplete);
+                    assert_eq!(done.count, 3);
+                }
+                message => panic!("unexpected message {:?}", message),
+            }
+        }
+    }
+}
+
+#[test]
+fn fetch_done_unknown_status() {
+    let mut decode = Decode::new(0);
+    decode.set_buff(&b"\x14\x07\x00\x00\x00\x00\x04test"[..]);
+    let e = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(e.kind(), &ErrorKind::UnknownFetchStatus(7));
+}
+
+#[test]
+fn fetch_parse_frame() {
+    let buff = Fetch::new("test", 2).with_max_bytes(64).encode();
+    assert_eq!(
+        parse_frame(&buff).unwrap(),
+        (
+            FrameRef::Fetch {
+                batch: 2,
+                max_bytes: 64,
+                expires: Duration::from_secs(0),
+                sub_name: b"test",
+            },
+            buff.len()
+        )
+    );
+
+    let buff = FetchDone::new(b"test", FetchStatus::NoMessages, 0).encode();
+    assert_eq!(
+        parse_frame(&buff).unwrap(),
+        (
+            FrameRef::FetchDone {
+                status: FetchStatus::NoMessages,
+                count: 0,
+                sub_name: b"test",
+            },
+            buff.len()
+        )
+    );
+}
+
+fn connect() -> (ServerConnection, ClientConnection) {
+    // 两种模式都支持时初始为推模式
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    server_config.support_pull();
+    let mut client_config = ClientConfig::default();
+    client_config.support_push();
+    client_config.support_pull();
+    common::connect(server_config, client_config)
+}
+
+#[test]
+fn fetch_batch_over_connection() {
+    let (mut server, mut client) = connect();
+    let mut fetches = Fetches::with_clock(FakeClock::new());
+
+    assert!(matches!(
+        client.fetch("test", 2, 0, Duration::from_secs(1)),
+        Err(Error::NotPullMode)
+    ));
+    client.subscribe("test").unwrap();
+    client.turn_pull().unwrap();
+    to_server(&mut server, &mut client);
+    to_client(&mut server, &mut client);
+
+    client.fetch("test", 2, 0, Duration::from_secs(1)).unwrap();
+    match to_server(&mut server, &mut client).pop() {
+        Some(ServerEvent::Fetch(fetch)) => assert_eq!(fetches.start(&fetch), None),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    for (offset, payload) in [(1, b"a"), (2, b"b")].iter() {
+        assert!(fetches.accepts(b"test", payload.len()));
+        server.send_msg(*offset, b"test", *payload).unwrap();
+        if let Some(done) = fetches.deliver(b"test", payload.len()) {
+            server
+                .send_fetch_done(&done.sub_name, done.status, done.count)
+                .unwrap();
+        }
+    }
+    assert!(fetches.is_empty());
+
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Msg(_), ClientEvent::Msg(_), ClientEvent::FetchDone(done)] => {
+            assert_eq!(&done.sub_name[..], b"test");
+            assert_eq!(done.status, FetchStatus::BatchComplete);
+            assert_eq!(done.count, 2);
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn fetch_rejected_in_push_mode() {
+    let (mut server, _client) = connect();
+    server.receive(Fetch::new("test", 1).encode());
+    assert!(server.poll_event().is_none());
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(server.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(&erro.msg[..], b"not in pull mode"),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn fetches_long_poll_timeout() {
+    let clock = FakeClock::new();
+    let mut fetches = Fetches::with_clock(clock.clone());
+
+    fetches.start(&fetch(10, 0, 500));
+    assert_eq!(
+        fetches.next_deadline(),
+        Some(clock.now() + Duration::from_millis(500))
+    );
+    clock.advance(Duration::from_millis(499));
+    assert_eq!(fetches.poll_timeout(), None);
+    clock.advance(Duration::from_millis(1));
+    assert_eq!(
+        fetches.poll_timeout(),
+        Some(Done {
+            sub_name: "test".into(),
+            status: FetchStatus::NoMessages,
+            count: 0,
+        })
+    );
+
+    // 超时前发出了部分消息
+    fetches.start(&fetch(10, 0, 500));
+    fetches.deliver(b"test", 1);
+    clock.advance(Duration::from_millis(500));
+    let done = fetches.poll_timeout().unwrap();
+    assert_eq!(done.status, FetchStatus::BatchComplete);
+    assert_eq!(done.count, 1);
+    assert!(fetches.is_empty());
+    assert_eq!(fetches.next_deadline(), None);
+}
+
+#[test]
+fn fetches_max_bytes() {
+    let mut fetches = Fetches::with_clock(FakeClock::new());
+    fetches.start(&fetch(10, 8, 0));
+
+    // 第一条消息不受字节数限制
+    assert!(fetches.accepts(b"test", 20));
+    assert!(fetches.deliver(b"test", 5).is_none());
+    assert!(fetches.accepts(b"test", 3));
+    assert!(!fetches.accepts(b"test", 4));
+    let done = fetches.deliver(b"test", 3).unwrap();
+    assert_eq!(done.count, 2);
+    assert!(!fetches.accepts(b"other", 1));
+}
+
+#[test]
+fn fetches_replace_and_finish() {
+    let mut fetches = Fetches::with_clock(FakeClock::new());
+    assert_eq!(fetches.start(&fetch(1, 0, 100)), None);
+    let old = fetches.start(&fetch(5, 0, 100)).unwrap();
+    assert_eq!(old.status, FetchStatus::NoMessages);
+    assert_eq!(fetches.len(), 1);
+
+    // 没有消息可以立即结束
+    let done = fetches.finish(b"test").unwrap();
+    assert_eq!(done.status, FetchStatus::NoMessages);
+    assert!(fetches.finish(b"test").is_none());
+}
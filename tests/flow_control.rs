@@ -0,0 +1,162 @@
+mod common;
+
+use common::to_server;
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::send_to_client::connection::{Event as ServerEvent, ServerConnection};
+use protocol::send_to_client::decode as server_decode;
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::connection::{ClientConnection, Event as ClientEvent};
+use protocol::send_to_server::encode::{ClientConfig, Credit};
+
+fn connect(max_task_size: u8) -> (ServerConnection, ClientConnection) {
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    let mut client_config = ClientConfig::default();
+    client_config.support_push();
+    client_config.max_task_size(max_task_size);
+    let (mut server, mut client) = common::connect(server_config, client_config);
+
+    client.subscribe("test").unwrap();
+    server.receive(client.poll_transmit().unwrap());
+    assert!(matches!(server.poll_event(), Some(Ok(ServerEvent::Sub(_)))));
+
+    (server, client)
+}
+
+// 客户端收到的消息号
+fn received(server: &mut ServerConnection, client: &mut ClientConnection) -> Vec<u64> {
+    if let Some(buff) = server.poll_transmit() {
+        client.receive(buff);
+    }
+    std::iter::from_fn(|| client.poll_event())
+        .map(|event| match event.unwrap() {
+            ClientEvent::Msg(msg) => msg.offset,
+            event => panic!("unexpected event {:?}", event),
+        })
+        .collect()
+}
+
+#[test]
+fn credit_encode_decode() {
+    let buff = Credit::new(5).encode();
+    assert_eq!(&buff[..], &b"\x15\x00\x00\x00\x05"[..]);
+    assert_eq!(
+        parse_frame(&buff).unwrap(),
+        (FrameRef::Credit { credit: 5 }, 5)
+    );
+
+    let mut decode = server_decode::Decode::new(0);
+    for chunk in buff.chunks(2) {
+        decode.set_buff(chunk);
+        if let Some(message) = decode.iter().next() {
+            assert!(matches!(message, Ok(server_decode::Message::Credit(5))));
+        }
+    }
+}
+
+#[test]
+fn slow_consumer_window() {
+    let (mut server, mut client) = connect(2);
+    assert_eq!(server.credit(), 2);
+
+    for offset in 1..=5 {
+        server.send_msg(offset, b"test", b"x").unwrap();
+    }
+    assert_eq!(server.credit(), 0);
+    assert_eq!(server.in_flight(), 2);
+    assert_eq!(server.queued(), 3);
+
+    // 只收到窗口内的消息
+    assert_eq!(received(&mut server, &mut client), [1, 2]);
+
+    // 慢的客户端每处理完一条就应答, 服务器每次只补上一条
+    client.ack(1, "test").unwrap();
+    to_server(&mut server, &mut client);
+    assert_eq!(received(&mut server, &mut client), [3]);
+    assert_eq!(server.queued(), 2);
+
+    // 重复的应答不会归还额度
+    client.ack(1, "test").unwrap();
+    to_server(&mut server, &mut client);
+    assert!(server.poll_transmit().is_none());
+    assert_eq!(server.credit(), 0);
+
+    client.ack(2, "test").unwrap();
+    client.ack(3, "test").unwrap();
+    to_server(&mut server, &mut client);
+    assert_eq!(received(&mut server, &mut client), [4, 5]);
+    assert_eq!(server.queued(), 0);
+    assert_eq!(server.in_flight(), 2);
+}
+
+#[test]
+fn grant_credit() {
+    let (mut server, mut client) = connect(1);
+    for offset in 1..=4 {
+        server.send_msg(offset, b"test", b"x").unwrap();
+    }
+    assert_eq!(received(&mut server, &mut client), [1]);
+
+    client.grant_credit(2).unwrap();
+    assert!(matches!(
+        &to_server(&mut server, &mut client)[..],
+        [ServerEvent::Credit(2)]
+    ));
+    assert_eq!(received(&mut server, &mut client), [2, 3]);
+    assert_eq!(server.in_flight(), 3);
+    assert_eq!(server.queued(), 1);
+
+    // 额外的额度没有用完时新的消息直接推送
+    client.grant_credit(3).unwrap();
+    to_server(&mut server, &mut client);
+    server.send_msg(5, b"test", b"x").unwrap();
+    assert_eq!(received(&mut server, &mut client), [4, 5]);
+    assert_eq!(server.credit(), 1);
+}
+
+#[test]
+fn unsub_drops_backlog() {
+    let (mut server, mut client) = connect(1);
+    for offset in 1..=3 {
+        server.send_msg(offset, b"test", b"x").unwrap();
+    }
+    assert_eq!(received(&mut server, &mut client), [1]);
+
+    client.unsubscribe(&["test"]).unwrap();
+    to_server(&mut server, &mut client);
+    assert_eq!(server.queued(), 0);
+    assert_eq!(server.in_flight(), 0);
+    assert_eq!(server.credit(), 1);
+    assert!(server.poll_transmit().is_none());
+}
+
+#[test]
+fn zero_max_task_size_is_unlimited() {
+    let (mut server, mut client) = connect(0);
+    for offset in 1..=300 {
+        server.send_msg(offset, b"test", b"x").unwrap();
+    }
+    assert_eq!(received(&mut server, &mut client).len(), 300);
+    assert_eq!(server.queued(), 0);
+    assert_eq!(server.in_flight(), 0);
+}
+
+#[test]
+fn credit_saturates() {
+    let (mut server, mut client) = connect(1);
+    server.send_msg(1, b"test", b"x").unwrap();
+    server.send_msg(2, b"test", b"x").unwrap();
+    assert_eq!(received(&mut server, &mut client), [1]);
+
+    // 额度已经到上限之后的应答和拒绝不会溢出
+    client.grant_credit(u32::MAX).unwrap();
+    to_server(&mut server, &mut client);
+    assert_eq!(received(&mut server, &mut client), [2]);
+    assert_eq!(server.credit(), u32::MAX - 1);
+
+    client.ack(1, "test").unwrap();
+    client.nack(2, "test", None, "").unwrap();
+    to_server(&mut server, &mut client);
+    assert_eq!(server.credit(), u32::MAX);
+    assert_eq!(server.in_flight(), 0);
+}
@@ -0,0 +1,93 @@
+use bytes::BytesMut;
+use protocol::error::ErrorCode;
+
+#[test]
+fn client_frame_encode_into() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::{
+        Ack, ClientConfig, ClientFrame, Err, Offset, Pub, Sub, UnSub,
+    };
+
+    let config = ClientConfig::default();
+    let mut unsub = UnSub::new();
+    unsub.push(b"hello");
+    unsub.push(b"world");
+
+    let frames = vec![
+        ClientFrame::Info(&config),
+        ClientFrame::Ping,
+        ClientFrame::Pong,
+        ClientFrame::TurnPush,
+        ClientFrame::TurnPull,
+        ClientFrame::Ok,
+        ClientFrame::Err(Err::new(ErrorCode::ProtocolViolation).with_msg("decode error")),
+        ClientFrame::Sub(Sub::new("test")),
+        ClientFrame::Pub(Pub::new("test", b"qweasd")),
+        ClientFrame::UnSub(unsub),
+        ClientFrame::Offset(Offset::new(1, "test")),
+        ClientFrame::Ack(Ack::new(2, "test")),
+    ];
+
+    let mut buff = BytesMut::new();
+    for frame in frames.iter() {
+        let len = buff.len();
+        frame.encode_into(&mut buff);
+        assert_eq!(buff.len() - len, frame.encoded_len());
+        assert_eq!(&buff[len..], &frame.encode()[..]);
+    }
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(&buff);
+    let messages = decode.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(messages.len(), frames.len());
+
+    assert!(matches!(messages[0], Message::Info(_)));
+    assert!(matches!(messages[1], Message::Ping));
+    assert!(matches!(messages[2], Message::Pong));
+    assert!(matches!(messages[3], Message::TurnPush));
+    assert!(matches!(messages[4], Message::TurnPull));
+    assert!(matches!(messages[5], Message::Ok));
+    assert!(matches!(messages[6], Message::Err(_)));
+    assert!(matches!(messages[7], Message::Sub(_)));
+    assert!(matches!(messages[8], Message::Pub(_)));
+    assert!(matches!(messages[9], Message::UnSub(_)));
+    assert!(matches!(messages[10], Message::Offset(_)));
+    assert!(matches!(messages[11], Message::Ack(_)));
+}
+
+#[test]
+fn server_frame_encode_into() {
+    use protocol::send_to_client::encode::{Ack, Err, Msg, Offset, ServerConfig, ServerFrame};
+    use protocol::send_to_server::decode::{Decode, Message};
+
+    let config = ServerConfig::default();
+    let frames = [
+        ServerFrame::Info(&config),
+        ServerFrame::Ping,
+        ServerFrame::Pong,
+        ServerFrame::Ok,
+        ServerFrame::Err(Err::new(ErrorCode::ProtocolViolation).with_msg("decode error")),
+        ServerFrame::Msg(Msg::new(9, b"test", b"qweasd")),
+        ServerFrame::Offset(Offset::new(1, b"test")),
+        ServerFrame::Ack(Ack::new(2, b"test")),
+    ];
+
+    let len = frames.iter().map(|frame| frame.encoded_len()).sum();
+    let mut buff = BytesMut::with_capacity(len);
+    frames.iter().for_each(|frame| frame.encode_into(&mut buff));
+    assert_eq!(buff.len(), len);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(&buff);
+    let messages = decode.iter().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(messages.len(), frames.len());
+
+    assert!(matches!(messages[0], Message::Info(_)));
+    assert!(matches!(messages[1], Message::Ping));
+    assert!(matches!(messages[2], Message::Pong));
+    assert!(matches!(messages[3], Message::Ok));
+    assert!(matches!(messages[4], Message::Err(_)));
+    assert!(matches!(messages[5], Message::Msg(_)));
+    assert!(matches!(messages[6], Message::Offset(_)));
+    assert!(matches!(messages[7], Message::Ack(_)));
+}
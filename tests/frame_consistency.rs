@@ -0,0 +1,448 @@
+use bytes::BytesMut;
+use protocol::error::ErrorCode;
+use protocol::frame::{parse_frame_with, FrameRef};
+use protocol::headers::Headers;
+use protocol::state::FetchStatus;
+use protocol::version::Version;
+use std::time::Duration;
+
+// parse_frame 和两个方向的 Decode 是分开实现的, 这里把同一段字节交给两边解析,
+// 统一转换成字符串之后比较, 任何一边漏改字段都会在这里发现
+
+fn s(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+fn headers(headers: &Headers) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+fn describe_frame(frame: &FrameRef<'_>) -> String {
+    match frame {
+        FrameRef::ServerInfo {
+            version,
+            support,
+            max_message_length,
+            nonce,
+        } => format!(
+            "info {} {} {} {:?}",
+            version,
+            support,
+            max_message_length,
+            s(nonce)
+        ),
+        FrameRef::ClientInfo {
+            version,
+            support,
+            max_message_size,
+            user,
+            proof,
+        } => format!(
+            "info {} {} {} {:?} {:?}",
+            version,
+            support,
+            max_message_size,
+            s(user),
+            proof
+        ),
+        FrameRef::Ping => "ping".into(),
+        FrameRef::Pong => "pong".into(),
+        FrameRef::TurnPush => "turn push".into(),
+        FrameRef::TurnPull => "turn pull".into(),
+        FrameRef::Ok => "ok".into(),
+        FrameRef::Err { code, msg } => format!("err {:?} {:?}", code, s(msg)),
+        FrameRef::Msg {
+            offset,
+            sid,
+            sub_name,
+            payload,
+        } => format!(
+            "msg {} {} {:?} {:?} {:?}",
+            offset,
+            sid,
+            s(sub_name),
+            Vec::<(String, String)>::new(),
+            s(payload)
+        ),
+        FrameRef::HMsg {
+            offset,
+            sid,
+            sub_name,
+            headers,
+            payload,
+        } => format!(
+            "msg {} {} {:?} {:?} {:?}",
+            offset,
+            sid,
+            s(sub_name),
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect::<Vec<_>>(),
+            s(payload)
+        ),
+        FrameRef::Offset { offset, sub_name } => format!("offset {} {:?}", offset, s(sub_name)),
+        FrameRef::Ack { offset, sub_name } => format!("ack {} {:?}", offset, s(sub_name)),
+        FrameRef::Sub { reply, name, queue } => {
+            format!("sub {} {:?} {:?}", reply, s(name), queue.map(s))
+        }
+        FrameRef::Pub { name, msg } => format!(
+            "pub {:?} {:?} {:?}",
+            s(name),
+            Vec::<(String, String)>::new(),
+            s(msg)
+        ),
+        FrameRef::HPub { name, headers, msg } => format!(
+            "pub {:?} {:?} {:?}",
+            s(name),
+            headers
+                .iter()
+                .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                .collect::<Vec<_>>(),
+            s(msg)
+        ),
+        FrameRef::UnSub { reply, names } => format!(
+            "unsub {} {:?}",
+            reply,
+            names.iter().map(s).collect::<Vec<_>>()
+        ),
+        FrameRef::Request {
+            id,
+            sub_name,
+            reply_to,
+            payload,
+        } => format!(
+            "request {} {:?} {:?} {:?}",
+            id,
+            s(sub_name),
+            s(reply_to),
+            s(payload)
+        ),
+        FrameRef::Reply {
+            id,
+            sub_name,
+            payload,
+        } => format!("reply {} {:?} {:?}", id, s(sub_name), s(payload)),
+        FrameRef::SubAck { sid, sub_name } => format!("sub ack {} {:?}", sid, s(sub_name)),
+        FrameRef::Fetch {
+            batch,
+            max_bytes,
+            expires,
+            sub_name,
+        } => format!(
+            "fetch {:?} {} {} {:?}",
+            s(sub_name),
+            batch,
+            max_bytes,
+            expires
+        ),
+        FrameRef::FetchDone {
+            status,
+            count,
+            sub_name,
+        } => format!("fetch done {:?} {:?} {}", s(sub_name), status, count),
+        FrameRef::Credit { credit } => format!("credit {}", credit),
+        FrameRef::Nack {
+            offset,
+            delay,
+            sub_name,
+            reason,
+        } => format!(
+            "nack {} {:?} {:?} {:?}",
+            offset,
+            s(sub_name),
+            delay,
+            s(reason)
+        ),
+        FrameRef::Unknown { kind, body } => format!("unknown {} {:?}", kind, body),
+    }
+}
+
+fn describe_server_message(message: &protocol::send_to_client::decode::Message) -> String {
+    use protocol::send_to_client::decode::Message;
+
+    match message {
+        Message::Info(info) => format!(
+            "info {} {} {} {:?} {:?}",
+            info.version,
+            info.support,
+            info.max_message_size,
+            s(&info.user),
+            &info.proof[..]
+        ),
+        Message::Ping => "ping".into(),
+        Message::Pong => "pong".into(),
+        Message::TurnPush => "turn push".into(),
+        Message::TurnPull => "turn pull".into(),
+        Message::Ok => "ok".into(),
+        Message::Err(erro) => format!("err {:?} {:?}", erro.code, s(&erro.msg)),
+        Message::Pub(r#pub) => format!(
+            "pub {:?} {:?} {:?}",
+            s(&r#pub.name),
+            headers(&r#pub.headers),
+            s(&r#pub.msg)
+        ),
+        Message::Sub(sub) => format!(
+            "sub {} {:?} {:?}",
+            sub.reply,
+            s(&sub.name),
+            sub.queue.as_ref().map(|queue| s(queue))
+        ),
+        Message::UnSub(unsub) => format!(
+            "unsub {} {:?}",
+            unsub.reply,
+            unsub
+                .name_list
+                .iter()
+                .map(|name| s(name))
+                .collect::<Vec<_>>()
+        ),
+        Message::Offset(offset) => format!("offset {} {:?}", offset.offset, s(&offset.sub_name)),
+        Message::Ack(ack) => format!("ack {} {:?}", ack.offset, s(&ack.sub_name)),
+        Message::Nack(nack) => format!(
+            "nack {} {:?} {:?} {:?}",
+            nack.offset,
+            s(&nack.sub_name),
+            nack.delay,
+            s(&nack.reason)
+        ),
+        Message::Request(request) => format!(
+            "request {} {:?} {:?} {:?}",
+            request.id,
+            s(&request.sub_name),
+            s(&request.reply_to),
+            s(&request.payload)
+        ),
+        Message::Reply(reply) => format!(
+            "reply {} {:?} {:?}",
+            reply.id,
+            s(&reply.sub_name),
+            s(&reply.payload)
+        ),
+        Message::Fetch(fetch) => format!(
+            "fetch {:?} {} {} {:?}",
+            s(&fetch.sub_name),
+            fetch.batch,
+            fetch.max_bytes,
+            fetch.expires
+        ),
+        Message::Credit(credit) => format!("credit {}", credit),
+    }
+}
+
+fn describe_client_message(message: &protocol::send_to_server::decode::Message) -> String {
+    use protocol::send_to_server::decode::Message;
+
+    match message {
+        Message::Info(info) => format!(
+            "info {} {} {} {:?}",
+            info.version,
+            info.support,
+            info.max_message_length,
+            s(&info.nonce)
+        ),
+        Message::Ping => "ping".into(),
+        Message::Pong => "pong".into(),
+        Message::TurnPush => "turn push".into(),
+        Message::TurnPull => "turn pull".into(),
+        Message::Ok => "ok".into(),
+        Message::Err(erro) => format!("err {:?} {:?}", erro.code, s(&erro.msg)),
+        Message::Msg(msg) => format!(
+            "msg {} {} {:?} {:?} {:?}",
+            msg.offset,
+            msg.sid,
+            s(&msg.sub_name),
+            headers(&msg.headers),
+            s(&msg.payload)
+        ),
+        Message::Offset(offset) => format!("offset {} {:?}", offset.offset, s(&offset.sub_name)),
+        Message::Ack(ack) => format!("ack {} {:?}", ack.offset, s(&ack.sub_name)),
+        Message::Request(request) => format!(
+            "request {} {:?} {:?} {:?}",
+            request.id,
+            s(&request.sub_name),
+            s(&request.reply_to),
+            s(&request.payload)
+        ),
+        Message::Reply(reply) => format!(
+            "reply {} {:?} {:?}",
+            reply.id,
+            s(&reply.sub_name),
+            s(&reply.payload)
+        ),
+        Message::SubAck(sub_ack) => {
+            format!("sub ack {} {:?}", sub_ack.sid, s(&sub_ack.sub_name))
+        }
+        Message::FetchDone(done) => format!(
+            "fetch done {:?} {:?} {}",
+            s(&done.sub_name),
+            done.status,
+            done.count
+        ),
+    }
+}
+
+// 按帧切开之后逐个交给 parse_frame
+fn parse_all(version: Version, mut buff: &[u8]) -> Vec<String> {
+    let mut frames = Vec::new();
+    while !buff.is_empty() {
+        let (frame, len) = parse_frame_with(version, buff).unwrap();
+        frames.push(describe_frame(&frame));
+        buff = &buff[len..];
+    }
+    frames
+}
+
+fn test_headers() -> Headers {
+    let mut headers = Headers::new();
+    headers.insert("trace", "abc");
+    headers.append("tag", "a");
+    headers.append("tag", "b");
+    headers
+}
+
+#[test]
+fn client_frames_agree() {
+    use protocol::send_to_client::decode::Decode;
+    use protocol::send_to_server::encode::{
+        Ack, ClientConfig, ClientFrame, Credit, Err, Fetch, Nack, Offset, Pub, Reply, Request, Sub,
+        UnSub,
+    };
+
+    let mut config = ClientConfig::default();
+    config.support_push();
+    config.max_task_size(10);
+    let mut auth_config = ClientConfig::default();
+    auth_config.set_user("alice", "passw0rd");
+    auth_config.set_nonce(bytes::Bytes::from_static(b"abcd"));
+    let headers = test_headers();
+    let mut unsub = UnSub::new().with_reply();
+    unsub.push(b"hello");
+    unsub.push(b"world");
+
+    let frames = vec![
+        ClientFrame::Ping,
+        ClientFrame::Pong,
+        ClientFrame::TurnPush,
+        ClientFrame::TurnPull,
+        ClientFrame::Ok,
+        ClientFrame::Err(Err::new(ErrorCode::SlowConsumer).with_msg("slow")),
+        ClientFrame::Sub(Sub::new("test")),
+        ClientFrame::Sub(Sub::new("test").with_queue("group").with_reply()),
+        ClientFrame::Pub(Pub::new("test", &b"qweasd"[..])),
+        ClientFrame::Pub(Pub::new("test", &b"qweasd"[..]).with_headers(&headers)),
+        ClientFrame::UnSub(unsub),
+        ClientFrame::Offset(Offset::new(1, "test")),
+        ClientFrame::Ack(Ack::new(2, "test")),
+        ClientFrame::Nack(
+            Nack::new(3, "test")
+                .with_delay(Duration::from_millis(300))
+                .with_reason("busy"),
+        ),
+        ClientFrame::Request(Request::new(4, "test", "inbox", b"ping")),
+        ClientFrame::Reply(Reply::new(5, "inbox", b"pong")),
+        ClientFrame::Fetch(
+            Fetch::new("test", 10)
+                .with_max_bytes(1024)
+                .with_expires(Duration::from_millis(500)),
+        ),
+        ClientFrame::Credit(Credit::new(7)),
+    ];
+
+    for &version in [Version::V1, Version::V2].iter() {
+        let mut buff = BytesMut::new();
+        // 握手帧始终使用第一版格式
+        if version == Version::V1 {
+            ClientFrame::Info(&config).encode_into(&mut buff);
+            ClientFrame::Info(&auth_config).encode_into(&mut buff);
+        }
+        frames
+            .iter()
+            .for_each(|frame| frame.encode_into_with(version, &mut buff));
+
+        let parsed = parse_all(version, &buff);
+
+        let mut decode = Decode::new(0);
+        decode.set_version(version);
+        let mut decoded = Vec::new();
+        for byte in buff.iter() {
+            decode.set_buff([*byte]);
+            decoded.extend(
+                decode
+                    .iter()
+                    .map(|message| describe_server_message(&message.unwrap())),
+            );
+        }
+
+        let expected = frames.len() + if version == Version::V1 { 2 } else { 0 };
+        assert_eq!(parsed.len(), expected);
+        assert_eq!(parsed, decoded, "{:?}", version);
+    }
+}
+
+#[test]
+fn server_frames_agree() {
+    use protocol::send_to_client::encode::{
+        Ack, Err, FetchDone, Msg, Offset, Reply, Request, ServerConfig, ServerFrame, SubAck,
+    };
+    use protocol::send_to_server::decode::Decode;
+
+    let mut config = ServerConfig::default();
+    config.support_push();
+    config.max_message_length(1024);
+    let mut auth_config = ServerConfig::default();
+    auth_config.support_auth();
+    auth_config.set_nonce(bytes::Bytes::from_static(b"abcd"));
+    let headers = test_headers();
+
+    let frames = vec![
+        ServerFrame::Ping,
+        ServerFrame::Pong,
+        ServerFrame::Ok,
+        ServerFrame::Err(Err::new(ErrorCode::NotSubscribed).with_msg("not subscribed")),
+        ServerFrame::Msg(Msg::new(1, b"test", b"hello").with_sid(3)),
+        ServerFrame::Msg(
+            Msg::new(2, b"test", b"hello")
+                .with_sid(3)
+                .with_headers(&headers),
+        ),
+        ServerFrame::Offset(Offset::new(3, b"test")),
+        ServerFrame::Ack(Ack::new(4, b"test")),
+        ServerFrame::Request(Request::new(5, b"test", b"inbox", b"ping")),
+        ServerFrame::Reply(Reply::new(6, b"inbox", b"pong")),
+        ServerFrame::SubAck(SubAck::new(7, b"test")),
+        ServerFrame::FetchDone(FetchDone::new(b"test", FetchStatus::BatchComplete, 2)),
+    ];
+
+    for &version in [Version::V1, Version::V2].iter() {
+        let mut buff = BytesMut::new();
+        if version == Version::V1 {
+            ServerFrame::Info(&config).encode_into(&mut buff);
+            ServerFrame::Info(&auth_config).encode_into(&mut buff);
+        }
+        frames
+            .iter()
+            .for_each(|frame| frame.encode_into_with(version, &mut buff));
+
+        let parsed = parse_all(version, &buff);
+
+        let mut decode = Decode::new(0);
+        decode.set_version(version);
+        let mut decoded = Vec::new();
+        for byte in buff.iter() {
+            decode.set_buff([*byte]);
+            decoded.extend(
+                decode
+                    .iter()
+                    .map(|message| describe_client_message(&message.unwrap())),
+            );
+        }
+
+        let expected = frames.len() + if version == Version::V1 { 2 } else { 0 };
+        assert_eq!(parsed.len(), expected);
+        assert_eq!(parsed, decoded, "{:?}", version);
+    }
+}
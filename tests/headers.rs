@@ -0,0 +1,161 @@
+use bytes::BytesMut;
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::headers::Headers;
+use protocol::send_to_client::decode as server_decode;
+use protocol::send_to_client::encode::Msg;
+use protocol::send_to_server::decode::{Decode, ErrorKind, Message};
+use protocol::send_to_server::encode::Pub;
+
+fn headers() -> Headers {
+    let mut headers = Headers::new();
+    headers.insert("content-type", "text/plain");
+    headers.append("trace-id", "a");
+    headers.append("trace-id", "b");
+    headers
+}
+
+#[test]
+fn headers_multi_value() {
+    let mut headers = headers();
+    assert_eq!(headers.len(), 3);
+    assert_eq!(headers.get("trace-id"), Some("a"));
+    assert_eq!(headers.get_all("trace-id").collect::<Vec<_>>(), ["a", "b"]);
+
+    // insert 替换同名的所有值
+    headers.insert("trace-id", "c");
+    assert_eq!(headers.get_all("trace-id").collect::<Vec<_>>(), ["c"]);
+
+    assert!(headers.remove("trace-id"));
+    assert!(!headers.contains_key("trace-id"));
+    assert_eq!(headers.len(), 1);
+}
+
+#[test]
+fn headers_encode_decode() {
+    let headers = headers();
+    let mut buff = BytesMut::new();
+    headers.encode_into(&mut buff);
+    assert_eq!(buff.len(), headers.encoded_len());
+    assert_eq!(&buff[..3], b"\x00\x03\x0c");
+    assert_eq!(Headers::decode(&buff), Ok(headers));
+
+    // 多出来的字节, 截断和空名称都是错误
+    buff.extend_from_slice(b"x");
+    assert_eq!(Headers::decode(&buff), Err(ErrorKind::InvalidHeaders));
+    assert_eq!(
+        Headers::decode(b"\x00\x01\x01a"),
+        Err(ErrorKind::InvalidHeaders)
+    );
+    assert_eq!(
+        Headers::decode(b"\x00\x01\x00\x00\x00"),
+        Err(ErrorKind::InvalidHeaders)
+    );
+}
+
+#[test]
+fn pub_with_headers_decode() {
+    let headers = headers();
+    let publish = Pub::new("test", "qweasd").with_headers(&headers);
+    let buff = publish.encode();
+    assert_eq!(buff[0], 16);
+
+    // 分段输入
+    let mut decode = server_decode::Decode::new(0);
+    let mut messages = Vec::new();
+    for chunk in buff.chunks(5) {
+        decode.set_buff(chunk);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+
+    assert_eq!(messages.len(), 1);
+    match &messages[0] {
+        server_decode::Message::Pub(r#pub) => {
+            assert_eq!(&r#pub.name[..], b"test");
+            assert_eq!(&r#pub.msg[..], b"qweasd");
+            assert_eq!(r#pub.headers, headers);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn pub_with_empty_headers_is_plain_pub() {
+    let headers = Headers::new();
+    let buff = Pub::new("test", "qweasd").with_headers(&headers).encode();
+    assert_eq!(&buff[..], &Pub::new("test", "qweasd").encode()[..]);
+
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(buff);
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Pub(r#pub) => assert!(r#pub.headers.is_empty()),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn msg_from_pub_keeps_headers() {
+    let headers = headers();
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(Pub::new("test", "qweasd").with_headers(&headers).encode());
+    let r#pub = match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Pub(r#pub) => r#pub,
+        message => panic!("unexpected message {:?}", message),
+    };
+
+    let buff = Msg::from_pub(3, &r#pub).encode();
+    assert_eq!(buff[0], 17);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(buff);
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Msg(msg) => {
+            assert_eq!(msg.offset, 3);
+            assert_eq!(&msg.sub_name[..], b"test");
+            assert_eq!(&msg.payload[..], b"qweasd");
+            assert_eq!(msg.headers, headers);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn msg_with_invalid_headers() {
+    // |17|offset|4 test|u32 3|\x00\x01\x00|
+    let mut buff = BytesMut::new();
+    buff.extend_from_slice(b"\x11\x00\x00\x00\x00\x00\x00\x00\x01\x04test");
+    buff.extend_from_slice(b"\x00\x00\x00\x03\x00\x01\x00");
+    buff.extend_from_slice(b"\x00\x00\x00\x00");
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(buff);
+    let e = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(e.kind(), &ErrorKind::InvalidHeaders);
+    assert_eq!(e.offset(), 0);
+}
+
+#[test]
+fn headers_parse_frame() {
+    let headers = headers();
+    let buff = Msg::new(1, b"test", b"x").with_headers(&headers).encode();
+    let (frame, used) = parse_frame(&buff).unwrap();
+    assert_eq!(used, buff.len());
+
+    match frame {
+        FrameRef::HMsg {
+            offset,
+            sid,
+            sub_name,
+            headers: list,
+            payload,
+        } => {
+            assert_eq!(offset, 1);
+            assert_eq!(sid, 0);
+            assert_eq!(sub_name, b"test");
+            assert_eq!(payload, b"x");
+            assert_eq!(list.len(), 3);
+            assert_eq!(list.get("content-type"), Some("text/plain"));
+            assert!(list.iter().eq(headers.iter()));
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
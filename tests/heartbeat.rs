@@ -0,0 +1,102 @@
+mod common;
+
+use common::FakeClock;
+use protocol::clock::Clock;
+use protocol::heartbeat::{Action, Heartbeat};
+use protocol::send_to_client::encode::Pong;
+use protocol::send_to_server::decode::{Decode, Message};
+use std::time::Duration;
+
+#[test]
+fn heartbeat_ping_every_30_secs() {
+    let clock = FakeClock::new();
+    let mut heartbeat = Heartbeat::new(clock.clone());
+
+    assert_eq!(
+        heartbeat.poll(),
+        Action::Wait(clock.now() + Duration::from_secs(30))
+    );
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+
+    for _ in 0..10 {
+        clock.advance(Duration::from_secs(1));
+        heartbeat.pong();
+        assert_eq!(heartbeat.timeout(), 0);
+
+        clock.advance(Duration::from_secs(28));
+        assert!(matches!(heartbeat.poll(), Action::Wait(_)));
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(heartbeat.poll(), Action::SendPing);
+    }
+}
+
+#[test]
+fn heartbeat_backoff() {
+    let clock = FakeClock::new();
+    let mut heartbeat = Heartbeat::new(clock.clone());
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+
+    // 第一次超时, 下一次心跳在 30 + 1 * 10 秒后
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+    assert_eq!(heartbeat.timeout(), 1);
+    assert_eq!(
+        heartbeat.next_deadline(),
+        clock.now() + Duration::from_secs(40)
+    );
+
+    clock.advance(Duration::from_secs(39));
+    assert!(matches!(heartbeat.poll(), Action::Wait(_)));
+
+    // 第二次超时, 下一次心跳在 30 + 2 * 10 秒后
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+    assert_eq!(heartbeat.timeout(), 2);
+    assert_eq!(
+        heartbeat.next_deadline(),
+        clock.now() + Duration::from_secs(50)
+    );
+
+    // 第三次超时, 连接断开
+    clock.advance(Duration::from_secs(50));
+    assert_eq!(heartbeat.poll(), Action::Dead);
+    assert!(heartbeat.is_dead());
+
+    heartbeat.pong();
+    assert_eq!(heartbeat.poll(), Action::Dead);
+}
+
+#[test]
+fn heartbeat_pong_reset_timeout() {
+    let clock = FakeClock::new();
+    let mut heartbeat = Heartbeat::new(clock.clone());
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+    clock.advance(Duration::from_secs(40));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+    assert_eq!(heartbeat.timeout(), 2);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(Pong::encode());
+    let message = decode.iter().next().unwrap().unwrap();
+    assert!(heartbeat.receive(&message));
+    assert!(!heartbeat.receive(&Message::Ping));
+    assert_eq!(heartbeat.timeout(), 0);
+
+    // 收到pong之后按原计划发送下一次ping, 不再记超时
+    clock.advance(Duration::from_secs(50));
+    assert_eq!(heartbeat.poll(), Action::SendPing);
+    assert_eq!(heartbeat.timeout(), 0);
+    assert_eq!(
+        heartbeat.next_deadline(),
+        clock.now() + Duration::from_secs(30)
+    );
+}
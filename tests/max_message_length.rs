@@ -0,0 +1,98 @@
+use bytes::{BufMut, BytesMut};
+use protocol::error::ErrorCode;
+
+#[test]
+fn pub_payload_too_large() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind};
+
+    let mut decode = Decode::new(0);
+    decode.set_max_message_length(16);
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(8);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+    buff.put_u32(u32::MAX);
+    decode.set_buff(&buff);
+
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::PayloadTooLarge {
+            limit: 16,
+            got: u32::MAX as usize
+        }
+    );
+}
+
+#[test]
+fn pub_payload_at_limit() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::Pub;
+
+    let mut decode = Decode::new(0);
+    decode.set_max_message_length(6);
+    decode.set_buff(Pub::new("test", "qweasd").encode());
+
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Pub(r#pub) => assert_eq!(&r#pub.msg, &b"qweasd"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn client_err_too_large() {
+    use protocol::send_to_client::decode::{Decode, ErrorKind};
+
+    let mut decode = Decode::new(0);
+    decode.set_max_message_length(4);
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(10);
+    buff.put_u16(5);
+    decode.set_buff(&buff);
+
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::PayloadTooLarge { limit: 4, got: 5 }
+    );
+}
+
+#[test]
+fn msg_payload_too_large() {
+    use protocol::send_to_server::decode::{Decode, ErrorKind};
+
+    let mut decode = Decode::new(0);
+    decode.set_max_message_length(16);
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(4);
+    buff.put_u64(9);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+    buff.put_u32(17);
+    decode.set_buff(&buff);
+
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::PayloadTooLarge { limit: 16, got: 17 }
+    );
+}
+
+#[test]
+fn server_err_too_large() {
+    use protocol::send_to_client::encode::Err;
+    use protocol::send_to_server::decode::{Decode, ErrorKind};
+
+    let mut decode = Decode::new(0);
+    decode.set_max_message_length(4);
+    decode.set_buff(
+        Err::new(ErrorCode::ProtocolViolation)
+            .with_msg("decode error")
+            .encode(),
+    );
+
+    assert_eq!(
+        decode.iter().next().unwrap().unwrap_err().kind(),
+        &ErrorKind::PayloadTooLarge { limit: 4, got: 12 }
+    );
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use common::{to_client, to_server, FakeClock};
+use protocol::capabilities::Capabilities;
+use protocol::clock::Clock;
+use protocol::error::ErrorCode;
+use protocol::mode::{DeliveryMode, ModeError, State};
+use protocol::send_to_client::connection::{Event as ServerEvent, ServerConnection};
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::connection::{ClientConnection, Error, Event as ClientEvent};
+use protocol::send_to_server::decode::{Decode, Message};
+use protocol::send_to_server::encode::{ClientConfig, TurnPush};
+use protocol::state::{Mode, Phase, Support};
+use std::time::{Duration, Instant};
+
+fn connect(
+    push: bool,
+    max_task_size: u8,
+    clock: FakeClock,
+) -> (ServerConnection, ClientConnection<FakeClock>) {
+    let mut server_config = ServerConfig::default();
+    server_config.support_pull();
+    let mut client_config = ClientConfig::default();
+    client_config.support_pull();
+    client_config.max_task_size(max_task_size);
+    if push {
+        server_config.support_push();
+        client_config.support_push();
+    }
+
+    common::connect_with_clock(server_config, client_config, clock)
+}
+
+#[test]
+fn delivery_mode_transitions() {
+    let now = Instant::now();
+    let mut delivery = DeliveryMode::new(Capabilities::from(Support::Pull));
+    assert_eq!(delivery.state(), State::Stable(Mode::Pull));
+
+    assert_eq!(
+        delivery.request(Mode::Push, now),
+        Err(ModeError::NotAllowed(Mode::Push))
+    );
+    assert_eq!(delivery.confirm(), Err(ModeError::NoSwitchPending));
+    assert_eq!(
+        delivery.accept(Mode::Push),
+        Err(ModeError::NotAllowed(Mode::Push))
+    );
+
+    let mut delivery = DeliveryMode::new([Support::Push, Support::Pull].iter().copied().collect());
+    assert_eq!(delivery.state(), State::Stable(Mode::Push));
+
+    delivery.request(Mode::Pull, now).unwrap();
+    assert_eq!(delivery.mode(), Mode::Push);
+    assert_eq!(
+        delivery.request(Mode::Pull, now),
+        Err(ModeError::SwitchPending)
+    );
+    assert_eq!(delivery.confirm(), Ok(Mode::Pull));
+    assert_eq!(delivery.mode(), Mode::Pull);
+
+    delivery.request(Mode::Pull, now).unwrap();
+    assert_eq!(delivery.reject(), Ok(Mode::Pull));
+    assert!(!delivery.is_switching());
+}
+
+#[test]
+fn delivery_mode_timeout() {
+    let now = Instant::now();
+    let mut delivery = DeliveryMode::new([Support::Push, Support::Pull].iter().copied().collect());
+    delivery.set_timeout(Duration::from_secs(3));
+    delivery.request(Mode::Pull, now).unwrap();
+
+    let deadline = now + Duration::from_secs(3);
+    assert_eq!(delivery.next_deadline(), Some(deadline));
+    assert_eq!(delivery.poll_timeout(now), None);
+    assert_eq!(delivery.poll_timeout(deadline), Some(Mode::Pull));
+    assert_eq!(delivery.state(), State::Stable(Mode::Push));
+    assert_eq!(delivery.next_deadline(), None);
+}
+
+#[test]
+fn turn_not_negotiated() {
+    let (mut server, mut client) = connect(false, 10, FakeClock::new());
+
+    assert!(matches!(
+        client.turn_push(),
+        Err(Error::ModeNotAllowed(Mode::Push))
+    ));
+    assert!(client.poll_transmit().is_none());
+
+    // 服务器也拒绝没有协商的模式, 连接不关闭
+    server.receive(TurnPush::encode());
+    assert!(server.poll_event().is_none());
+    assert!(server.is_connected());
+    let mut decode = Decode::new(0);
+    decode.set_buff(server.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(&erro.msg[..], b"mode not supported"),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn pull_only_handshake() {
+    // 只协商了拉模式时握手之后直接是拉模式, 不需要再切换
+    let (mut server, mut client) = connect(false, 10, FakeClock::new());
+    assert_eq!(client.mode(), Mode::Pull);
+    assert_eq!(server.mode(), Mode::Pull);
+
+    client.subscribe("test").unwrap();
+    client.fetch("test", 1, 0, Duration::from_secs(1)).unwrap();
+    match &to_server(&mut server, &mut client)[..] {
+        [ServerEvent::Sub(_), ServerEvent::Fetch(fetch)] => assert_eq!(fetch.batch, 1),
+        events => panic!("unexpected events {:?}", events),
+    }
+    assert!(server.poll_transmit().is_none());
+}
+
+#[test]
+fn turn_rejected_keeps_mode() {
+    let (_, mut client) = connect(true, 10, FakeClock::new());
+    client.turn_pull().unwrap();
+    client.poll_transmit().unwrap();
+
+    // 服务器拒绝之后可以再次切换
+    let err = protocol::send_to_client::encode::Err::new(ErrorCode::ModeNotSupported)
+        .with_msg("mode not supported")
+        .encode();
+    client.receive(err);
+    assert!(matches!(client.poll_event(), Some(Ok(ClientEvent::Err(_)))));
+    assert_eq!(client.mode(), Mode::Push);
+    assert!(!client.delivery().is_switching());
+    client.turn_pull().unwrap();
+}
+
+#[test]
+fn turn_timeout_closes() {
+    let clock = FakeClock::new();
+    let (_, mut client) = connect(true, 10, clock.clone());
+    client.set_switch_timeout(Duration::from_secs(5));
+
+    client.turn_pull().unwrap();
+    assert_eq!(
+        client.next_deadline(),
+        Some(clock.now() + Duration::from_secs(5))
+    );
+    clock.advance(Duration::from_secs(4));
+    assert!(client.poll_timeout().is_ok());
+
+    clock.advance(Duration::from_secs(1));
+    assert!(matches!(
+        client.poll_timeout(),
+        Err(Error::SwitchTimeout(Mode::Pull))
+    ));
+    assert_eq!(client.phase(), Phase::Closed);
+    assert_eq!(client.mode(), Mode::Push);
+}
+
+#[test]
+fn turn_pull_returns_backlog() {
+    let (mut server, mut client) = connect(true, 1, FakeClock::new());
+    client.subscribe("test").unwrap();
+    to_server(&mut server, &mut client);
+    for offset in 1..=3 {
+        server.send_msg(offset, b"test", b"x").unwrap();
+    }
+    assert_eq!(server.queued(), 2);
+
+    client.turn_pull().unwrap();
+    match &to_server(&mut server, &mut client)[..] {
+        [ServerEvent::ModeChanged(Mode::Pull)] => {}
+        events => panic!("unexpected events {:?}", events),
+    }
+    assert_eq!(server.queued(), 0);
+    assert_eq!(
+        server.take_returned(),
+        [("test".into(), 2), ("test".into(), 3)]
+    );
+    assert!(server.take_returned().is_empty());
+
+    // 已经推送的消息仍然可以应答
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Msg(msg), ClientEvent::ModeChanged(Mode::Pull)] => {
+            assert_eq!(msg.offset, 1)
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+    assert_eq!(server.in_flight(), 1);
+    client.ack(1, "test").unwrap();
+    to_server(&mut server, &mut client);
+    assert_eq!(server.in_flight(), 0);
+    assert_eq!(server.credit(), 1);
+}
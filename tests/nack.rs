@@ -0,0 +1,281 @@
+mod common;
+
+use bytes::Bytes;
+use common::FakeClock;
+use protocol::clock::Clock;
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::send_to_client::connection::{Event as ServerEvent, ServerConnection};
+use protocol::send_to_client::decode as server_decode;
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_client::redelivery::{Action, Delivery, Redelivery};
+use protocol::send_to_server::connection::{ClientConnection, Event as ClientEvent};
+use protocol::send_to_server::encode::{ClientConfig, Nack};
+use std::time::Duration;
+
+fn redeliver(offset: u64, deliveries: u32) -> Option<Action> {
+    Some(Action::Redeliver(Delivery {
+        offset,
+        sub_name: "test".into(),
+        payload: "x".into(),
+        deliveries,
+    }))
+}
+
+#[test]
+fn nack_encode_decode() {
+    let buff = Nack::new(7, "test")
+        .with_delay(Duration::from_millis(300))
+        .with_reason("busy")
+        .encode();
+    assert_eq!(
+        &buff[..],
+        &b"\x16\x00\x00\x00\x00\x00\x00\x00\x07\x00\x00\x01\x2c\x04test\x00\x04busy"[..]
+    );
+    assert_eq!(
+        parse_frame(&buff).unwrap(),
+        (
+            FrameRef::Nack {
+                offset: 7,
+                delay: Duration::from_millis(300),
+                sub_name: b"test",
+                reason: b"busy",
+            },
+            buff.len()
+        )
+    );
+
+    let mut decode = server_decode::Decode::new(0);
+    let mut messages = Vec::new();
+    for chunk in buff.chunks(3) {
+        decode.set_buff(chunk);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+    match &messages[..] {
+        [server_decode::Message::Nack(nack)] => {
+            assert_eq!(nack.offset, 7);
+            assert_eq!(&nack.sub_name[..], b"test");
+            assert_eq!(nack.delay, Duration::from_millis(300));
+            assert_eq!(&nack.reason[..], b"busy");
+        }
+        messages => panic!("unexpected messages {:?}", messages),
+    }
+}
+
+#[test]
+fn nack_without_reason() {
+    let buff = Nack::new(1, "test").encode();
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(buff);
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Nack(nack) => {
+            assert_eq!(nack.delay, Duration::from_secs(0));
+            assert!(nack.reason.is_empty());
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn nack_large_delay() {
+    // 超过上限的等待时间按上限发送, 不会变成很短的时间
+    let buff = Nack::new(1, "test")
+        .with_delay(Duration::from_secs(u64::MAX))
+        .encode();
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(buff);
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Nack(nack) => {
+            assert_eq!(nack.delay, Duration::from_millis(u32::MAX as u64));
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn nack_long_reason() {
+    // 超长的原因被截掉, 长度不会溢出
+    let reason = "x".repeat(u16::MAX as usize + 1);
+    let buff = Nack::new(1, "test").with_reason(&reason).encode();
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(buff);
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Nack(nack) => {
+            assert_eq!(&nack.reason[..], &reason.as_bytes()[..u16::MAX as usize]);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    assert!(!decode.is_partial());
+}
+
+fn connect() -> (ServerConnection, ClientConnection) {
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    let mut client_config = ClientConfig::default();
+    client_config.support_push();
+    client_config.max_task_size(1);
+    let (mut server, mut client) = common::connect(server_config, client_config);
+
+    client.subscribe("test").unwrap();
+    server.receive(client.poll_transmit().unwrap());
+    assert!(matches!(server.poll_event(), Some(Ok(ServerEvent::Sub(_)))));
+
+    (server, client)
+}
+
+#[test]
+fn nack_over_connection() {
+    let (mut server, mut client) = connect();
+
+    let clock = FakeClock::new();
+    let mut redelivery = Redelivery::with_clock(clock.clone());
+    server.send_msg(1, b"test", b"x").unwrap();
+    redelivery.track(1, b"test", Bytes::from_static(b"x"));
+    server.send_msg(2, b"test", b"y").unwrap();
+    assert_eq!(server.queued(), 1);
+    client.receive(server.poll_transmit().unwrap());
+    client.poll_event();
+
+    // 拒绝也归还额度
+    client
+        .nack(1, "test", Some(Duration::from_secs(2)), "busy")
+        .unwrap();
+    server.receive(client.poll_transmit().unwrap());
+    match server.poll_event() {
+        Some(Ok(ServerEvent::Nack(nack))) => {
+            assert_eq!(nack.offset, 1);
+            assert_eq!(&nack.reason[..], b"busy");
+            assert!(redelivery.nack(nack.offset, nack.delay));
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(server.queued(), 0);
+    assert_eq!(server.in_flight(), 1);
+    assert_eq!(
+        redelivery.next_deadline(),
+        Some(clock.now() + Duration::from_secs(2))
+    );
+}
+
+#[test]
+fn redeliver_in_flight_keeps_credit() {
+    let (mut server, mut client) = connect();
+
+    server.send_msg(1, b"test", b"x").unwrap();
+    assert_eq!(server.credit(), 0);
+
+    // 应答超时后重新投递同一条消息, 不需要新的额度
+    server.send_msg(1, b"test", b"x").unwrap();
+    assert_eq!(server.queued(), 0);
+    assert_eq!(server.in_flight(), 1);
+    client.receive(server.poll_transmit().unwrap());
+    let offsets: Vec<u64> = std::iter::from_fn(|| client.poll_event())
+        .map(|event| match event.unwrap() {
+            ClientEvent::Msg(msg) => msg.offset,
+            event => panic!("unexpected event {:?}", event),
+        })
+        .collect();
+    assert_eq!(offsets, [1, 1]);
+
+    client.ack(1, "test").unwrap();
+    server.receive(client.poll_transmit().unwrap());
+    server.poll_event();
+    assert_eq!(server.credit(), 1);
+}
+
+#[test]
+fn ack_wait_backoff() {
+    let clock = FakeClock::new();
+    let mut redelivery = Redelivery::with_clock(clock.clone())
+        .with_ack_wait(Duration::from_secs(10))
+        .with_backoff(Duration::from_secs(1), Duration::from_secs(3))
+        .with_max_deliver(0);
+    redelivery.track(1, b"test", Bytes::from_static(b"x"));
+
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(redelivery.poll(), None);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(redelivery.poll(), redeliver(1, 1));
+
+    // 第二次失败之后等待 2 秒
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(redelivery.poll(), None);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(redelivery.poll(), None);
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(redelivery.poll(), redeliver(1, 2));
+
+    // 第三次失败之后不超过上限
+    clock.advance(Duration::from_secs(10 + 3));
+    assert_eq!(redelivery.poll(), redeliver(1, 3));
+
+    assert!(redelivery.ack(1));
+    assert!(redelivery.is_empty());
+    assert_eq!(redelivery.next_deadline(), None);
+}
+
+#[test]
+fn nack_delay() {
+    let clock = FakeClock::new();
+    let mut redelivery = Redelivery::with_clock(clock.clone())
+        .with_backoff(Duration::from_secs(4), Duration::from_secs(60));
+    redelivery.track(1, b"test", Bytes::from_static(b"x"));
+    redelivery.track(2, b"test", Bytes::from_static(b"x"));
+
+    // 没有等待时间时按照退避时间
+    assert!(redelivery.nack(1, Duration::from_secs(0)));
+    assert!(redelivery.nack(2, Duration::from_secs(1)));
+    assert!(!redelivery.nack(2, Duration::from_secs(1)));
+    assert!(!redelivery.nack(3, Duration::from_secs(1)));
+    assert_eq!(
+        redelivery.next_deadline(),
+        Some(clock.now() + Duration::from_secs(1))
+    );
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(redelivery.poll(), redeliver(2, 1));
+    assert_eq!(redelivery.poll(), None);
+    clock.advance(Duration::from_secs(3));
+    assert_eq!(redelivery.poll(), redeliver(1, 1));
+}
+
+#[test]
+fn max_deliver_dead_letter() {
+    let clock = FakeClock::new();
+    let mut redelivery = Redelivery::with_clock(clock.clone())
+        .with_ack_wait(Duration::from_secs(5))
+        .with_backoff(Duration::from_secs(0), Duration::from_secs(0))
+        .with_max_deliver(2)
+        .with_dead_letter(b"dlq");
+    assert_eq!(redelivery.get_max_deliver(), 2);
+    redelivery.track(1, b"test", Bytes::from_static(b"x"));
+
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(redelivery.poll(), redeliver(1, 1));
+
+    // 用完次数之后的拒绝直接转到死信
+    assert!(redelivery.nack(1, Duration::from_secs(30)));
+    assert_eq!(
+        redelivery.poll(),
+        Some(Action::DeadLetter {
+            subject: "dlq".into(),
+            delivery: Delivery {
+                offset: 1,
+                sub_name: "test".into(),
+                payload: "x".into(),
+                deliveries: 2,
+            },
+        })
+    );
+    assert!(redelivery.is_empty());
+}
+
+#[test]
+fn remove_sub() {
+    let mut redelivery = Redelivery::with_clock(FakeClock::new());
+    redelivery.track(1, b"test", Bytes::from_static(b"x"));
+    redelivery.track(2, b"other", Bytes::from_static(b"x"));
+    redelivery.remove_sub(b"test");
+    assert!(!redelivery.is_tracked(1));
+    assert!(redelivery.is_tracked(2));
+    assert_eq!(redelivery.len(), 1);
+}
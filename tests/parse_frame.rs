@@ -0,0 +1,215 @@
+use bytes::{BufMut, BytesMut};
+use protocol::error::{ErrorCode, ErrorKind};
+use protocol::frame::{parse_frame, parse_frame_with, FrameRef, ParseError};
+use protocol::version::Version;
+
+#[test]
+fn parse_client_frames() {
+    use protocol::send_to_server::encode::{
+        Ack, ClientConfig, ClientFrame, Offset, Pub, Sub, UnSub,
+    };
+
+    let mut config = ClientConfig::default();
+    config.max_task_size(10);
+    let mut unsub = UnSub::new();
+    unsub.push(b"hello");
+    unsub.push(b"world");
+
+    let frames = [
+        ClientFrame::Info(&config),
+        ClientFrame::TurnPush,
+        ClientFrame::Sub(Sub::new("test")),
+        ClientFrame::Pub(Pub::new("test", b"qweasd")),
+        ClientFrame::UnSub(unsub),
+        ClientFrame::Offset(Offset::new(1, "test")),
+        ClientFrame::Ack(Ack::new(2, "test")),
+    ];
+    let mut buff = BytesMut::new();
+    frames.iter().for_each(|frame| frame.encode_into(&mut buff));
+
+    let mut parsed = Vec::new();
+    let mut rest = &buff[..];
+    while !rest.is_empty() {
+        let (frame, len) = parse_frame(rest).unwrap();
+        parsed.push(frame);
+        rest = &rest[len..];
+    }
+
+    assert_eq!(
+        parsed[0],
+        FrameRef::ClientInfo {
+            version: 1,
+            support: 0,
+            max_message_size: 10,
+            user: b"",
+            proof: b"",
+        }
+    );
+    assert_eq!(parsed[1], FrameRef::TurnPush);
+    assert_eq!(
+        parsed[2],
+        FrameRef::Sub {
+            reply: false,
+            name: b"test",
+            queue: None
+        }
+    );
+    assert_eq!(
+        parsed[3],
+        FrameRef::Pub {
+            name: b"test",
+            msg: b"qweasd"
+        }
+    );
+    match parsed[4] {
+        FrameRef::UnSub { reply, names } => {
+            assert!(!reply);
+            assert_eq!(names.len(), 2);
+            assert_eq!(
+                names.iter().collect::<Vec<_>>(),
+                vec![&b"hello"[..], &b"world"[..]]
+            );
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+    assert_eq!(
+        parsed[5],
+        FrameRef::Offset {
+            offset: 1,
+            sub_name: b"test"
+        }
+    );
+    assert_eq!(
+        parsed[6],
+        FrameRef::Ack {
+            offset: 2,
+            sub_name: b"test"
+        }
+    );
+}
+
+#[test]
+fn parse_server_frames() {
+    use protocol::send_to_client::encode::{Err, Msg, ServerConfig, ServerFrame};
+
+    let mut config = ServerConfig::default();
+    config.max_message_length(1024);
+    let mut buff = BytesMut::new();
+    ServerFrame::Info(&config).encode_into(&mut buff);
+    ServerFrame::Err(Err::new(ErrorCode::ProtocolViolation).with_msg("decode error"))
+        .encode_into(&mut buff);
+    ServerFrame::Msg(Msg::new(9, b"test", b"qweasd")).encode_into(&mut buff);
+
+    let (frame, len) = parse_frame(&buff).unwrap();
+    assert_eq!(
+        frame,
+        FrameRef::ServerInfo {
+            version: 1,
+            support: 0,
+            max_message_length: 1024,
+            nonce: b"",
+        }
+    );
+    assert_eq!(len, 8);
+
+    let (frame, len) = parse_frame(&buff[8..]).unwrap();
+    assert_eq!(
+        frame,
+        FrameRef::Err {
+            // 第一版没有错误码
+            code: ErrorCode::Unknown(0),
+            msg: b"decode error"
+        }
+    );
+
+    let (frame, _) = parse_frame(&buff[8 + len..]).unwrap();
+    match frame {
+        FrameRef::Msg {
+            offset,
+            sid,
+            sub_name,
+            payload,
+        } => {
+            assert_eq!(offset, 9);
+            assert_eq!(sid, 0);
+            assert_eq!(sub_name, b"test");
+            // 借用输入, 没有拷贝
+            assert_eq!(payload.as_ptr(), buff[buff.len() - 6..].as_ptr());
+        }
+        frame => panic!("unexpected frame {:?}", frame),
+    }
+}
+
+#[test]
+fn parse_incomplete() {
+    use protocol::send_to_client::encode::Msg;
+
+    let buff = Msg::new(9, b"test", b"qweasd").encode();
+    for len in 0..buff.len() {
+        assert_eq!(parse_frame(&buff[..len]), Err(ParseError::Incomplete));
+    }
+    assert_eq!(parse_frame(&buff).unwrap().1, buff.len());
+}
+
+#[test]
+fn parse_invalid() {
+    assert_eq!(
+        parse_frame(&[u8::MAX]),
+        Err(ParseError::Invalid(ErrorKind::UnknownFrameType(u8::MAX)))
+    );
+    assert_eq!(
+        parse_frame(&[7, 0, 0]),
+        Err(ParseError::Invalid(ErrorKind::InvalidSubject))
+    );
+}
+
+#[test]
+fn parse_envelope() {
+    use protocol::send_to_server::encode::{ClientFrame, Sub};
+
+    let mut buff = BytesMut::new();
+    buff.put_u8(200);
+    buff.put_u8(0);
+    buff.put_u32(3);
+    buff.extend_from_slice(&[1, 2, 3]);
+    ClientFrame::Sub(Sub::new("test")).encode_into_with(Version::V2, &mut buff);
+
+    let (frame, len) = parse_frame_with(Version::V2, &buff).unwrap();
+    assert_eq!(
+        frame,
+        FrameRef::Unknown {
+            kind: 200,
+            body: &[1, 2, 3]
+        }
+    );
+    assert_eq!(len, 9);
+
+    let (frame, len) = parse_frame_with(Version::V2, &buff[len..]).unwrap();
+    assert_eq!(
+        frame,
+        FrameRef::Sub {
+            reply: false,
+            name: b"test",
+            queue: None
+        }
+    );
+    assert_eq!(len, 12);
+
+    assert_eq!(
+        parse_frame_with(
+            Version::V2,
+            &[7, 0, 0, 0, 0, 2, 0, 4, b't', b'e', b's', b't']
+        ),
+        Err(ParseError::Invalid(ErrorKind::LengthMismatch {
+            declared: 2
+        }))
+    );
+    assert_eq!(
+        parse_frame_with(Version::V2, &[2, 1, 0, 0, 0, 0]),
+        Err(ParseError::Invalid(ErrorKind::UnsupportedFlags(1)))
+    );
+    assert_eq!(
+        parse_frame_with(Version::V2, &[2, 0, 0, 0]),
+        Err(ParseError::Incomplete)
+    );
+}
@@ -0,0 +1,134 @@
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::send_to_client::decode::{Decode, ErrorKind, Message};
+use protocol::send_to_client::router::{Policy, Router};
+use protocol::send_to_server::encode::Sub;
+
+#[test]
+fn sub_with_queue_encode() {
+    let buff = Sub::new("test").with_queue("workers").encode();
+    assert_eq!(&buff[..], &b"\x07\x02\x04test\x07workers"[..]);
+
+    let buff = Sub::new("test").with_queue("workers").with_reply().encode();
+    assert_eq!(buff[1], 3);
+
+    // 不带队列组时和原来的格式一样
+    assert_eq!(&Sub::new("test").encode()[..], &b"\x07\x00\x04test"[..]);
+}
+
+#[test]
+fn sub_with_queue_decode_in_chunks() {
+    let mut buff = Sub::new("test").with_queue("workers").encode();
+    buff.extend_from_slice(&Sub::new("other").encode());
+
+    let mut decode = Decode::new(0);
+    let mut messages = Vec::new();
+    for chunk in buff.chunks(3) {
+        decode.set_buff(chunk);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+
+    assert_eq!(messages.len(), 2);
+    match &messages[0] {
+        Message::Sub(sub) => {
+            assert!(!sub.reply);
+            assert_eq!(&sub.name[..], b"test");
+            assert_eq!(sub.queue.as_deref(), Some(&b"workers"[..]));
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    match &messages[1] {
+        Message::Sub(sub) => assert!(sub.queue.is_none()),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn sub_with_empty_queue() {
+    let mut decode = Decode::new(0);
+    decode.set_buff(&b"\x07\x02\x04test\x00"[..]);
+    let e = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(e.kind(), &ErrorKind::InvalidSubject);
+}
+
+#[test]
+fn sub_with_queue_parse_frame() {
+    let buff = Sub::new("test").with_queue("workers").encode();
+    let (frame, used) = parse_frame(&buff).unwrap();
+    assert_eq!(used, buff.len());
+    assert_eq!(
+        frame,
+        FrameRef::Sub {
+            reply: false,
+            name: b"test",
+            queue: Some(b"workers"),
+        }
+    );
+}
+
+fn router(policy: Policy) -> Router<u32> {
+    let mut router = Router::new(policy).with_seed(7);
+    // 1 为普通订阅者, 2 3 4 在同一个队列组
+    assert!(router.subscribe(1, b"test", None));
+    for member in 2..5 {
+        assert!(router.subscribe(member, b"test", Some(b"workers")));
+    }
+    router
+}
+
+#[test]
+fn router_round_robin() {
+    let mut router = router(Policy::RoundRobin);
+    assert!(!router.subscribe(2, b"test", Some(b"workers")));
+
+    assert_eq!(router.route(b"test"), [1, 2]);
+    assert_eq!(router.route(b"test"), [1, 3]);
+    assert_eq!(router.route(b"test"), [1, 4]);
+    assert_eq!(router.route(b"test"), [1, 2]);
+    assert!(router.route(b"other").is_empty());
+}
+
+#[test]
+fn router_random() {
+    let mut router = router(Policy::Random);
+    let mut counts = [0; 5];
+    for _ in 0..300 {
+        let targets = router.route(b"test");
+        assert_eq!(targets.len(), 2);
+        assert_eq!(targets[0], 1);
+        counts[targets[1] as usize] += 1;
+    }
+    assert!(counts[2..].iter().all(|&count| count > 0));
+    assert_eq!(counts[2..].iter().sum::<i32>(), 300);
+}
+
+#[test]
+fn router_least_pending() {
+    let mut router = router(Policy::LeastPending);
+    assert_eq!(router.route(b"test"), [1, 2]);
+    assert_eq!(router.route(b"test"), [1, 3]);
+
+    // 2 确认之后未确认数量最少
+    router.ack(2);
+    assert_eq!(router.pending(2), 0);
+    assert_eq!(router.route(b"test"), [1, 4]);
+    assert_eq!(router.route(b"test"), [1, 2]);
+    assert_eq!(router.pending(1), 4);
+}
+
+#[test]
+fn router_unsubscribe_and_remove() {
+    let mut router = router(Policy::RoundRobin);
+    router.subscribe(5, b"other", Some(b"workers"));
+
+    assert!(router.unsubscribe(2, b"test"));
+    assert!(!router.unsubscribe(2, b"test"));
+    assert_eq!(router.route(b"test"), [1, 3]);
+
+    router.remove(3);
+    router.remove(4);
+    assert_eq!(router.route(b"test"), [1]);
+
+    router.remove(1);
+    assert!(!router.is_subscribed(b"test"));
+    assert_eq!(router.route(b"other"), [5]);
+}
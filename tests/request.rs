@@ -0,0 +1,196 @@
+mod common;
+
+use bytes::BytesMut;
+use common::FakeClock;
+use protocol::clock::Clock;
+use protocol::frame::{parse_frame, FrameRef};
+use protocol::send_to_client::decode as server_decode;
+use protocol::send_to_client::encode as server_encode;
+use protocol::send_to_server::decode::{Decode, Message};
+use protocol::send_to_server::encode::{Reply, Request};
+use protocol::send_to_server::request::{Requests, Response};
+use std::time::Duration;
+
+#[test]
+fn request_encode() {
+    let buff = Request::new(7, "add", "inbox.7", b"1+1").encode();
+    assert_eq!(
+        &buff[..],
+        &b"\x0e\x00\x00\x00\x00\x00\x00\x00\x07\x03add\x07inbox.7\x00\x00\x00\x031+1"[..]
+    );
+}
+
+#[test]
+fn request_decode_by_server() {
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(Request::new(7, "add", "inbox.7", b"1+1").encode());
+
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Request(request) => {
+            assert_eq!(request.id, 7);
+            assert_eq!(&request.sub_name[..], b"add");
+            assert_eq!(&request.reply_to[..], b"inbox.7");
+            assert_eq!(&request.payload[..], b"1+1");
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn reply_decode_by_server() {
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(Reply::new(7, "inbox.7", b"2").encode());
+
+    match decode.iter().next().unwrap().unwrap() {
+        server_decode::Message::Reply(reply) => {
+            assert_eq!(reply.id, 7);
+            assert_eq!(&reply.sub_name[..], b"inbox.7");
+            assert_eq!(&reply.payload[..], b"2");
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn request_and_reply_decode_by_client_in_chunks() {
+    let mut buff = BytesMut::new();
+    server_encode::ServerFrame::Request(server_encode::Request::new(1, b"add", b"inbox.1", b"1+1"))
+        .encode_into(&mut buff);
+    server_encode::ServerFrame::Reply(server_encode::Reply::new(2, b"inbox.2", b"2"))
+        .encode_into(&mut buff);
+
+    let mut decode = Decode::new(0);
+    let mut messages = Vec::new();
+    for chunk in buff.chunks(3) {
+        decode.set_buff(chunk);
+        messages.extend(decode.iter().map(Result::unwrap));
+    }
+
+    assert_eq!(messages.len(), 2);
+    match &messages[0] {
+        Message::Request(request) => {
+            assert_eq!(request.id, 1);
+            assert_eq!(&request.reply_to[..], b"inbox.1");
+            assert_eq!(&request.payload[..], b"1+1");
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+    match &messages[1] {
+        Message::Reply(reply) => {
+            assert_eq!(reply.id, 2);
+            assert_eq!(&reply.payload[..], b"2");
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn request_invalid_reply_to() {
+    let mut decode = server_decode::Decode::new(0);
+    decode.set_buff(Request::new(1, "add", "", b"").encode());
+    let e = decode.iter().next().unwrap().unwrap_err();
+    assert_eq!(e.kind(), &server_decode::ErrorKind::InvalidSubject);
+}
+
+#[test]
+fn request_parse_frame() {
+    let buff = Request::new(3, "add", "inbox.3", b"x").encode();
+    let (frame, used) = parse_frame(&buff).unwrap();
+    assert_eq!(used, buff.len());
+    assert_eq!(
+        frame,
+        FrameRef::Request {
+            id: 3,
+            sub_name: b"add",
+            reply_to: b"inbox.3",
+            payload: b"x",
+        }
+    );
+}
+
+fn decode_one(buff: BytesMut) -> Message {
+    let mut decode = Decode::new(0);
+    decode.set_buff(buff);
+    decode.iter().next().unwrap().unwrap()
+}
+
+#[test]
+fn requests_match_reply_and_msg() {
+    let mut requests = Requests::with_clock("_inbox", FakeClock::new());
+
+    let first = requests.start();
+    let second = requests.start();
+    assert_eq!(first.inbox, "_inbox.0");
+    assert_eq!(second.inbox, "_inbox.1");
+    assert_eq!(requests.len(), 2);
+
+    let reply = decode_one(server_encode::Reply::new(1, b"_inbox.1", b"b").encode());
+    assert_eq!(
+        requests.receive(&reply),
+        Some(Response {
+            id: 1,
+            payload: "b".into(),
+        })
+    );
+
+    // 同一个请求只应答一次
+    assert_eq!(requests.receive(&reply), None);
+
+    let msg = decode_one(server_encode::Msg::new(9, b"_inbox.0", b"a").encode());
+    assert_eq!(
+        requests.receive(&msg),
+        Some(Response {
+            id: 0,
+            payload: "a".into(),
+        })
+    );
+    assert!(requests.is_empty());
+}
+
+#[test]
+fn requests_ignore_other_subjects() {
+    let mut requests = Requests::with_clock("_inbox", FakeClock::new());
+    let ticket = requests.start();
+
+    let msg = decode_one(server_encode::Msg::new(0, b"orders", b"a").encode());
+    assert_eq!(requests.receive(&msg), None);
+
+    // 编号和收件箱对不上
+    let reply = decode_one(server_encode::Reply::new(5, b"_inbox.0", b"a").encode());
+    assert_eq!(requests.receive(&reply), None);
+
+    let msg = decode_one(server_encode::Msg::new(0, b"_inbox_x.0", b"a").encode());
+    assert_eq!(requests.receive(&msg), None);
+
+    assert!(requests.is_pending(ticket.id));
+}
+
+#[test]
+fn requests_timeout() {
+    let clock = FakeClock::new();
+    let mut requests = Requests::with_clock("_inbox", clock.clone());
+    requests.set_timeout(Duration::from_secs(5));
+
+    let first = requests.start();
+    clock.advance(Duration::from_secs(2));
+    let second = requests.start();
+
+    assert_eq!(
+        requests.next_deadline(),
+        Some(clock.now() + Duration::from_secs(3))
+    );
+    assert_eq!(requests.poll_timeout(), None);
+
+    clock.advance(Duration::from_secs(3));
+    assert_eq!(requests.poll_timeout(), Some(first.id));
+    assert_eq!(requests.poll_timeout(), None);
+
+    // 超时之后的应答被忽略
+    let reply = decode_one(server_encode::Reply::new(0, b"_inbox.0", b"a").encode());
+    assert_eq!(requests.receive(&reply), None);
+
+    assert!(requests.cancel(second.id));
+    assert_eq!(requests.next_deadline(), None);
+    clock.advance(Duration::from_secs(10));
+    assert_eq!(requests.poll_timeout(), None);
+}
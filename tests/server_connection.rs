@@ -0,0 +1,175 @@
+use bytes::{BufMut, BytesMut};
+use protocol::capabilities::Capabilities;
+use protocol::send_to_client::connection::{Error, Event, ServerConnection};
+use protocol::send_to_client::decode::ErrorKind;
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::decode::{Decode, Message};
+use protocol::send_to_server::encode::{ClientConfig, Ping, Pub, Sub, TurnPull, UnSub};
+use protocol::state::{Mode, Phase, Support};
+
+fn connect() -> (ServerConnection, Decode) {
+    let mut server_config = ServerConfig::default();
+    server_config.support_push();
+    server_config.support_pull();
+    server_config.max_message_length(1024);
+
+    let mut connection = ServerConnection::new(server_config);
+
+    // 服务器先发出自己的信息
+    let mut decode = Decode::new(0);
+    decode.set_buff(connection.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Info(info) => {
+            assert_eq!(info.support, 3);
+            assert_eq!(info.max_message_length, 1024);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    let mut client_config = ClientConfig::default();
+    client_config.support_pull();
+    client_config.max_task_size(10);
+    connection.receive(client_config.encode());
+
+    match connection.poll_event().unwrap().unwrap() {
+        Event::Connected(info) => assert_eq!(info.max_message_size, 10),
+        event => panic!("unexpected event {:?}", event),
+    }
+
+    (connection, decode)
+}
+
+#[test]
+fn server_connection_handshake() {
+    let (connection, _) = connect();
+
+    assert!(connection.is_connected());
+    assert_eq!(connection.capabilities(), Capabilities::from(Support::Pull));
+    assert_eq!(connection.max_task_size(), 10);
+}
+
+#[test]
+fn server_connection_sub_before_info() {
+    let mut connection = ServerConnection::new(ServerConfig::default());
+    let mut decode = Decode::new(0);
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Info(_)))));
+
+    connection.receive(Sub::new("test").encode());
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(Error::UnexpectedFrame {
+            frame: "sub",
+            phase: Phase::Handshake,
+        }))
+    ));
+    assert_eq!(connection.phase(), Phase::Closed);
+
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Err(_)))));
+}
+
+#[test]
+fn server_connection_answer_ping() {
+    let (mut connection, mut decode) = connect();
+
+    connection.receive(Ping::encode());
+    assert!(connection.poll_event().is_none());
+
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Pong))));
+}
+
+#[test]
+fn server_connection_subscriptions() {
+    let (mut connection, mut decode) = connect();
+
+    connection.receive(Sub::new("hello").encode());
+    connection.receive(Sub::new("world").encode());
+    assert!(matches!(connection.poll_event(), Some(Ok(Event::Sub(_)))));
+    assert!(matches!(connection.poll_event(), Some(Ok(Event::Sub(_)))));
+    assert!(connection.is_subscribed("hello"));
+    assert!(connection.is_subscribed("world"));
+
+    connection.send_msg(1, b"hello", b"payload").unwrap();
+    decode.set_buff(connection.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Msg(msg) => {
+            assert_eq!(msg.offset, 1);
+            assert_eq!(&msg.payload, &b"payload"[..]);
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    let mut unsub = UnSub::new();
+    unsub.push(b"hello");
+    connection.receive(unsub.encode());
+    assert!(matches!(connection.poll_event(), Some(Ok(Event::UnSub(_)))));
+    assert!(!connection.is_subscribed("hello"));
+    assert!(matches!(
+        connection.send_msg(2, b"hello", b"payload"),
+        Err(Error::NotSubscribed)
+    ));
+}
+
+#[test]
+fn server_connection_pub() {
+    let (mut connection, _) = connect();
+
+    connection.receive(Pub::new("test", "qweasd").encode());
+    match connection.poll_event().unwrap().unwrap() {
+        Event::Pub(r#pub) => {
+            assert_eq!(&r#pub.name, &b"test"[..]);
+            assert_eq!(&r#pub.msg, &b"qweasd"[..]);
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+}
+
+#[test]
+fn server_connection_pub_too_large() {
+    let (mut connection, mut decode) = connect();
+
+    // 只发送帧头, 不需要等到消息体到达就报错
+    let mut buff = BytesMut::new();
+    buff.put_u8(8);
+    buff.put_u8(4);
+    buff.extend_from_slice(b"test");
+    buff.put_u32(1025);
+    connection.receive(&buff);
+
+    match connection.poll_event() {
+        Some(Err(Error::Decode(e))) => {
+            assert_eq!(
+                e.kind(),
+                &ErrorKind::PayloadTooLarge {
+                    limit: 1024,
+                    got: 1025
+                }
+            );
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(connection.phase(), Phase::Closed);
+
+    decode.set_buff(connection.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(&erro.msg, &b"payload too large"[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn server_connection_turn_pull() {
+    let (mut connection, mut decode) = connect();
+
+    connection.receive(TurnPull::encode());
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Ok(Event::ModeChanged(Mode::Pull)))
+    ));
+    assert_eq!(connection.mode(), Mode::Pull);
+
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Ok))));
+}
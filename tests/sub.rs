@@ -25,11 +25,15 @@ fn server_decode_sub_chunk() {
         decode.set_buff(&[7]);
         assert!(decode.iter().next().is_none());
 
+        decode.set_buff(&[1]);
+        assert!(decode.iter().next().is_none());
+
         decode.set_buff(&[4]);
         assert!(decode.iter().next().is_none());
 
         decode.set_buff(b"test");
         if let Message::Sub(sub) = decode.iter().next().unwrap().unwrap() {
+            assert!(sub.reply);
             assert_eq!(&sub.name, &b"test"[..]);
         }
     }
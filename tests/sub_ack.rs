@@ -0,0 +1,239 @@
+mod common;
+
+use common::{to_client, to_server};
+use protocol::error::ErrorCode;
+use protocol::send_to_client::connection::{Event as ServerEvent, ServerConnection};
+use protocol::send_to_client::encode::{Err, ServerConfig, ServerFrame};
+use protocol::send_to_server::connection::{ClientConnection, Error, Event as ClientEvent};
+use protocol::send_to_server::encode::ClientConfig;
+use protocol::version::Version;
+
+// 订阅号和取消订阅的回复只在第二版中发送
+fn connect_with_version(version: Version) -> (ServerConnection, ClientConnection) {
+    let mut server_config = ServerConfig::default();
+    server_config.set_version(version.as_u8());
+    server_config.support_push();
+    server_config.support_pull();
+    let mut client_config = ClientConfig::default();
+    client_config.set_version(version.as_u8());
+    client_config.support_push();
+    client_config.support_pull();
+    common::connect(server_config, client_config)
+}
+
+fn connect() -> (ServerConnection, ClientConnection) {
+    connect_with_version(Version::V2)
+}
+
+#[test]
+fn sub_with_reply() {
+    let (mut server, mut client) = connect();
+
+    client.subscribe_with_reply("test").unwrap();
+    let events = to_server(&mut server, &mut client);
+    match &events[..] {
+        [ServerEvent::Sub(sub)] => {
+            assert!(sub.reply);
+            assert_eq!(&sub.name[..], b"test");
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+    let sid = server.sid("test").unwrap();
+
+    let events = to_client(&mut server, &mut client);
+    match &events[..] {
+        [ClientEvent::Subscribed(sub_ack)] => {
+            assert_eq!(sub_ack.sid, sid);
+            assert_eq!(&sub_ack.sub_name[..], b"test");
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+    assert_eq!(&client.subscription(sid).unwrap()[..], b"test");
+
+    // 之后的消息可以按订阅号分发
+    server.send_msg(1, b"test", b"qweasd").unwrap();
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Msg(msg)] => {
+            assert_eq!(msg.sid, sid);
+            assert_eq!(&client.subscription(msg.sid).unwrap()[..], b"test");
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn sub_without_reply() {
+    let (mut server, mut client) = connect();
+
+    client.subscribe("test").unwrap();
+    match &to_server(&mut server, &mut client)[..] {
+        [ServerEvent::Sub(sub)] => assert!(!sub.reply),
+        events => panic!("unexpected events {:?}", events),
+    }
+
+    // 服务器不回复, 但消息中仍然带上订阅号
+    assert!(server.poll_transmit().is_none());
+    server.send_msg(1, b"test", b"qweasd").unwrap();
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Msg(msg)] => {
+            assert_eq!(Some(msg.sid), server.sid("test"));
+            assert!(client.subscription(msg.sid).is_none());
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn sub_twice_with_reply() {
+    let (mut server, mut client) = connect();
+
+    client.subscribe_with_reply("test").unwrap();
+    client.subscribe_with_reply("test").unwrap();
+    client.subscribe_with_reply("other").unwrap();
+    assert_eq!(to_server(&mut server, &mut client).len(), 3);
+    assert_ne!(server.sid("test"), server.sid("other"));
+
+    // 按照顺序回复, 重复的订阅被拒绝
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Subscribed(first), ClientEvent::Err(erro), ClientEvent::Subscribed(other)] => {
+            assert_eq!(&first.sub_name[..], b"test");
+            assert_eq!(&erro.msg[..], b"already subscribed");
+            assert_eq!(&other.sub_name[..], b"other");
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn unsub_with_reply() {
+    let (mut server, mut client) = connect();
+
+    client.subscribe_with_reply("test").unwrap();
+    to_server(&mut server, &mut client);
+    to_client(&mut server, &mut client);
+    let sid = server.sid("test").unwrap();
+
+    client.unsubscribe_with_reply(&["test"]).unwrap();
+    assert!(client.subscription(sid).is_none());
+    match &to_server(&mut server, &mut client)[..] {
+        [ServerEvent::UnSub(unsub)] => assert!(unsub.reply),
+        events => panic!("unexpected events {:?}", events),
+    }
+    assert!(!server.is_subscribed("test"));
+    assert!(matches!(
+        &to_client(&mut server, &mut client)[..],
+        [ClientEvent::Unsubscribed]
+    ));
+
+    client.unsubscribe_with_reply(&["test"]).unwrap();
+    to_server(&mut server, &mut client);
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Err(erro)] => assert_eq!(&erro.msg[..], b"not subscribed"),
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn sub_reply_and_turn_in_order() {
+    let (mut server, mut client) = connect();
+
+    client.subscribe_with_reply("test").unwrap();
+    client.turn_pull().unwrap();
+    assert!(matches!(client.turn_push(), Err(Error::SwitchPending)));
+    to_server(&mut server, &mut client);
+
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Subscribed(_), ClientEvent::ModeChanged(_)] => {}
+        events => panic!("unexpected events {:?}", events),
+    }
+}
+
+#[test]
+fn unrelated_err_keeps_pending() {
+    let (mut server, mut client) = connect();
+
+    client.subscribe_with_reply("test").unwrap();
+    client.unsubscribe_with_reply(&["other"]).unwrap();
+    to_server(&mut server, &mut client);
+
+    // 中间插入一个与请求无关的错误, 之后的回复仍然对应原来的请求
+    let mut buff = ServerFrame::Err(Err::new(ErrorCode::SlowConsumer)).encode_with(Version::V2);
+    buff.extend_from_slice(&server.poll_transmit().unwrap());
+    client.receive(buff);
+    let events: Vec<ClientEvent> = std::iter::from_fn(|| client.poll_event())
+        .map(Result::unwrap)
+        .collect();
+    match &events[..] {
+        [ClientEvent::Err(slow), ClientEvent::Subscribed(sub_ack), ClientEvent::Err(erro)] => {
+            assert_eq!(slow.code, ErrorCode::SlowConsumer);
+            assert_eq!(&sub_ack.sub_name[..], b"test");
+            assert_eq!(erro.code, ErrorCode::NotSubscribed);
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+
+    // 等待的请求都已经回复
+    client.turn_pull().unwrap();
+    to_server(&mut server, &mut client);
+    assert!(matches!(
+        &to_client(&mut server, &mut client)[..],
+        [ClientEvent::ModeChanged(_)]
+    ));
+}
+
+#[test]
+fn v1_msg_without_sid() {
+    let (mut server, mut client) = connect_with_version(Version::V1);
+
+    client.subscribe_with_reply("test").unwrap();
+    to_server(&mut server, &mut client);
+    let sid = server.sid("test").unwrap();
+    assert!(matches!(
+        &to_client(&mut server, &mut client)[..],
+        [ClientEvent::Subscribed(_)]
+    ));
+
+    // 第一版的消息不带订阅号
+    server.send_msg(1, b"test", b"qweasd").unwrap();
+    let buff = server.poll_transmit().unwrap();
+    assert_eq!(
+        &buff[..],
+        &b"\x04\x00\x00\x00\x00\x00\x00\x00\x01\x04test\x00\x00\x00\x06qweasd"[..]
+    );
+    client.receive(buff);
+    match client.poll_event() {
+        Some(Ok(ClientEvent::Msg(msg))) => {
+            assert_eq!(msg.sid, 0);
+            assert_eq!(&msg.sub_name[..], b"test");
+        }
+        event => panic!("unexpected event {:?}", event),
+    }
+    assert_eq!(&client.subscription(sid).unwrap()[..], b"test");
+
+    // 第一版的取消订阅不能要求回复
+    assert!(matches!(
+        client.unsubscribe_with_reply(&["test"]),
+        Err(Error::UnSubReplyNotSupported)
+    ));
+}
+
+#[test]
+fn v1_err_rejects_oldest_request() {
+    let (mut server, mut client) = connect_with_version(Version::V1);
+
+    client.subscribe_with_reply("test").unwrap();
+    client.subscribe_with_reply("test").unwrap();
+    client.subscribe_with_reply("other").unwrap();
+    to_server(&mut server, &mut client);
+
+    // 第一版的错误没有错误码, 按顺序当作拒绝了最早的请求
+    match &to_client(&mut server, &mut client)[..] {
+        [ClientEvent::Subscribed(first), ClientEvent::Err(erro), ClientEvent::Subscribed(other)] => {
+            assert_eq!(&first.sub_name[..], b"test");
+            assert_eq!(erro.code, ErrorCode::Unknown(0));
+            assert_eq!(&erro.msg[..], b"already subscribed");
+            assert_eq!(&other.sub_name[..], b"other");
+        }
+        events => panic!("unexpected events {:?}", events),
+    }
+}
@@ -0,0 +1,65 @@
+use bytes::Buf;
+use protocol::version::Version;
+use std::io::IoSlice;
+
+#[test]
+fn msg_encode_chain() {
+    use protocol::send_to_client::encode::{Msg, ServerFrame};
+
+    let payload = vec![7u8; 1024 * 1024];
+    let msg = Msg::new(9, b"test", &payload);
+    let chain = msg.encode_chain();
+
+    // 内容没有被拷贝
+    assert_eq!(chain.last_ref().as_ptr(), payload.as_ptr());
+    assert_eq!(chain.first_ref().len(), 1 + 8 + 1 + 4 + 4);
+
+    let mut slices = [IoSlice::new(&[]); 4];
+    assert_eq!(chain.bytes_vectored(&mut slices), 2);
+    assert_eq!(&slices[1][..], &payload[..]);
+
+    let mut chain = msg.encode_chain();
+    assert_eq!(chain.to_bytes(), msg.encode().freeze());
+
+    let mut chain = msg.encode_chain_with(Version::V2);
+    assert_eq!(
+        chain.to_bytes(),
+        ServerFrame::Msg(Msg::new(9, b"test", &payload))
+            .encode_with(Version::V2)
+            .freeze()
+    );
+}
+
+#[test]
+fn pub_encode_chain() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::{ClientFrame, Pub};
+
+    let payload = vec![7u8; 1024 * 1024];
+    let publish = Pub::new("test", &payload);
+    let chain = publish.encode_chain();
+
+    assert_eq!(chain.last_ref().as_ptr(), payload.as_ptr());
+    assert_eq!(chain.first_ref().len(), 1 + 1 + 4 + 4);
+
+    let mut slices = [IoSlice::new(&[]); 4];
+    assert_eq!(chain.bytes_vectored(&mut slices), 2);
+
+    // 帧头和内容分开到达也能解析
+    let mut decode = Decode::new(0);
+    decode.set_buff(chain.first_ref());
+    assert!(decode.iter().next().is_none());
+    decode.set_buff(chain.last_ref());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Pub(r#pub) => assert_eq!(&r#pub.msg[..], &payload[..]),
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    let mut chain = publish.encode_chain_with(Version::V2);
+    assert_eq!(
+        chain.to_bytes(),
+        ClientFrame::Pub(Pub::new("test", &payload))
+            .encode_with(Version::V2)
+            .freeze()
+    );
+}
@@ -0,0 +1,112 @@
+use protocol::send_to_client::connection::{Error as ServerError, ServerConnection};
+use protocol::send_to_client::encode::ServerConfig;
+use protocol::send_to_server::connection::{ClientConnection, Error as ClientError};
+use protocol::send_to_server::encode::ClientConfig;
+use protocol::state::Phase;
+use protocol::version::{Version, VersionError, INCOMPATIBLE_VERSION};
+
+#[test]
+fn version_negotiate() {
+    assert_eq!(Version::negotiate(1, 1, 1), Ok(Version::V1));
+
+    // 对方版本更高时使用本地最高版本
+    assert_eq!(Version::negotiate(1, 1, 9), Ok(Version::V1));
+    assert_eq!(Version::negotiate(1, 9, 1), Ok(Version::V1));
+
+    assert_eq!(
+        Version::negotiate(1, 1, 0),
+        Err(VersionError::Incompatible {
+            min: 1,
+            max: 1,
+            peer: 0
+        })
+    );
+    assert_eq!(
+        Version::negotiate(2, 3, 1),
+        Err(VersionError::Incompatible {
+            min: 2,
+            max: 3,
+            peer: 1
+        })
+    );
+}
+
+#[test]
+fn version_accept() {
+    assert_eq!(Version::accept(1, 1, 1), Ok(Version::V1));
+    assert!(matches!(
+        Version::accept(1, 1, 2),
+        Err(VersionError::Incompatible { .. })
+    ));
+    assert_eq!(Version::accept(1, 9, 9), Err(VersionError::Unknown(9)));
+}
+
+#[test]
+fn client_connection_choose_version() {
+    use protocol::send_to_client::decode::{Decode, Message};
+
+    let mut client_config = ClientConfig::default();
+    client_config.set_version(7);
+
+    let mut connection = ClientConnection::new(client_config);
+    connection.receive(ServerConfig::default().encode());
+    assert!(connection.poll_event().unwrap().is_ok());
+    assert_eq!(connection.version(), Version::V1);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(connection.poll_transmit().unwrap());
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Info(info) => assert_eq!(info.version, 1),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn client_connection_refuse_old_server() {
+    let mut client_config = ClientConfig::default();
+    client_config.set_min_version(2);
+
+    let mut connection = ClientConnection::new(client_config);
+    connection.receive(ServerConfig::default().encode());
+
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(ClientError::Version(VersionError::Incompatible { .. })))
+    ));
+    assert_eq!(connection.phase(), Phase::Closed);
+    assert!(connection.poll_transmit().is_none());
+}
+
+#[test]
+fn server_connection_refuse_version() {
+    use protocol::send_to_server::decode::{Decode, Message};
+
+    let mut connection = ServerConnection::new(ServerConfig::default());
+
+    let mut client_config = ClientConfig::default();
+    client_config.set_version(2);
+    connection.receive(client_config.encode());
+
+    assert!(matches!(
+        connection.poll_event(),
+        Some(Err(ServerError::Version(VersionError::Incompatible { .. })))
+    ));
+    assert_eq!(connection.phase(), Phase::Closed);
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(connection.poll_transmit().unwrap());
+    assert!(matches!(decode.iter().next(), Some(Ok(Message::Info(_)))));
+    match decode.iter().next().unwrap().unwrap() {
+        Message::Err(erro) => assert_eq!(&erro.msg, INCOMPATIBLE_VERSION.as_bytes()),
+        message => panic!("unexpected message {:?}", message),
+    }
+}
+
+#[test]
+fn decode_version() {
+    let mut decode = protocol::send_to_client::decode::Decode::new(0);
+    assert_eq!(decode.version(), Version::V1);
+
+    decode.set_version(Version::LATEST);
+    assert_eq!(decode.version(), Version::LATEST);
+}
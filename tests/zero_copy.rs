@@ -0,0 +1,60 @@
+use bytes::Bytes;
+use std::thread;
+
+#[test]
+fn pub_bytes_shared() {
+    use protocol::send_to_client::decode::{Decode, Message};
+    use protocol::send_to_server::encode::Pub;
+
+    let mut decode = Decode::new(0);
+    decode.set_buff(Pub::new("test", "qweasd").encode());
+
+    let r#pub = match decode.iter().next().unwrap().unwrap() {
+        Message::Pub(r#pub) => r#pub,
+        message => panic!("unexpected message {:?}", message),
+    };
+
+    // 克隆只增加引用计数
+    let payload: Bytes = r#pub.msg.clone();
+    assert_eq!(payload.as_ptr(), r#pub.msg.as_ptr());
+
+    // 可以交给其他线程
+    let handles = (0..4)
+        .map(|_| {
+            let payload = payload.clone();
+            thread::spawn(move || payload.len())
+        })
+        .collect::<Vec<_>>();
+    handles
+        .into_iter()
+        .for_each(|handle| assert_eq!(handle.join().unwrap(), 6));
+}
+
+#[test]
+fn msg_from_pub() {
+    use protocol::send_to_client::decode::{Decode as ServerDecode, Message as ServerMessage};
+    use protocol::send_to_client::encode::Msg;
+    use protocol::send_to_server::decode::{Decode as ClientDecode, Message as ClientMessage};
+    use protocol::send_to_server::encode::Pub;
+
+    let mut server_decode = ServerDecode::new(0);
+    server_decode.set_buff(Pub::new("test", "qweasd").encode());
+    let r#pub = match server_decode.iter().next().unwrap().unwrap() {
+        ServerMessage::Pub(r#pub) => r#pub,
+        message => panic!("unexpected message {:?}", message),
+    };
+
+    let msg = Msg::from_pub(9, &r#pub);
+    assert_eq!(msg.encode(), Msg::new(9, b"test", b"qweasd").encode());
+
+    let mut client_decode = ClientDecode::new(0);
+    client_decode.set_buff(msg.encode());
+    match client_decode.iter().next().unwrap().unwrap() {
+        ClientMessage::Msg(msg) => {
+            assert_eq!(msg.offset, 9);
+            assert_eq!(msg.sub_name, Bytes::from_static(b"test"));
+            assert_eq!(msg.payload, Bytes::from_static(b"qweasd"));
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+}